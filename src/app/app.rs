@@ -1,22 +1,42 @@
-use std::process::exit;
+use std::net::IpAddr;
+use std::time::Duration;
+use std::collections::BTreeMap;
 use std::env::current_dir;
+use std::fs;
+use std::path::Path;
 use teo_result::{Error, Result};
 use teo_runtime::namespace::Namespace;
 use crate::app::ctx::Ctx;
+use crate::app::database::use_memory_databases;
+use crate::app::environment::{apply_environment_overrides, load_env_files, resolve_environment};
 use teo_runtime::utils::find_main_schema_file;
 use crate::cli::parse::{parse as cli_parse};
 use teo_parser::{parse as schema_parse};
+use teo_parser::ast::schema::Schema;
+use teo_parser::diagnostics::diagnostics::Diagnostics;
 use teo_parser::diagnostics::printer::print_diagnostics;
 use teo_runtime::stdlib::load::{load as load_std};
 use teo_runtime::schema::load::load_schema::load_schema;
-use crate::cli::run::run;
-use dotenvy::dotenv;
+use crate::cli::outcome::Outcome;
+use crate::cli::run::{prepare_for_serving, run};
 use teo_runtime::connection::transaction;
 use crate::app::callbacks::callback::AsyncCallbackArgument;
 use crate::prelude::{Entrance, RuntimeVersion};
+use crate::server::compression::CompressionConfig;
+use crate::server::conditional::CachePolicy;
+use crate::server::error::ErrorFormatter;
+use crate::server::session::SessionConfig;
+use crate::server::rate_limit::{RateLimiter, rate_limit_middleware};
+use crate::test::TestClient;
+use teo_runtime::arguments::Arguments;
+use teo_runtime::handler::Handler;
+use teo_runtime::model::Model;
+use crate::server::options::apply_schema_options;
 
 #[derive(Debug)]
-pub struct App { }
+pub struct App {
+    ctx: Ctx,
+}
 
 impl App {
 
@@ -25,67 +45,244 @@ impl App {
     }
 
     pub fn new_with_entrance_and_runtime_version(entrance: Option<Entrance>, runtime_version: Option<RuntimeVersion>, argv: Option<Vec<String>>) -> Result<Self> {
-        // load env first
-        let _ = dotenv();
-        if !Ctx::create() {
-            Err(Error::new("cannot create app while there is an existing instance"))?
-        }
-        if let Some(entrance) = entrance {
-            Ctx::set_entrance(entrance);
-        }
-        if let Some(runtime_version) = runtime_version {
-            Ctx::set_runtime_version(runtime_version);
-        }
-        Ctx::set_argv(argv);
-        let cli = cli_parse(Ctx::get().runtime_version.clone(), Ctx::get().entrance, Ctx::argv());
+        let entrance = entrance.unwrap_or(Entrance::APP);
+        let runtime_version = runtime_version.unwrap_or(RuntimeVersion::Rust(env!("TEO_RUSTC_VERSION")));
+        let mut cli = cli_parse(runtime_version.clone(), entrance, argv.clone());
+        // load env before anything reads it
+        cli.env = resolve_environment(cli.env.as_ref());
+        load_env_files(cli.env.as_deref());
         let current_dir = match current_dir() {
             Ok(current_dir) => current_dir,
             Err(e) => Err(Error::new(format!("{}", e)))?,
         };
         let main_schema_file = find_main_schema_file(cli.schema.as_ref().map(AsRef::as_ref), &current_dir)?;
-        let (schema, diagnostics) = schema_parse(main_schema_file.as_path().to_str().unwrap(), None, None);
+        let (schema, diagnostics) = parse_schema(&main_schema_file)?;
         print_diagnostics(&diagnostics, true);
         if diagnostics.has_errors() {
-            exit(1);
+            Err(Error::new("the schema has errors"))?
         }
-        load_std(Ctx::main_namespace_mut());
-        Ctx::set_schema(schema);
-        Ctx::set_cli(cli);
-        Ok(Self { })
+        let mut ctx = Ctx::new(argv, runtime_version, entrance, cli, new_main_namespace(), main_schema_file, schema);
+        apply_schema_options(&mut ctx)?;
+        Ok(Self { ctx })
     }
 
-    pub fn setup<A, F>(&self, f: F) where F: AsyncCallbackArgument<A> + 'static {
+    pub fn setup<A, F>(&mut self, f: F) -> Result<()> where F: AsyncCallbackArgument<A> + 'static {
         let wrap_call = Box::leak(Box::new(f));
-        Ctx::set_setup(|ctx: transaction::Ctx| async {
+        self.ctx.set_setup(|ctx: transaction::Ctx| async {
             wrap_call.call(ctx).await
-        });
+        })
     }
 
-    pub fn program<A, F>(&self, name: &str, f: F) where F: AsyncCallbackArgument<A> + 'static {
+    pub fn program<A, F>(&mut self, name: &str, f: F) -> Result<()> where F: AsyncCallbackArgument<A> + 'static {
         let wrap_call = Box::leak(Box::new(f));
-        Ctx::insert_program(name, |ctx: transaction::Ctx| async {
+        self.ctx.insert_program(name, |ctx: transaction::Ctx| async {
             wrap_call.call(ctx).await
+        })
+    }
+
+    pub fn error_formatter<F>(&mut self, f: F) -> Result<()> where F: ErrorFormatter + 'static {
+        self.ctx.set_error_formatter(f)
+    }
+
+    /// Forwarding headers are only honored for requests coming from these
+    /// proxy addresses.
+    pub fn trusted_proxies(&mut self, proxies: Vec<IpAddr>) -> Result<()> {
+        self.ctx.set_trusted_proxies(proxies)
+    }
+
+    /// Enable cookie based sessions. This replaces the `session` of the
+    /// schema's server block.
+    pub fn session(&mut self, config: SessionConfig) -> Result<()> {
+        self.ctx.set_session_config(config)
+    }
+
+    /// Run a custom handler inside a database transaction. The transaction is
+    /// committed if the handler succeeds and rolled back if it fails.
+    ///
+    /// The handler is referenced by its dotted path, e.g. `"Artist.publish"`
+    /// or `"admin.stats.refresh"`.
+    pub fn transactional_handler(&mut self, path: &str) -> Result<()> {
+        self.ctx.insert_transactional_handler(path)
+    }
+
+    /// Respond with 504 when a request takes longer than `timeout`. This
+    /// replaces `requestTimeout` of the schema's server block.
+    pub fn request_timeout(&mut self, timeout: Duration) -> Result<()> {
+        self.ctx.request_limits_mut()?.set_timeout(timeout);
+        Ok(())
+    }
+
+    /// Override the request timeout for a handler, or for every handler
+    /// under a namespace or handler group, e.g. `"Artist.findMany"` or
+    /// `"Artist"`. Timeouts are also read from `handlerTimeouts` of the
+    /// schema's server block.
+    pub fn handler_timeout(&mut self, path: &str, timeout: Duration) -> Result<()> {
+        self.ctx.request_limits_mut()?.set_handler_timeout(path, timeout);
+        Ok(())
+    }
+
+    /// Handle at most `max` requests at the same time. At most `queue`
+    /// requests wait for a slot, further requests are rejected with 503. This
+    /// replaces `concurrencyLimit` of the schema's server block.
+    pub fn concurrency_limit(&mut self, max: usize, queue: usize) -> Result<()> {
+        self.ctx.request_limits_mut()?.set_concurrency(max, queue);
+        Ok(())
+    }
+
+    /// Limit the concurrency of a handler, or of every handler under a
+    /// namespace or handler group. This is applied on top of the global
+    /// limit, and replaces the limit of `path` in `handlerConcurrencyLimits`
+    /// of the schema's server block.
+    pub fn handler_concurrency_limit(&mut self, path: &str, max: usize, queue: usize) -> Result<()> {
+        self.ctx.request_limits_mut()?.set_handler_concurrency(path, max, queue);
+        Ok(())
+    }
+
+    /// Define a rate limiting middleware named `name`. Attach it to
+    /// namespaces with `middlewares [name]` in the schema. Requests are
+    /// counted before their bodies are read.
+    pub fn rate_limit(&mut self, name: &str, limiter: RateLimiter) -> Result<()> {
+        self.main_namespace_mut()?.define_middleware(name, |_arguments: Arguments| {
+            Ok(rate_limit_middleware())
         });
+        self.ctx.insert_rate_limiter(name, limiter)
     }
 
-    pub fn main_namespace(&self) -> &'static Namespace {
-        Ctx::main_namespace()
+    /// Compress responses with an encoding accepted by the client. This
+    /// replaces `compression` of the schema's server block.
+    pub fn compression(&mut self, config: CompressionConfig) -> Result<()> {
+        self.ctx.set_compression(config)
     }
 
-    pub fn main_namespace_mut(&self) -> &'static mut Namespace {
-        Ctx::main_namespace_mut()
+    /// Set caching headers for a read handler, or for every handler under a
+    /// namespace or handler group. `ETag` is always sent for read actions and
+    /// `GET` handlers. This replaces a `@cache` decorator of the schema.
+    pub fn cache_policy(&mut self, path: &str, policy: CachePolicy) -> Result<()> {
+        self.ctx.insert_cache_policy(path, policy)
     }
 
-    pub async fn run(&self) -> Result<()> {
+    /// The state of this app. Handlers reach it with `Ctx::current` or by
+    /// taking `app::Ctx` as an argument.
+    pub fn ctx(&self) -> &Ctx {
+        &self.ctx
+    }
+
+    pub fn main_namespace(&self) -> &Namespace {
+        self.ctx.main_namespace()
+    }
+
+    /// The main namespace, for defining handlers, middlewares and model
+    /// behavior. It cannot be modified once the app runs.
+    pub fn main_namespace_mut(&mut self) -> Result<&mut Namespace> {
+        self.ctx.main_namespace_mut()
+    }
+
+    /// Build a client which sends requests to this app in process. Databases
+    /// are connected, migrated and seeded like `serve` does, but no port is
+    /// bound. The app cannot be configured afterwards.
+    pub async fn test_client(&mut self) -> Result<TestClient> {
+        self.prepare_for_run().await?;
+        prepare_for_serving(&mut self.ctx, true, true, true).await?;
+        TestClient::new(self.ctx.clone()).await
+    }
+
+    /// Like `test_client`, but every SQL connector is replaced by an SQLite
+    /// memory database, so each test starts with its own empty database.
+    /// MongoDB connectors are kept, their databases aren't reset.
+    pub async fn test_client_with_memory_database(&mut self) -> Result<TestClient> {
+        self.prepare_for_run().await?;
+        use_memory_databases(self.ctx.main_namespace_mut()?);
+        prepare_for_serving(&mut self.ctx, true, true, true).await?;
+        TestClient::new(self.ctx.clone()).await
+    }
+
+    /// Prepare the app for being mounted into another server with
+    /// `actix_scope` or `TowerService`. Databases are connected, migrated and
+    /// seeded like `serve` does, but no port is bound. The app cannot be
+    /// configured afterwards.
+    pub async fn mount(&mut self) -> Result<Ctx> {
+        self.prepare_for_run().await?;
+        let silent = self.ctx.cli().silent;
+        prepare_for_serving(&mut self.ctx, true, true, silent).await?;
+        Ok(self.ctx.clone())
+    }
+
+    /// Run the command given on the command line. The process is never
+    /// exited, so hosts may run the app again once this returns.
+    pub async fn run(&mut self) -> Result<Outcome> {
         self.prepare_for_run().await?;
         self.run_without_prepare().await
     }
 
-    pub async fn prepare_for_run(&self) -> Result<()> {
-        load_schema(Ctx::main_namespace_mut(), Ctx::schema(), Ctx::cli().command.ignores_loading()).await
+    pub async fn prepare_for_run(&mut self) -> Result<()> {
+        if self.ctx.schema_loaded() {
+            return Ok(());
+        }
+        let schema = self.ctx.shared_schema();
+        let ignores_loading = self.ctx.cli().command.ignores_loading();
+        self.ctx.record_code_defined_handlers()?;
+        load_schema(self.ctx.main_namespace_mut()?, &schema, ignores_loading).await?;
+        if let Some(environment) = self.ctx.cli().env.clone() {
+            let overrides = apply_environment_overrides(self.ctx.main_namespace_mut()?, &schema, &environment)?;
+            self.ctx.set_autoseed_data_sets(overrides.autoseed)?;
+        }
+        self.ctx.set_schema_loaded()?;
+        Ok(())
+    }
+
+    pub async fn run_without_prepare(&mut self) -> Result<Outcome> {
+        run(&mut self.ctx).await
+    }
+}
+
+/// A main namespace with the standard library and the decorators read by
+/// this crate, ready for loading a schema.
+pub(crate) fn new_main_namespace() -> Namespace {
+    let mut namespace = Namespace::main();
+    load_std(&mut namespace);
+    // `@cache` is declared in the server source and read from the schema by
+    // `cache_policies`
+    let std_namespace = namespace.namespace_mut_or_create("std");
+    std_namespace.define_model_decorator("cache", |_arguments: Arguments, _model: &mut Model| Ok(()));
+    std_namespace.define_handler_decorator("cache", |_arguments: Arguments, _handler: &mut Handler| Ok(()));
+    namespace
+}
+
+/// Declarations of the server features configured in the schema. They are
+/// parsed as a builtin source beside the standard library.
+const SERVER_SOURCE: &str = include_str!("server.teo");
+
+const SERVER_SOURCE_FILE_NAME: &str = ".teo-server.teo";
+
+/// Parse the schema whose main file is `main`, with the server source.
+///
+/// The server source is never written to disk. It's handed to the parser as
+/// an unsaved file which is imported after the last line of the main file,
+/// so locations in the main file are unchanged.
+pub(crate) fn parse_schema(main: &Path) -> Result<(Schema, Diagnostics)> {
+    let main_path = schema_file_path(main)?;
+    let content = match fs::read_to_string(main) {
+        Ok(content) => content,
+        Err(err) => Err(Error::new(format!("cannot read {}: {}", main.display(), err)))?,
+    };
+    let server_path = main.with_file_name(SERVER_SOURCE_FILE_NAME);
+    let server_path = schema_file_path(&server_path)?.to_owned();
+    let mut unsaved_files = BTreeMap::new();
+    unsaved_files.insert(main_path.to_owned(), format!("{}\n\nimport \"{}\"\n", content, server_path));
+    unsaved_files.insert(server_path.clone(), SERVER_SOURCE.to_owned());
+    let (mut schema, diagnostics) = schema_parse(main_path, None, Some(unsaved_files));
+    for source in schema.sources.values_mut() {
+        if source.file_path == server_path {
+            source.builtin = true;
+        }
     }
+    Ok((schema, diagnostics))
+}
 
-    pub async fn run_without_prepare(&self) -> Result<()> {
-        run(Ctx::cli()).await
+/// The schema file path as the parser takes it.
+fn schema_file_path(path: &Path) -> Result<&str> {
+    match path.to_str() {
+        Some(path) => Ok(path),
+        None => Err(Error::new(format!("schema file path is not valid UTF-8: {}", path.display()))),
     }
 }
@@ -0,0 +1,70 @@
+use std::future::Future;
+use std::sync::Arc;
+use clap::Parser;
+use teo_result::{Error, Result};
+use teo_runtime::connection::transaction::{Ctx, ExtractFromTransactionCtx};
+
+tokio::task_local! {
+    /// The name and arguments of the program being run by `teo run`.
+    static CURRENT_PROGRAM: Arc<(String, Vec<String>)>;
+}
+
+/// Run `future` with the arguments given to a program on the command line.
+pub(crate) async fn with_program_args<F>(name: &str, args: Vec<String>, future: F) -> F::Output where F: Future {
+    CURRENT_PROGRAM.scope(Arc::new((name.to_owned(), args)), future).await
+}
+
+/// Values a setup callback or a program takes as arguments.
+///
+/// Everything extractable from a transaction ctx is accepted. Unlike
+/// those, extracting may fail, and the error is returned from the callback.
+pub trait ExtractFromCallbackCtx: Sized {
+    fn extract(ctx: &Ctx) -> Result<Self>;
+}
+
+impl<T> ExtractFromCallbackCtx for T where T: ExtractFromTransactionCtx {
+    fn extract(ctx: &Ctx) -> Result<Self> {
+        Ok(ExtractFromTransactionCtx::extract(ctx))
+    }
+}
+
+/// The arguments following the program name in `teo run NAME ARGS...`, as
+/// they are typed. Empty outside of programs.
+#[derive(Debug, Clone)]
+pub struct ProgramArgs(pub Vec<String>);
+
+impl ExtractFromCallbackCtx for ProgramArgs {
+    fn extract(_ctx: &Ctx) -> Result<Self> {
+        Ok(ProgramArgs(CURRENT_PROGRAM.try_with(|program| program.1.clone()).unwrap_or_default()))
+    }
+}
+
+/// Program arguments parsed into a clap-derived struct.
+///
+/// ```ignore
+/// #[derive(clap::Parser)]
+/// struct Backfill {
+///     #[arg(long)]
+///     since: String,
+///     #[arg(long)]
+///     dry: bool,
+/// }
+///
+/// app.program("backfill", |ctx: transaction::Ctx, Args(args): Args<Backfill>| async move {
+///     Ok(())
+/// })?;
+/// ```
+///
+/// Invalid arguments fail the program with clap's usage message.
+#[derive(Debug, Clone)]
+pub struct Args<T>(pub T);
+
+impl<T> ExtractFromCallbackCtx for Args<T> where T: Parser {
+    fn extract(_ctx: &Ctx) -> Result<Self> {
+        let (name, args) = CURRENT_PROGRAM.try_with(|program| (program.0.clone(), program.1.clone())).unwrap_or_default();
+        match T::try_parse_from(std::iter::once(name).chain(args)) {
+            Ok(parsed) => Ok(Args(parsed)),
+            Err(err) => Err(Error::new(err.render().to_string())),
+        }
+    }
+}
@@ -1,7 +1,8 @@
 use std::future::Future;
 use futures_util::future::BoxFuture;
-use teo_runtime::connection::transaction::{Ctx, ExtractFromTransactionCtx};
+use teo_runtime::connection::transaction::Ctx;
 use teo_result::Result;
+use crate::app::callbacks::args::ExtractFromCallbackCtx;
 
 pub trait AsyncCallback: Send + Sync {
     fn call(&self, ctx: Ctx) -> BoxFuture<'static, Result<()>>;
@@ -19,12 +20,29 @@ pub trait AsyncCallbackArgument<I>: Send + Sync {
     fn call(&self, ctx: Ctx) -> BoxFuture<'static, Result<()>>;
 }
 
-impl<A0, F, Fut> AsyncCallbackArgument<(A0,)> for F where
-    A0: ExtractFromTransactionCtx + Send + Sync,
-    F: Fn(A0) -> Fut + Sync + Send + Clone + 'static,
-    Fut: Future<Output = Result<()>> + Send + 'static {
-    fn call(&self, ctx: Ctx) -> BoxFuture<'static, Result<()>> {
-        let value: A0 = ExtractFromTransactionCtx::extract(&ctx);
-        Box::pin(self(value))
+macro_rules! impl_async_callback_argument {
+    ($($a:ident),+) => {
+        impl<$($a,)+ F, Fut> AsyncCallbackArgument<($($a,)+)> for F where
+            $($a: ExtractFromCallbackCtx + Send + Sync,)+
+            F: Fn($($a),+) -> Fut + Sync + Send + Clone + 'static,
+            Fut: Future<Output = Result<()>> + Send + 'static {
+            #[allow(non_snake_case)]
+            fn call(&self, ctx: Ctx) -> BoxFuture<'static, Result<()>> {
+                $(
+                    let $a: $a = match ExtractFromCallbackCtx::extract(&ctx) {
+                        Ok(value) => value,
+                        Err(err) => return Box::pin(async move { Err(err) }),
+                    };
+                )+
+                Box::pin(self($($a),+))
+            }
+        }
     }
-}
\ No newline at end of file
+}
+
+impl_async_callback_argument!(A0);
+impl_async_callback_argument!(A0, A1);
+impl_async_callback_argument!(A0, A1, A2);
+impl_async_callback_argument!(A0, A1, A2, A3);
+impl_async_callback_argument!(A0, A1, A2, A3, A4);
+impl_async_callback_argument!(A0, A1, A2, A3, A4, A5);
@@ -1 +1,2 @@
-pub mod callback;
\ No newline at end of file
+pub mod callback;
+pub mod args;
\ No newline at end of file
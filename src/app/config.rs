@@ -0,0 +1,78 @@
+use teo_parser::ast::config::Config;
+use teo_parser::ast::schema::Schema;
+use teo_parser::traits::identifiable::Identifiable;
+use teo_parser::traits::named_identifiable::NamedIdentifiable;
+use teo_parser::traits::resolved::Resolve;
+use teo_result::{Error, Result};
+use teo_teon::value::Value;
+use crate::lint::rules::{location, Location};
+
+/// A `connector` or `server` block of the schema. Unnamed blocks are loaded
+/// by the runtime, named ones like `server production { ... }` override them
+/// in the environment of the same name.
+#[derive(Debug)]
+pub(crate) struct ConfigBlock<'a> {
+    pub(crate) keyword: &'a str,
+    /// The environment of a named block.
+    pub(crate) environment: Option<&'a str>,
+    pub(crate) namespace: Vec<String>,
+    /// Items with their resolved values.
+    pub(crate) items: Vec<(&'a str, &'a Value)>,
+    pub(crate) location: Location,
+}
+
+impl<'a> ConfigBlock<'a> {
+
+    pub(crate) fn invalid(&self, message: &str) -> Error {
+        let name = self.environment.map(|e| format!(" {}", e)).unwrap_or_default();
+        Error::new(format!("{}:{}: {}{}: {}", self.location.file, self.location.line, self.keyword, name, message))
+    }
+}
+
+/// The `connector` and `server` blocks of the schema sources, in
+/// declaration order.
+pub(crate) fn config_blocks(schema: &Schema) -> Result<Vec<ConfigBlock>> {
+    let mut result = vec![];
+    for config in schema.configs() {
+        let keyword = config.keyword().name();
+        if keyword != "connector" && keyword != "server" {
+            continue;
+        }
+        if schema.source(config.source_id()).map_or(true, |source| source.builtin) {
+            continue;
+        }
+        let mut block = ConfigBlock {
+            keyword,
+            environment: config.identifier().map(|identifier| identifier.name()),
+            namespace: config.string_path().split_last().map(|(_, namespace)| namespace.to_vec()).unwrap_or_default(),
+            items: vec![],
+            location: location(schema, config),
+        };
+        block.items = config_items(config, &block)?;
+        result.push(block);
+    }
+    Ok(result)
+}
+
+fn config_items<'a>(config: &'a Config, block: &ConfigBlock) -> Result<Vec<(&'a str, &'a Value)>> {
+    let mut items = vec![];
+    for (key, value) in config.items() {
+        let Some(key) = key.named_key_without_resolving() else {
+            Err(block.invalid("expect an identifier key"))?
+        };
+        let Some(value) = value.resolved().value.as_ref() else {
+            Err(block.invalid(&format!("{} expects a literal value", key)))?
+        };
+        items.push((key, value));
+    }
+    Ok(items)
+}
+
+/// An integer config value.
+pub(crate) fn integer(value: &Value) -> Option<i64> {
+    match value {
+        Value::Int(i) => Some(*i as i64),
+        Value::Int64(i) => Some(*i),
+        _ => None,
+    }
+}
@@ -1,156 +1,513 @@
 use educe::Educe;
-use std::collections::BTreeMap;
-use std::ops::{Deref, DerefMut};
-use std::sync::{Arc, Mutex};
+use std::collections::{BTreeMap, BTreeSet};
+use std::future::Future;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
 use maplit::btreemap;
-use once_cell::sync::OnceCell;
 use teo_parser::ast::schema::Schema;
-use teo_result:: Result;
+use teo_result::{Error, Result};
 use teo_runtime::connection;
 use teo_runtime::namespace::Namespace;
+use teo_runtime::request;
+use teo_runtime::request::ctx::extract::ExtractFromRequestCtx;
 use crate::app::callbacks::callback::AsyncCallback;
+use crate::app::database::connect_databases;
+use crate::app::database::options::connector_options;
 use crate::cli::command::CLI;
 use crate::cli::entrance::Entrance;
 use crate::cli::runtime_version::RuntimeVersion;
+use crate::server::compression::CompressionConfig;
+use crate::server::conditional::CachePolicy;
+use crate::server::error::{DefaultErrorFormatter, ErrorFormatter};
+use crate::server::limits::RequestLimits;
+use crate::server::make::lookup_by_handler_key;
+use crate::server::options::apply_schema_options;
+use crate::server::rate_limit::RateLimiter;
+use crate::server::request::RequestImpl;
+use crate::server::session::SessionConfig;
+
+tokio::task_local! {
+    static CURRENT: Ctx;
+}
+
+/// The state of an app.
+///
+/// Every `App` owns its own ctx, so several apps can live in one process.
+/// The ctx is cheap to clone. It can be configured until the app is served,
+/// mounted or gives out a test client, and while no clone of it exists;
+/// after that it's read only and configuring it is an error. The schema is
+/// owned by the ctx and dropped with its last clone.
+#[derive(Clone, Debug)]
+pub struct Ctx {
+    inner: Arc<CtxInner>,
+}
 
 #[derive(Educe)]
 #[educe(Debug)]
-pub struct Ctx {
-    loaded: bool,
-    pub(crate) argv: Option<Vec<String>>,
-    pub(crate) runtime_version: RuntimeVersion,
-    pub(crate) entrance: Entrance,
-    pub(crate) main_namespace: Namespace,
-    pub(crate) cli: Option<CLI>,
+struct CtxInner {
+    argv: Option<Vec<String>>,
+    runtime_version: RuntimeVersion,
+    entrance: Entrance,
+    cli: CLI,
+    main_schema_file: PathBuf,
     #[educe(Debug(ignore))]
-    pub(crate) schema: Option<Schema>,
+    schema: Arc<Schema>,
     #[educe(Debug(ignore))]
-    pub(crate) setup: Option<Arc<dyn AsyncCallback>>,
+    setup: Option<Arc<dyn AsyncCallback>>,
     #[educe(Debug(ignore))]
-    pub(crate) programs: BTreeMap<String, Arc<dyn AsyncCallback>>,
+    programs: BTreeMap<String, Arc<dyn AsyncCallback>>,
     #[educe(Debug(ignore))]
-    pub(crate) conn_ctx: Option<connection::Ctx>,
+    conn_ctx: Option<connection::Ctx>,
+    #[educe(Debug(ignore))]
+    error_formatter: Arc<dyn ErrorFormatter>,
+    trusted_proxies: Arc<Vec<IpAddr>>,
+    #[educe(Debug(ignore))]
+    session_config: Option<Arc<SessionConfig>>,
+    transactional_handlers: BTreeSet<String>,
+    #[educe(Debug(ignore))]
+    request_limits: Arc<RequestLimits>,
+    #[educe(Debug(ignore))]
+    rate_limiters: BTreeMap<String, Arc<RateLimiter>>,
+    compression: Option<Arc<CompressionConfig>>,
+    cache_policies: BTreeMap<String, CachePolicy>,
+    code_defined_handlers: BTreeSet<String>,
+    schema_loaded: bool,
+    running: bool,
+    autoseed_data_sets: Option<Vec<String>>,
+    main_namespace: MainNamespace,
+}
+
+/// The main namespace is editable until databases are connected. It's
+/// shared with the connections then, and read only.
+#[derive(Educe)]
+#[educe(Debug)]
+enum MainNamespace {
+    Editable(#[educe(Debug(ignore))] Box<Namespace>),
+    Connected(#[educe(Debug(ignore))] Arc<Namespace>),
+}
+
+/// The runtime refers to the namespace of a connection ctx as `'static`, so
+/// a connected namespace is kept for the rest of the process. Watch mode
+/// keeps one per reload.
+fn pin_namespace(namespace: &Arc<Namespace>) -> &'static Namespace {
+    let pinned: &'static Arc<Namespace> = Box::leak(Box::new(namespace.clone()));
+    pinned
 }
 
 impl Ctx {
 
-    fn new() -> Self {
+    pub(crate) fn new(
+        argv: Option<Vec<String>>,
+        runtime_version: RuntimeVersion,
+        entrance: Entrance,
+        cli: CLI,
+        main_namespace: Namespace,
+        main_schema_file: PathBuf,
+        schema: Schema,
+    ) -> Self {
         Self {
-            loaded: true,
-            argv: None,
-            runtime_version: RuntimeVersion::Rust(env!("TEO_RUSTC_VERSION")),
-            entrance: Entrance::APP,
-            main_namespace: Namespace::main(),
-            cli: None,
-            schema: None,
-            setup: None,
-            programs: btreemap!{},
-            conn_ctx: None,
+            inner: Arc::new(CtxInner {
+                argv,
+                runtime_version,
+                entrance,
+                cli,
+                main_schema_file,
+                schema: Arc::new(schema),
+                setup: None,
+                programs: btreemap!{},
+                conn_ctx: None,
+                error_formatter: Arc::new(DefaultErrorFormatter),
+                trusted_proxies: Arc::new(vec![]),
+                session_config: None,
+                transactional_handlers: BTreeSet::new(),
+                request_limits: Arc::new(RequestLimits::default()),
+                rate_limiters: btreemap!{},
+                compression: None,
+                cache_policies: btreemap!{},
+                code_defined_handlers: BTreeSet::new(),
+                schema_loaded: false,
+                running: false,
+                autoseed_data_sets: None,
+                main_namespace: MainNamespace::Editable(Box::new(main_namespace)),
+            })
         }
     }
 
-    pub(in crate::app) fn create() -> bool {
-        if CURRENT.get().is_none() {
-            CURRENT.set(Arc::new(Mutex::new(Self::new()))).unwrap();
-            true
-        } else {
-            false
+    /// The ctx of the app handling the current request.
+    pub fn current() -> Option<Ctx> {
+        CURRENT.try_with(|ctx| ctx.clone()).ok()
+    }
+
+    /// Run `fut` with this ctx as the current one.
+    pub(crate) async fn scope<F>(self, fut: F) -> F::Output where F: Future {
+        CURRENT.scope(self, fut).await
+    }
+
+    fn inner_mut(&mut self) -> Result<&mut CtxInner> {
+        match Arc::get_mut(&mut self.inner) {
+            Some(inner) if !inner.running => Ok(inner),
+            _ => Err(Error::new("app cannot be configured while it's running")),
         }
     }
 
-    pub(in crate::app) fn drop() -> Result<()> {
-        Ok(Self::get_mut().reset())
+    /// Whether the app is serving or mounted. It cannot be configured then.
+    pub fn is_running(&self) -> bool {
+        self.inner.running
     }
 
-    pub fn get() -> &'static Ctx {
-        match CURRENT.get() {
-            Some(ctx) => {
-                let retval = ctx.lock().unwrap();
-                unsafe {
-                    &*(retval.deref() as * const Ctx)
-                }
-            },
-            None => panic!("app ctx is accessed when it's not created"),
+    pub(crate) fn set_running(&mut self) -> Result<()> {
+        if !self.is_running() {
+            self.inner_mut()?.running = true;
         }
+        Ok(())
+    }
+
+    /// A copy of this ctx serving another namespace and schema. Database
+    /// connections of `namespace` have to be set up already. Server options
+    /// and cache policies are read from `schema` again.
+    pub(crate) fn reloaded(&self, namespace: Namespace, schema: Schema) -> Result<Ctx> {
+        let inner = &self.inner;
+        let namespace = Arc::new(namespace);
+        let mut ctx = Ctx {
+            inner: Arc::new(CtxInner {
+                argv: inner.argv.clone(),
+                runtime_version: inner.runtime_version.clone(),
+                entrance: inner.entrance,
+                cli: inner.cli.clone(),
+                main_schema_file: inner.main_schema_file.clone(),
+                schema: Arc::new(schema),
+                setup: inner.setup.clone(),
+                programs: inner.programs.clone(),
+                conn_ctx: Some(connection::Ctx::from_namespace(pin_namespace(&namespace))),
+                error_formatter: inner.error_formatter.clone(),
+                trusted_proxies: inner.trusted_proxies.clone(),
+                session_config: None,
+                transactional_handlers: inner.transactional_handlers.clone(),
+                request_limits: Arc::new(RequestLimits::default()),
+                rate_limiters: inner.rate_limiters.clone(),
+                compression: None,
+                cache_policies: btreemap!{},
+                code_defined_handlers: inner.code_defined_handlers.clone(),
+                schema_loaded: true,
+                running: false,
+                autoseed_data_sets: inner.autoseed_data_sets.clone(),
+                main_namespace: MainNamespace::Connected(namespace),
+            })
+        };
+        apply_schema_options(&mut ctx)?;
+        ctx.set_running()?;
+        Ok(ctx)
     }
 
-    pub fn get_mut() -> &'static mut Ctx {
-        match CURRENT.get() {
-            Some(ctx) => {
-                let mut retval = ctx.lock().unwrap();
-                unsafe {
-                    &mut *(retval.deref_mut() as * mut Ctx)
-                }
-            },
-            None => panic!("app ctx is accessed mutably when it's not created"),
+    pub fn main_namespace(&self) -> &Namespace {
+        match &self.inner.main_namespace {
+            MainNamespace::Editable(namespace) => namespace.as_ref(),
+            MainNamespace::Connected(namespace) => namespace.as_ref(),
         }
     }
 
-    fn reset(&mut self) {
-        self.loaded = false;
+    pub fn main_namespace_mut(&mut self) -> Result<&mut Namespace> {
+        match &mut self.inner_mut()?.main_namespace {
+            MainNamespace::Editable(namespace) => Ok(namespace.as_mut()),
+            MainNamespace::Connected(_) => Err(Error::new("main namespace cannot be modified after databases are connected")),
+        }
     }
 
-    fn reload(&mut self) {
-        self.main_namespace = Namespace::main();
-        self.loaded = true;
+    /// Connect the databases of the main namespace and its child
+    /// namespaces. The main namespace cannot be modified afterwards.
+    pub(crate) async fn connect_databases(&mut self, silent: bool) -> Result<()> {
+        if self.inner.conn_ctx.is_some() {
+            return Ok(());
+        }
+        let options = connector_options(self.schema(), self.environment())?;
+        connect_databases(self.main_namespace_mut()?, &options, silent).await?;
+        let inner = self.inner_mut()?;
+        let namespace = match std::mem::replace(&mut inner.main_namespace, MainNamespace::Editable(Box::new(Namespace::main()))) {
+            MainNamespace::Editable(namespace) => Arc::new(*namespace),
+            MainNamespace::Connected(namespace) => namespace,
+        };
+        inner.conn_ctx = Some(connection::Ctx::from_namespace(pin_namespace(&namespace)));
+        inner.main_namespace = MainNamespace::Connected(namespace);
+        Ok(())
     }
 
-    pub fn main_namespace() -> &'static Namespace {
-        &Ctx::get().main_namespace
+    /// The main namespace after databases are connected.
+    pub(crate) fn connected_namespace(&self) -> Result<&Namespace> {
+        match &self.inner.main_namespace {
+            MainNamespace::Connected(namespace) => Ok(namespace.as_ref()),
+            MainNamespace::Editable(_) => Err(Error::new("databases are not connected")),
+        }
+    }
+
+    /// The main namespace after databases are connected, for servers which
+    /// hold it while serving.
+    pub(crate) fn shared_namespace(&self) -> Result<Arc<Namespace>> {
+        match &self.inner.main_namespace {
+            MainNamespace::Connected(namespace) => Ok(namespace.clone()),
+            MainNamespace::Editable(_) => Err(Error::new("databases are not connected")),
+        }
+    }
+
+    pub fn conn_ctx(&self) -> Result<&connection::Ctx> {
+        match self.inner.conn_ctx.as_ref() {
+            Some(conn_ctx) => Ok(conn_ctx),
+            None => Err(Error::new("databases are not connected")),
+        }
+    }
+
+    pub fn cli(&self) -> &CLI {
+        &self.inner.cli
     }
 
-    pub fn main_namespace_mut() -> &'static mut Namespace {
-        &mut Ctx::get_mut().main_namespace
+    pub fn argv(&self) -> Option<&Vec<String>> {
+        self.inner.argv.as_ref()
     }
 
-    pub fn set_cli(cli: CLI) {
-        Ctx::get_mut().cli = Some(cli)
+    pub fn runtime_version(&self) -> &RuntimeVersion {
+        &self.inner.runtime_version
     }
 
-    pub fn cli() -> &'static CLI {
-        Ctx::get().cli.as_ref().unwrap()
+    pub fn entrance(&self) -> Entrance {
+        self.inner.entrance
     }
 
-    pub fn argv() -> Option<Vec<String>> {
-        Ctx::get().argv.clone()
+    pub fn main_schema_file(&self) -> &PathBuf {
+        &self.inner.main_schema_file
     }
 
-    pub fn set_argv(argv: Option<Vec<String>>) {
-        Ctx::get_mut().argv = argv;
+    pub fn schema(&self) -> &Schema {
+        self.inner.schema.as_ref()
     }
 
-    pub fn set_schema(schema: Schema) {
-        Ctx::get_mut().schema = Some(schema)
+    /// The schema, for holding it while the ctx is being configured.
+    pub(crate) fn shared_schema(&self) -> Arc<Schema> {
+        self.inner.schema.clone()
     }
 
-    pub fn schema() -> &'static Schema {
-        Ctx::get().schema.as_ref().unwrap()
+    pub fn setup(&self) -> Option<&Arc<dyn AsyncCallback>> {
+        self.inner.setup.as_ref()
     }
 
-    pub fn set_entrance(entrance: Entrance) {
-        Ctx::get_mut().entrance = entrance;
+    pub fn set_setup<F>(&mut self, f: F) -> Result<()> where F: AsyncCallback + 'static {
+        self.inner_mut()?.setup = Some(Arc::new(f));
+        Ok(())
     }
 
-    pub fn set_runtime_version(runtime_version: RuntimeVersion) {
-        Ctx::get_mut().runtime_version = runtime_version;
+    pub fn program(&self, name: &str) -> Option<&Arc<dyn AsyncCallback>> {
+        self.inner.programs.get(name)
     }
 
+    pub fn insert_program<F>(&mut self, name: &str, f: F) -> Result<()> where F: AsyncCallback + 'static {
+        self.inner_mut()?.programs.insert(name.to_owned(), Arc::new(f));
+        Ok(())
+    }
+
+    pub fn error_formatter(&self) -> Arc<dyn ErrorFormatter> {
+        self.inner.error_formatter.clone()
+    }
 
-    pub fn conn_ctx() -> &'static connection::Ctx {
-        Ctx::get().conn_ctx.as_ref().unwrap()
+    pub fn set_error_formatter<F>(&mut self, f: F) -> Result<()> where F: ErrorFormatter + 'static {
+        self.inner_mut()?.error_formatter = Arc::new(f);
+        Ok(())
     }
 
-    pub fn setup() -> Option<&'static Arc<dyn AsyncCallback>> {
-        Ctx::get().setup.as_ref()
+    pub fn trusted_proxies(&self) -> Arc<Vec<IpAddr>> {
+        self.inner.trusted_proxies.clone()
     }
 
-    pub fn set_setup<F>(f: F) where F: AsyncCallback + 'static {
-        Ctx::get_mut().setup = Some(Arc::new(f));
+    pub fn set_trusted_proxies(&mut self, trusted_proxies: Vec<IpAddr>) -> Result<()> {
+        self.inner_mut()?.trusted_proxies = Arc::new(trusted_proxies);
+        Ok(())
     }
 
-    pub fn insert_program<F>(name: &str, f: F) where F: AsyncCallback + 'static {
-        Ctx::get_mut().programs.insert(name.to_owned(), Arc::new(f));
+    pub fn session_config(&self) -> Option<Arc<SessionConfig>> {
+        self.inner.session_config.clone()
+    }
+
+    pub fn set_session_config(&mut self, session_config: SessionConfig) -> Result<()> {
+        self.inner_mut()?.session_config = Some(Arc::new(session_config));
+        Ok(())
+    }
+
+    pub fn is_transactional_handler(&self, handler_key: &str) -> bool {
+        self.inner.transactional_handlers.contains(handler_key)
+    }
+
+    pub fn insert_transactional_handler(&mut self, handler_key: &str) -> Result<()> {
+        self.inner_mut()?.transactional_handlers.insert(handler_key.to_owned());
+        Ok(())
+    }
+
+    /// The rate limiter standing for the middleware at `path`.
+    pub(crate) fn rate_limiter(&self, path: &str) -> Option<Arc<RateLimiter>> {
+        self.inner.rate_limiters.get(path).cloned()
+    }
+
+    pub(crate) fn insert_rate_limiter(&mut self, path: &str, limiter: RateLimiter) -> Result<()> {
+        self.inner_mut()?.rate_limiters.insert(path.to_owned(), Arc::new(limiter));
+        Ok(())
+    }
+
+    pub fn request_limits(&self) -> Arc<RequestLimits> {
+        self.inner.request_limits.clone()
+    }
+
+    pub fn request_limits_mut(&mut self) -> Result<&mut RequestLimits> {
+        match Arc::get_mut(&mut self.inner_mut()?.request_limits) {
+            Some(request_limits) => Ok(request_limits),
+            None => Err(Error::new("request limits cannot be changed while serving")),
+        }
+    }
+
+    pub fn compression(&self) -> Option<Arc<CompressionConfig>> {
+        self.inner.compression.clone()
+    }
+
+    pub fn set_compression(&mut self, compression: CompressionConfig) -> Result<()> {
+        self.inner_mut()?.compression = Some(Arc::new(compression));
+        Ok(())
+    }
+
+    pub fn cache_policy(&self, handler_key: &str) -> Option<&CachePolicy> {
+        lookup_by_handler_key(&self.inner.cache_policies, handler_key).map(|(_, policy)| policy)
+    }
+
+    pub fn insert_cache_policy(&mut self, path: &str, policy: CachePolicy) -> Result<()> {
+        self.inner_mut()?.cache_policies.insert(path.to_owned(), policy);
+        Ok(())
+    }
+
+    /// Keys of the handlers defined in code, recorded before the schema is
+    /// loaded.
+    pub(crate) fn code_defined_handlers(&self) -> &BTreeSet<String> {
+        &self.inner.code_defined_handlers
+    }
+
+    pub(crate) fn record_code_defined_handlers(&mut self) -> Result<()> {
+        let mut handlers = BTreeSet::new();
+        collect_handler_keys(self.main_namespace(), &mut vec![], &mut handlers);
+        self.inner_mut()?.code_defined_handlers = handlers;
+        Ok(())
+    }
+
+    /// Whether the schema is loaded into the main namespace. It's loaded
+    /// once, however many times the app runs.
+    pub(crate) fn schema_loaded(&self) -> bool {
+        self.inner.schema_loaded
+    }
+
+    pub(crate) fn set_schema_loaded(&mut self) -> Result<()> {
+        self.inner_mut()?.schema_loaded = true;
+        Ok(())
+    }
+
+    /// The environment selected with `--env` or `TEO_ENV`.
+    pub fn environment(&self) -> Option<&str> {
+        self.inner.cli.env.as_deref()
+    }
+
+    /// Data sets to autoseed in the current environment, if the schema
+    /// overrides those declared `autoseed`.
+    pub(crate) fn autoseed_data_sets(&self) -> Option<&Vec<String>> {
+        self.inner.autoseed_data_sets.as_ref()
+    }
+
+    pub(crate) fn set_autoseed_data_sets(&mut self, names: Option<Vec<String>>) -> Result<()> {
+        self.inner_mut()?.autoseed_data_sets = names;
+        Ok(())
+    }
+}
+
+fn collect_handler_keys(namespace: &Namespace, path: &mut Vec<String>, result: &mut BTreeSet<String>) {
+    let key = |path: &Vec<String>, group: Option<&String>, name: &String| {
+        path.iter().chain(group).chain(std::iter::once(name)).cloned().collect::<Vec<String>>().join(".")
+    };
+    for name in namespace.handlers.keys() {
+        result.insert(key(path, None, name));
+    }
+    for (group_name, group) in namespace.handler_groups.iter().chain(namespace.model_handler_groups.iter()) {
+        for name in group.handlers.keys() {
+            result.insert(key(path, Some(group_name), name));
+        }
+    }
+    for (name, child) in namespace.namespaces.iter() {
+        path.push(name.clone());
+        collect_handler_keys(child, path, result);
+        path.pop();
     }
 }
 
-static CURRENT: OnceCell<Arc<Mutex<Ctx>>> = OnceCell::new();
\ No newline at end of file
+/// The app ctx is kept by the request, so it's available wherever the
+/// request ctx is, including tasks spawned by handlers.
+impl ExtractFromRequestCtx for Ctx {
+    fn extract(ctx: &request::Ctx) -> Self {
+        match RequestImpl::of(ctx.request()) {
+            Some(request) => request.app_ctx().clone(),
+            // request ctxs are only built by the server and the console
+            None => unreachable!("request ctx is not built by a Teo app"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::sync::Arc;
+    use teo_runtime::connection::transaction;
+    use teo_runtime::handler::r#match::HandlerMatch;
+    use teo_runtime::request;
+    use teo_runtime::request::ctx::extract::ExtractFromRequestCtx;
+    use teo_teon::Value;
+    use crate::server::compression::CompressionConfig;
+    use crate::server::request::RequestImpl;
+    use crate::test::schema::app_for_schema_source;
+    use super::Ctx;
+
+    const SCHEMA: &str = "connector {\n  provider .sqlite\n  url \"sqlite::memory:\"\n}\n\nserver {\n  bind (\"0.0.0.0\", 4000)\n}\n\nmodel Artist {\n  @id @autoIncrement\n  id: Int\n}\n";
+
+    #[actix_web::test]
+    async fn cannot_be_configured_once_a_test_client_is_built() {
+        let mut app = app_for_schema_source(SCHEMA);
+        assert!(app.trusted_proxies(vec![]).is_ok());
+        let client = app.test_client_with_memory_database().await.unwrap();
+        drop(client);
+        assert!(app.ctx().is_running());
+        assert!(app.trusted_proxies(vec![]).is_err());
+        assert!(app.compression(CompressionConfig::default()).is_err());
+    }
+
+    #[actix_web::test]
+    async fn cannot_be_configured_once_mounted() {
+        let mut app = app_for_schema_source(SCHEMA);
+        let ctx = app.mount().await.unwrap();
+        drop(ctx);
+        assert!(app.trusted_proxies(vec![]).is_err());
+        assert!(app.main_namespace_mut().is_err());
+    }
+
+    #[actix_web::test]
+    async fn request_ctxs_keep_the_app_ctx() {
+        let mut app = app_for_schema_source(SCHEMA);
+        let ctx = app.mount().await.unwrap();
+        let request = RequestImpl::detached("/Artist/findMany", ctx.clone()).unwrap();
+        let request_ctx = request::Ctx::new(
+            request::Request::new(request),
+            Arc::new(Value::Null),
+            transaction::Ctx::new(ctx.conn_ctx().unwrap().clone()),
+            HandlerMatch::new(vec!["Artist".to_owned()], "findMany".to_owned(), BTreeMap::new()),
+        );
+        // outside of any request task
+        assert!(Ctx::current().is_none());
+        assert_eq!(Ctx::extract(&request_ctx).main_schema_file(), ctx.main_schema_file());
+    }
+
+    #[actix_web::test]
+    async fn main_namespace_is_read_only_once_connected() {
+        let mut app = app_for_schema_source(SCHEMA);
+        let client = app.test_client_with_memory_database().await.unwrap();
+        drop(client);
+        assert!(app.main_namespace_mut().is_err());
+        assert!(app.ctx().connected_namespace().is_ok());
+    }
+}
@@ -1,6 +1,13 @@
+pub mod options;
+pub(crate) mod sql;
+
+use std::collections::{BTreeMap, HashMap};
 use std::sync::Arc;
+use std::time::Duration;
 use array_tool::vec::Join;
-use teo_result::{Result};
+use async_recursion::async_recursion;
+use tokio::time::sleep;
+use teo_result::{Error, Result};
 use teo_runtime::config::connector::Connector;
 use teo_runtime::connection::connection::Connection;
 use teo_runtime::database::database::Database;
@@ -8,45 +15,161 @@ use teo_runtime::namespace::Namespace;
 use teo_sql_connector::connector::SQLConnection;
 use teo_sql_connector::schema::dialect::SQLDialect;
 use teo_mongodb_connector::connector::MongoDBConnection;
-use crate::app::ctx::Ctx;
-use teo_runtime::connection::Ctx as ConnCtx;
+use crate::app::database::options::ConnectorOptions;
+use crate::app::environment::{interpolate, mask_secrets, mask_url};
 use crate::message::info_message;
 
-pub async fn connect_databases(namespace: &mut Namespace, silent: bool) -> Result<()> {
-    may_connect_database(namespace, silent).await?;
+const INITIAL_RETRY_DELAY: Duration = Duration::from_millis(500);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(10);
+
+/// Connect the databases of `namespace` and all of its descendants.
+///
+/// Namespaces declaring the same provider and url share one connection
+/// pool, except SQLite memory databases, which are separate databases per
+/// connection.
+pub async fn connect_databases(namespace: &mut Namespace, options: &BTreeMap<Vec<String>, ConnectorOptions>, silent: bool) -> Result<()> {
+    connect_namespace_databases(namespace, options, &mut HashMap::new(), silent).await
+}
+
+#[async_recursion]
+async fn connect_namespace_databases(namespace: &mut Namespace, options: &BTreeMap<Vec<String>, ConnectorOptions>, pools: &mut Pools, silent: bool) -> Result<()> {
+    may_connect_database(namespace, options, pools, silent).await?;
     for namespace in namespace.namespaces.values_mut() {
-        may_connect_database(namespace, silent).await?;
+        connect_namespace_databases(namespace, options, pools, silent).await?;
     }
-    let ctx = ConnCtx::from_namespace(Ctx::main_namespace());
-    Ctx::get_mut().conn_ctx = Some(ctx);
     Ok(())
 }
 
-pub async fn may_connect_database(namespace: &mut Namespace, silent: bool) -> Result<()> {
+/// Connections by provider and url, with the namespace which made them.
+type Pools = HashMap<(String, String), (String, Arc<dyn Connection>)>;
+
+/// Replace the SQL connectors of `namespace` and its child namespaces with
+/// SQLite memory databases.
+///
+/// MongoDB connectors are left alone: their models use MongoDB types like
+/// `ObjectId`, which SQLite can't store, so they keep their own databases.
+pub(crate) fn use_memory_databases(namespace: &mut Namespace) {
+    if let Some(connector) = namespace.connector.as_mut().filter(|connector| !matches!(connector.provider, Database::MongoDB)) {
+        connector.provider = Database::SQLite;
+        connector.url = "sqlite::memory:".to_owned();
+        namespace.database = Some(Database::SQLite);
+    }
+    for namespace in namespace.namespaces.values_mut() {
+        use_memory_databases(namespace);
+    }
+}
+
+async fn may_connect_database(namespace: &mut Namespace, options: &BTreeMap<Vec<String>, ConnectorOptions>, pools: &mut Pools, silent: bool) -> Result<()> {
     if namespace.connector.is_none() { return Ok(()) }
     let connector = namespace.connector.as_ref().unwrap();
-    let connection = connection_for_connector(connector).await;
+    let name = if namespace.path.is_empty() { "main".to_string() } else { namespace.path().join(".") };
+    let options = options.get(&namespace.path).cloned().unwrap_or_default();
+    let url = interpolate(&connector.url)?;
+    let key = (connector.provider.lowercase_desc().to_string(), options.apply_to_url(&connector.provider, &url)?);
+    if let Some((owner, connection)) = pools.get(&key) {
+        if !silent {
+            info_message(format!("{} connector for `{}` shares the connection of `{}`", connector.provider.lowercase_desc(), name, owner));
+        }
+        namespace.connection = Some(connection.clone());
+        return Ok(());
+    }
+    let connection = connect_with_retries(&name, &connector.provider, &url, &options, silent).await?;
     if !silent {
-        info_message(format!("{} connector connected for `{}` at \"{}\"", connector.provider.lowercase_desc(), if namespace.path.is_empty() { "main".to_string() } else { namespace.path().join(".") }, connector.url));
+        info_message(format!("{} connector connected for `{}` at \"{}\"", connector.provider.lowercase_desc(), name, mask_url(&url)));
+    }
+    if !url.contains(":memory:") {
+        pools.insert(key, (name, connection.clone()));
     }
     namespace.connection = Some(connection);
     Ok(())
 }
 
-async fn connection_for_connector(connector: &Connector) -> Arc<dyn Connection> {
-    if connector.provider.is_mongo() {
-        Arc::new(MongoDBConnection::new(connector.url.as_str()).await)
-    } else {
-        Arc::new(SQLConnection::new(
-            match connector.provider {
-                Database::MongoDB => unreachable!(),
-                Database::MySQL => SQLDialect::MySQL,
-                Database::PostgreSQL => SQLDialect::PostgreSQL,
-                Database::SQLite => SQLDialect::SQLite,
-            },
-            connector.url.as_str(),
-            false,
-        ).await)
+/// Paths of the models which have no connection to query with, because
+/// neither their namespace nor any namespace above it has a connector. The
+/// builtin `std` models are only used with a connector, and are left out.
+pub(crate) fn unreachable_models(namespace: &Namespace) -> Vec<String> {
+    let mut result = vec![];
+    collect_unreachable_models(namespace, false, &mut result);
+    result
+}
+
+fn collect_unreachable_models(namespace: &Namespace, reachable: bool, result: &mut Vec<String>) {
+    let reachable = reachable || namespace.connection.is_some();
+    if !reachable {
+        for model in namespace.models.values() {
+            result.push(model.path.join("."));
+        }
+    }
+    for namespace in namespace.namespaces.values() {
+        if namespace.path == vec!["std".to_owned()] && !reachable {
+            continue;
+        }
+        collect_unreachable_models(namespace, reachable, result);
     }
+}
+
+/// Connect to the database of `connector`. Environment variables and secret
+/// files referenced in the url are resolved.
+pub(crate) async fn connection_for_connector(connector: &Connector) -> Result<Arc<dyn Connection>> {
+    connect_with_retries("main", &connector.provider, &interpolate(&connector.url)?, &ConnectorOptions::default(), true).await
+}
 
-}
\ No newline at end of file
+/// Connect, retrying with exponential backoff, since the database may start
+/// later than the app, like in a container setup.
+async fn connect_with_retries(name: &str, provider: &Database, url: &str, options: &ConnectorOptions, silent: bool) -> Result<Arc<dyn Connection>> {
+    let url_with_options = options.apply_to_url(provider, url)?;
+    let retries = options.connect_retries();
+    let mut delay = INITIAL_RETRY_DELAY;
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        match connection_for_url(provider, &url_with_options).await {
+            Ok(connection) => return Ok(connection),
+            Err(reason) if attempt <= retries => {
+                if !silent {
+                    info_message(format!("cannot connect {} database for `{}`, retrying in {}ms: {}", provider.lowercase_desc(), name, delay.as_millis(), mask_secrets(&reason, url)));
+                }
+                sleep(delay).await;
+                delay = (delay * 2).min(MAX_RETRY_DELAY);
+            }
+            Err(reason) => Err(Error::new(format!(
+                "cannot connect {} database for `{}` at \"{}\" after {} attempt(s): {}",
+                provider.lowercase_desc(), name, mask_url(url), attempt, mask_secrets(&reason, url),
+            )))?,
+        }
+    }
+}
+
+/// The connectors panic when they cannot connect. The connection is made in
+/// a task, so that the panic is returned as the reason instead.
+async fn connection_for_url(provider: &Database, url: &str) -> std::result::Result<Arc<dyn Connection>, String> {
+    let provider = provider.clone();
+    let url = url.to_owned();
+    let task = tokio::spawn(async move {
+        let connection: Arc<dyn Connection> = if provider.is_mongo() {
+            Arc::new(MongoDBConnection::new(&url).await)
+        } else {
+            Arc::new(SQLConnection::new(
+                match provider {
+                    Database::MongoDB => unreachable!(),
+                    Database::MySQL => SQLDialect::MySQL,
+                    Database::PostgreSQL => SQLDialect::PostgreSQL,
+                    Database::SQLite => SQLDialect::SQLite,
+                },
+                &url,
+                false,
+            ).await)
+        };
+        connection
+    });
+    match task.await {
+        Ok(connection) => Ok(connection),
+        Err(err) if err.is_panic() => {
+            let payload = err.into_panic();
+            Err(payload.downcast_ref::<String>().cloned()
+                .or_else(|| payload.downcast_ref::<&str>().map(|s| s.to_string()))
+                .unwrap_or_else(|| "unknown error".to_owned()))
+        }
+        Err(err) => Err(err.to_string()),
+    }
+}
@@ -0,0 +1,180 @@
+use std::collections::BTreeMap;
+use teo_parser::ast::schema::Schema;
+use teo_result::{Error, Result};
+use teo_runtime::database::database::Database;
+use teo_teon::value::Value;
+use crate::app::config::{config_blocks, integer, ConfigBlock};
+
+/// Keys of a `connector` block which tune the connection pool. They are read
+/// by teo rather than the runtime, and may be overridden per environment.
+pub(crate) const CONNECTOR_OPTION_KEYS: [&str; 5] = ["poolSize", "idleTimeout", "acquireTimeout", "statementTimeout", "connectRetries"];
+
+/// Failed connections are retried this many times at startup unless
+/// `connectRetries` is given.
+const DEFAULT_CONNECT_RETRIES: u32 = 3;
+
+/// Connection pool settings of a connector. Timeouts are in seconds.
+/// `statementTimeout` is only supported by PostgreSQL, which cancels
+/// statements running longer.
+///
+/// ```teo
+/// connector {
+///   provider: .postgres,
+///   url: "${DATABASE_URL}",
+///   poolSize: 20,
+///   idleTimeout: 300,
+///   acquireTimeout: 10,
+///   statementTimeout: 30,
+///   connectRetries: 5,
+/// }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ConnectorOptions {
+    pub(crate) pool_size: Option<u64>,
+    pub(crate) idle_timeout: Option<u64>,
+    pub(crate) acquire_timeout: Option<u64>,
+    pub(crate) statement_timeout: Option<u64>,
+    pub(crate) connect_retries: Option<u64>,
+}
+
+impl ConnectorOptions {
+
+    pub(crate) fn connect_retries(&self) -> u32 {
+        self.connect_retries.map(|retries| retries as u32).unwrap_or(DEFAULT_CONNECT_RETRIES)
+    }
+
+    /// `url` with the pool settings added as query parameters the connectors
+    /// understand. Parameters given in the url already are kept.
+    pub(crate) fn apply_to_url(&self, provider: &Database, url: &str) -> Result<String> {
+        let mut parameters: Vec<(&str, String)> = vec![];
+        match provider {
+            Database::MongoDB => {
+                if let Some(pool_size) = self.pool_size {
+                    parameters.push(("maxPoolSize", pool_size.to_string()));
+                }
+                if let Some(idle_timeout) = self.idle_timeout {
+                    parameters.push(("maxIdleTimeMS", (idle_timeout * 1000).to_string()));
+                }
+                if let Some(acquire_timeout) = self.acquire_timeout {
+                    parameters.push(("serverSelectionTimeoutMS", (acquire_timeout * 1000).to_string()));
+                }
+                if self.statement_timeout.is_some() {
+                    Err(Error::new("statementTimeout is not supported by the mongo connector"))?
+                }
+            }
+            _ => {
+                if let Some(pool_size) = self.pool_size {
+                    parameters.push(("connection_limit", pool_size.to_string()));
+                }
+                if let Some(idle_timeout) = self.idle_timeout {
+                    parameters.push(("max_idle_connection_lifetime", idle_timeout.to_string()));
+                }
+                if let Some(acquire_timeout) = self.acquire_timeout {
+                    parameters.push(("pool_timeout", acquire_timeout.to_string()));
+                }
+                if let Some(statement_timeout) = self.statement_timeout {
+                    if !matches!(provider, Database::PostgreSQL) {
+                        // a socket timeout would leave the statement running
+                        Err(Error::new(format!("statementTimeout is not supported by the {} connector, only by postgres", provider.lowercase_desc())))?
+                    }
+                    // enforced by the server, so the statement is cancelled
+                    parameters.push(("options", format!("-c%20statement_timeout%3D{}", statement_timeout * 1000)));
+                }
+            }
+        }
+        let mut url = url.to_owned();
+        for (key, value) in parameters {
+            if has_parameter(&url, key) {
+                continue;
+            }
+            url.push(if url.contains('?') { '&' } else { '?' });
+            url.push_str(&format!("{}={}", key, value));
+        }
+        Ok(url)
+    }
+
+    fn set(&mut self, block: &ConfigBlock, key: &str, value: &Value) -> Result<()> {
+        let Some(number) = integer(value).and_then(|number| u64::try_from(number).ok()) else {
+            Err(block.invalid(&format!("{} expects a non negative integer", key)))?
+        };
+        match key {
+            "poolSize" => self.pool_size = Some(number),
+            "idleTimeout" => self.idle_timeout = Some(number),
+            "acquireTimeout" => self.acquire_timeout = Some(number),
+            "statementTimeout" => self.statement_timeout = Some(number),
+            "connectRetries" => self.connect_retries = Some(number),
+            _ => Err(block.invalid(&format!("unknown connector option `{}`", key)))?,
+        }
+        Ok(())
+    }
+}
+
+/// The options of every connector in the schema by namespace path, with
+/// those of `connector <environment>` blocks applied.
+pub(crate) fn connector_options(schema: &Schema, environment: Option<&str>) -> Result<BTreeMap<Vec<String>, ConnectorOptions>> {
+    let blocks = config_blocks(schema)?;
+    let mut result: BTreeMap<Vec<String>, ConnectorOptions> = BTreeMap::new();
+    let defaults = blocks.iter().filter(|b| b.environment.is_none());
+    let overrides = blocks.iter().filter(|b| b.environment.is_some() && b.environment == environment);
+    for block in defaults.chain(overrides).filter(|b| b.keyword == "connector") {
+        let options = result.entry(block.namespace.clone()).or_default();
+        for (key, value) in &block.items {
+            if CONNECTOR_OPTION_KEYS.contains(key) {
+                options.set(block, key, value)?;
+            }
+        }
+    }
+    Ok(result)
+}
+
+fn has_parameter(url: &str, key: &str) -> bool {
+    match url.split_once('?') {
+        Some((_, query)) => query.split('&').any(|pair| pair.split('=').next() == Some(key)),
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use teo_runtime::database::database::Database;
+    use super::ConnectorOptions;
+
+    fn options() -> ConnectorOptions {
+        ConnectorOptions { pool_size: Some(20), idle_timeout: Some(300), acquire_timeout: Some(10), statement_timeout: None, connect_retries: None }
+    }
+
+    #[test]
+    fn apply_to_sql_url() {
+        let url = options().apply_to_url(&Database::MySQL, "mysql://localhost/app").unwrap();
+        assert_eq!(url, "mysql://localhost/app?connection_limit=20&max_idle_connection_lifetime=300&pool_timeout=10");
+    }
+
+    #[test]
+    fn statement_timeout_is_only_supported_by_postgres() {
+        let statement_timeout = ConnectorOptions { statement_timeout: Some(30), ..Default::default() };
+        let url = statement_timeout.apply_to_url(&Database::PostgreSQL, "postgres://localhost/app").unwrap();
+        assert_eq!(url, "postgres://localhost/app?options=-c%20statement_timeout%3D30000");
+        assert!(statement_timeout.apply_to_url(&Database::MySQL, "mysql://localhost/app").is_err());
+        assert!(statement_timeout.apply_to_url(&Database::SQLite, "sqlite::memory:").is_err());
+    }
+
+    #[test]
+    fn apply_to_url_keeps_given_parameters() {
+        let url = options().apply_to_url(&Database::PostgreSQL, "postgres://localhost/app?connection_limit=5").unwrap();
+        assert_eq!(url, "postgres://localhost/app?connection_limit=5&max_idle_connection_lifetime=300&pool_timeout=10");
+    }
+
+    #[test]
+    fn apply_to_mongo_url() {
+        let url = options().apply_to_url(&Database::MongoDB, "mongodb://localhost/app").unwrap();
+        assert_eq!(url, "mongodb://localhost/app?maxPoolSize=20&maxIdleTimeMS=300000&serverSelectionTimeoutMS=10000");
+        let statement_timeout = ConnectorOptions { statement_timeout: Some(30), ..Default::default() };
+        assert!(statement_timeout.apply_to_url(&Database::MongoDB, "mongodb://localhost/app").is_err());
+    }
+
+    #[test]
+    fn connect_retries_default() {
+        assert_eq!(ConnectorOptions::default().connect_retries(), 3);
+        assert_eq!(ConnectorOptions { connect_retries: Some(0), ..Default::default() }.connect_retries(), 0);
+    }
+}
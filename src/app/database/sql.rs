@@ -0,0 +1,38 @@
+use teo_runtime::database::database::Database;
+
+/// Quote a table or column name for `database`.
+pub(crate) fn quote_identifier(database: &Database, name: &str) -> String {
+    match database {
+        Database::MySQL => format!("`{}`", name.replace('`', "``")),
+        _ => format!("\"{}\"", name.replace('"', "\"\"")),
+    }
+}
+
+/// Quote a string literal for `database`. MySQL treats backslashes in
+/// literals as escapes by default, so they are doubled there.
+pub(crate) fn quote_string(database: &Database, value: &str) -> String {
+    let value = value.replace('\'', "''");
+    match database {
+        Database::MySQL => format!("'{}'", value.replace('\\', "\\\\")),
+        _ => format!("'{}'", value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use teo_runtime::database::database::Database;
+    use super::{quote_identifier, quote_string};
+
+    #[test]
+    fn quotes_identifiers() {
+        assert_eq!(quote_identifier(&Database::MySQL, "a`b"), "`a``b`");
+        assert_eq!(quote_identifier(&Database::PostgreSQL, "a\"b"), "\"a\"\"b\"");
+        assert_eq!(quote_identifier(&Database::SQLite, "users"), "\"users\"");
+    }
+
+    #[test]
+    fn quotes_strings() {
+        assert_eq!(quote_string(&Database::PostgreSQL, "it's \\n"), "'it''s \\n'");
+        assert_eq!(quote_string(&Database::MySQL, "it's \\n"), "'it''s \\\\n'");
+    }
+}
@@ -2,6 +2,8 @@ pub mod app;
 pub mod ctx;
 pub mod callbacks;
 pub mod database;
+pub(crate) mod config;
+pub(crate) mod environment;
 
 pub use app::App;
 pub use ctx::Ctx;
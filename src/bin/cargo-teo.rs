@@ -1,3 +1,4 @@
+use std::process::exit;
 use tokio::main;
 use teo_result::Result;
 use teo::app::App;
@@ -5,6 +6,10 @@ use teo::cli::entrance::Entrance;
 
 #[main]
 async fn main() -> Result<()> {
-    let app = App::new_with_entrance_and_runtime_version(Some(Entrance::CLI), None, None)?;
-    app.run().await
+    let mut app = App::new_with_entrance_and_runtime_version(Some(Entrance::CLI), None, None)?;
+    let outcome = app.run().await?;
+    if !outcome.is_success() {
+        exit(outcome.exit_code());
+    }
+    Ok(())
 }
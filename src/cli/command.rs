@@ -1,34 +1,34 @@
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub(crate) struct ServeCommand {
     pub(crate) no_migration: bool,
     pub(crate) no_autoseed: bool,
-    pub(crate) env: Option<String>,
+    pub(crate) watch: bool,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub(crate) enum GenerateCommand {
     GenerateClientCommand(GenerateClientCommand),
     GenerateEntityCommand(GenerateEntityCommand),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub(crate) struct GenerateClientCommand {
     pub(crate) all: bool,
     pub(crate) names: Option<Vec<String>>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub(crate) struct GenerateEntityCommand {
     pub(crate) all: bool,
     pub(crate) names: Option<Vec<String>>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub(crate) struct MigrateCommand {
     pub(crate) dry: bool,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub(crate) struct SeedCommand {
     pub(crate) action: SeedCommandAction,
     pub(crate) all: bool,
@@ -42,22 +42,74 @@ pub(crate) enum SeedCommandAction {
     Reseed,
 }
 
-#[derive(Debug)]
-pub(crate) struct PurgeCommand { }
+#[derive(Debug, Clone)]
+pub(crate) struct PurgeCommand {
+    pub(crate) namespaces: Vec<String>,
+    pub(crate) models: Vec<String>,
+    pub(crate) yes: bool,
+    pub(crate) force: bool,
+    pub(crate) reset_sequences: bool,
+    pub(crate) reseed: bool,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct LintCommand {
+    pub(crate) format: String,
+    pub(crate) rules: Vec<String>,
+    pub(crate) deny_warnings: bool,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct FormatCommand {
+    pub(crate) check: bool,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct IntrospectCommand {
+    pub(crate) output: Option<String>,
+    pub(crate) overwrite: bool,
+    pub(crate) sample_size: usize,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct ConsoleCommand {
+    pub(crate) read_only: bool,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct RoutesCommand {
+    pub(crate) format: String,
+    pub(crate) namespace: Option<String>,
+    pub(crate) model: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct DumpCommand {
+    pub(crate) output: String,
+    pub(crate) format: String,
+    pub(crate) batch_size: usize,
+}
 
-#[derive(Debug)]
-pub(crate) struct LintCommand { }
+#[derive(Debug, Clone)]
+pub(crate) struct RestoreCommand {
+    pub(crate) input: String,
+    pub(crate) batch_size: usize,
+    pub(crate) force: bool,
+}
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub(crate) struct RunCommand {
-    pub(crate) name: String
+    pub(crate) name: String,
+    pub(crate) args: Vec<String>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct CLI {
     pub(crate) command: CLICommand,
     pub(crate) schema: Option<String>,
     pub(crate) silent: bool,
+    /// The environment from `--env`, or `TEO_ENV` once resolved.
+    pub(crate) env: Option<String>,
 }
 
 impl CLI {
@@ -66,7 +118,7 @@ impl CLI {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub(crate) enum CLICommand {
     Serve(ServeCommand),
     Generate(GenerateCommand),
@@ -74,6 +126,12 @@ pub(crate) enum CLICommand {
     Seed(SeedCommand),
     Purge(PurgeCommand),
     Lint(LintCommand),
+    Format(FormatCommand),
+    Introspect(IntrospectCommand),
+    Console(ConsoleCommand),
+    Routes(RoutesCommand),
+    Dump(DumpCommand),
+    Restore(RestoreCommand),
     Run(RunCommand),
 }
 
@@ -83,6 +141,8 @@ impl CLICommand {
         match self {
             CLICommand::Generate(_) => true,
             CLICommand::Lint(_) => true,
+            CLICommand::Format(_) => true,
+            CLICommand::Introspect(_) => true,
             _ => false,
         }
     }
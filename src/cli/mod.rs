@@ -2,4 +2,5 @@ pub(crate) mod parse;
 pub mod entrance;
 pub mod runtime_version;
 pub(crate) mod command;
-pub mod run;
\ No newline at end of file
+pub mod run;
+pub mod outcome;
\ No newline at end of file
@@ -0,0 +1,26 @@
+/// How a command ended.
+///
+/// Commands never exit the process. Problems they find, like lint errors or
+/// unformatted schema files, are reported and returned as a failure, and the
+/// host decides what to do with it. `cargo teo` exits with `exit_code`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+    Success,
+    /// The command ran and found problems. They are printed already, the
+    /// message summarizes them.
+    Failure(String),
+}
+
+impl Outcome {
+
+    pub fn is_success(&self) -> bool {
+        *self == Outcome::Success
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Outcome::Success => 0,
+            Outcome::Failure(_) => 1,
+        }
+    }
+}
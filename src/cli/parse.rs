@@ -3,7 +3,7 @@ use std::ffi::OsString;
 use clap::{Arg, ArgAction, Command as ClapCommand};
 use crate::cli::entrance::Entrance;
 use crate::cli::runtime_version::RuntimeVersion;
-use crate::cli::command::{CLI, CLICommand, GenerateClientCommand, GenerateCommand, GenerateEntityCommand, LintCommand, MigrateCommand, PurgeCommand, RunCommand, SeedCommand, SeedCommandAction, ServeCommand};
+use crate::cli::command::{CLI, CLICommand, ConsoleCommand, DumpCommand, GenerateClientCommand, GenerateCommand, GenerateEntityCommand, FormatCommand, IntrospectCommand, LintCommand, MigrateCommand, PurgeCommand, RestoreCommand, RoutesCommand, RunCommand, SeedCommand, SeedCommandAction, ServeCommand};
 
 pub(crate) fn parse(runtime_version: RuntimeVersion, entrance: Entrance, argv: Option<Vec<String>>) -> CLI {
     let argv = argv.unwrap_or(env::args_os().map(|s| s.to_str().unwrap().to_owned()).collect());
@@ -56,6 +56,11 @@ pub(crate) fn parse(runtime_version: RuntimeVersion, entrance: Entrance, argv: O
                 .short('S')
                 .long("no-autoseed")
                 .help("Start server without auto seeding autoseed dataset")
+                .action(ArgAction::SetTrue))
+            .arg(Arg::new("watch")
+                .short('w')
+                .long("watch")
+                .help("Reload the schema when schema files change")
                 .action(ArgAction::SetTrue)))
         .subcommand(ClapCommand::new("generate")
             .about("Generate code")
@@ -118,16 +123,151 @@ pub(crate) fn parse(runtime_version: RuntimeVersion, entrance: Entrance, argv: O
                 .help("Data set names to process")
                 .num_args(1..)))
         .subcommand(ClapCommand::new("purge")
-            .about("Purge and clear the database without dropping tables."))
+            .about("Purge and clear the database without dropping tables.")
+            .arg(Arg::new("namespace")
+                .short('n')
+                .long("namespace")
+                .help("Only purge models in this namespace, `main` for those of the main namespace")
+                .action(ArgAction::Append))
+            .arg(Arg::new("model")
+                .short('m')
+                .long("model")
+                .help("Only purge this model, by name or by dotted path")
+                .action(ArgAction::Append))
+            .arg(Arg::new("yes")
+                .short('y')
+                .long("yes")
+                .help("Purge without asking for confirmation")
+                .action(ArgAction::SetTrue))
+            .arg(Arg::new("force")
+                .long("force")
+                .help("Purge even in the production environment")
+                .action(ArgAction::SetTrue))
+            .arg(Arg::new("reset-sequences")
+                .long("reset-sequences")
+                .help("Restart auto increment counters of purged tables")
+                .action(ArgAction::SetTrue))
+            .arg(Arg::new("reseed")
+                .long("reseed")
+                .help("Seed autoseed data sets after purging")
+                .action(ArgAction::SetTrue)))
         .subcommand(ClapCommand::new("lint")
-            .about("Lint the schema files"))
+            .about("Lint the schema files")
+            .arg(Arg::new("format")
+                .short('f')
+                .long("format")
+                .help("Output format")
+                .value_parser(["text", "json", "sarif"])
+                .default_value("text")
+                .action(ArgAction::Set))
+            .arg(Arg::new("rule")
+                .short('r')
+                .long("rule")
+                .help("Set the severity of a rule, e.g. naming-convention=off")
+                .value_name("NAME=off|warning|error")
+                .action(ArgAction::Append))
+            .arg(Arg::new("deny-warnings")
+                .long("deny-warnings")
+                .help("Exit with failure on warnings")
+                .action(ArgAction::SetTrue)))
+        .subcommand(ClapCommand::new("format")
+            .about("Format the schema files")
+            .arg(Arg::new("check")
+                .short('c')
+                .long("check")
+                .help("Check formatting without writing, exit with failure if any file is unformatted")
+                .action(ArgAction::SetTrue)))
+        .subcommand(ClapCommand::new("introspect")
+            .about("Generate models from an existing database")
+            .arg(Arg::new("output")
+                .short('o')
+                .long("output")
+                .help("Write models to this file, merging into it if it exists")
+                .action(ArgAction::Set))
+            .arg(Arg::new("overwrite")
+                .long("overwrite")
+                .help("Overwrite the output file instead of merging")
+                .requires("output")
+                .action(ArgAction::SetTrue))
+            .arg(Arg::new("sample-size")
+                .long("sample-size")
+                .help("Documents sampled per MongoDB collection")
+                .value_parser(clap::value_parser!(usize))
+                .default_value("100")
+                .action(ArgAction::Set)))
+        .subcommand(ClapCommand::new("console")
+            .about("Run builtin actions against the databases interactively")
+            .arg(Arg::new("read-only")
+                .long("read-only")
+                .help("Reject actions which write to the databases")
+                .action(ArgAction::SetTrue)))
+        .subcommand(ClapCommand::new("routes")
+            .about("List the HTTP endpoints")
+            .arg(Arg::new("format")
+                .short('f')
+                .long("format")
+                .help("Output format")
+                .value_parser(["text", "json"])
+                .default_value("text")
+                .action(ArgAction::Set))
+            .arg(Arg::new("namespace")
+                .short('n')
+                .long("namespace")
+                .help("Only list routes under this namespace, e.g. blog.admin")
+                .action(ArgAction::Set))
+            .arg(Arg::new("model")
+                .short('m')
+                .long("model")
+                .help("Only list routes of this model")
+                .action(ArgAction::Set)))
+        .subcommand(ClapCommand::new("dump")
+            .about("Write the records of every model into a compressed archive")
+            .arg(Arg::new("OUTPUT")
+                .required(true)
+                .help("Archive file to write, e.g. backup.ndjson.gz")
+                .action(ArgAction::Set))
+            .arg(Arg::new("format")
+                .short('f')
+                .long("format")
+                .help("Record format")
+                .value_parser(["ndjson", "teon"])
+                .default_value("ndjson")
+                .action(ArgAction::Set))
+            .arg(Arg::new("batch-size")
+                .long("batch-size")
+                .help("Records fetched per query")
+                .value_parser(clap::value_parser!(usize))
+                .default_value("500")
+                .action(ArgAction::Set)))
+        .subcommand(ClapCommand::new("restore")
+            .about("Insert the records of an archive written by dump")
+            .arg(Arg::new("INPUT")
+                .required(true)
+                .help("Archive file to read")
+                .action(ArgAction::Set))
+            .arg(Arg::new("batch-size")
+                .long("batch-size")
+                .help("Records inserted per transaction")
+                .value_parser(clap::value_parser!(usize))
+                .default_value("500")
+                .action(ArgAction::Set))
+            .arg(Arg::new("force")
+                .long("force")
+                .help("Restore even if the archive was dumped with a different schema")
+                .action(ArgAction::SetTrue)))
         .subcommand(ClapCommand::new("run")
             .about("Run a defined program")
             .arg(Arg::new("NAME")
                 .required(true)
                 .action(ArgAction::Append)
                 .help("Program name to run")
-                .num_args(1)))
+                .num_args(1))
+            .arg(Arg::new("ARGS")
+                .action(ArgAction::Append)
+                .help("Arguments passed to the program")
+                .num_args(0..)
+                .trailing_var_arg(true)
+                .allow_hyphen_values(true)))
         .get_matches_from(match runtime_version {
             RuntimeVersion::Python(_) | RuntimeVersion::NodeJS(_) => {
                 let result = argv.iter().enumerate().filter(|(i, x)| (*i != 1) && !x.as_str().ends_with(".ts")).map(|(_i, x)| x.clone()).collect::<Vec<String>>();
@@ -139,10 +279,13 @@ pub(crate) fn parse(runtime_version: RuntimeVersion, entrance: Entrance, argv: O
         });
     let silent: bool = matches.get_flag("silent");
     let schema: Option<&String> = matches.get_one("SCHEMA_FILE");
+    // global args are given before or after the subcommand
+    let env: Option<String> = matches.subcommand()
+        .and_then(|(_, submatches)| submatches.get_one::<String>("ENV").cloned())
+        .or(matches.get_one::<String>("ENV").cloned());
     let command = match matches.subcommand() {
         Some(("serve", submatches)) => {
-            let env: Option<&String> = submatches.get_one("ENV");
-            CLICommand::Serve(ServeCommand { no_migration: submatches.get_flag("no-migration"), no_autoseed: submatches.get_flag("no-autoseed"), env: env.cloned() })
+            CLICommand::Serve(ServeCommand { no_migration: submatches.get_flag("no-migration"), no_autoseed: submatches.get_flag("no-autoseed"), watch: submatches.get_flag("watch") })
         }
         Some(("generate", submatches)) => {
             match submatches.subcommand() {
@@ -175,17 +318,75 @@ pub(crate) fn parse(runtime_version: RuntimeVersion, entrance: Entrance, argv: O
                 names,
             })
         }
-        Some(("purge", _submatches)) => {
-            CLICommand::Purge(PurgeCommand { })
+        Some(("purge", submatches)) => {
+            CLICommand::Purge(PurgeCommand {
+                namespaces: submatches.get_many::<String>("namespace").map(|s| s.map(|v| v.to_string()).collect::<Vec<String>>()).unwrap_or_default(),
+                models: submatches.get_many::<String>("model").map(|s| s.map(|v| v.to_string()).collect::<Vec<String>>()).unwrap_or_default(),
+                yes: submatches.get_flag("yes"),
+                force: submatches.get_flag("force"),
+                reset_sequences: submatches.get_flag("reset-sequences"),
+                reseed: submatches.get_flag("reseed"),
+            })
+        }
+        Some(("lint", submatches)) => {
+            let format: Option<&String> = submatches.get_one("format");
+            let rules: Vec<String> = submatches.get_many::<String>("rule").map(|s| s.map(|v| v.to_string()).collect::<Vec<String>>()).unwrap_or_default();
+            CLICommand::Lint(LintCommand {
+                format: format.cloned().unwrap_or("text".to_owned()),
+                rules,
+                deny_warnings: submatches.get_flag("deny-warnings"),
+            })
+        }
+        Some(("format", submatches)) => {
+            CLICommand::Format(FormatCommand { check: submatches.get_flag("check") })
+        }
+        Some(("introspect", submatches)) => {
+            let output: Option<&String> = submatches.get_one("output");
+            let sample_size: Option<&usize> = submatches.get_one("sample-size");
+            CLICommand::Introspect(IntrospectCommand {
+                output: output.cloned(),
+                overwrite: submatches.get_flag("overwrite"),
+                sample_size: sample_size.cloned().unwrap_or(100),
+            })
+        }
+        Some(("console", submatches)) => {
+            CLICommand::Console(ConsoleCommand { read_only: submatches.get_flag("read-only") })
+        }
+        Some(("routes", submatches)) => {
+            let format: Option<&String> = submatches.get_one("format");
+            let namespace: Option<&String> = submatches.get_one("namespace");
+            let model: Option<&String> = submatches.get_one("model");
+            CLICommand::Routes(RoutesCommand {
+                format: format.cloned().unwrap_or("text".to_owned()),
+                namespace: namespace.cloned(),
+                model: model.cloned(),
+            })
         }
-        Some(("lint", _submatches)) => {
-            CLICommand::Lint(LintCommand { })
+        Some(("dump", submatches)) => {
+            let output: Option<&String> = submatches.get_one("OUTPUT");
+            let format: Option<&String> = submatches.get_one("format");
+            let batch_size: Option<&usize> = submatches.get_one("batch-size");
+            CLICommand::Dump(DumpCommand {
+                output: output.cloned().unwrap(),
+                format: format.cloned().unwrap_or("ndjson".to_owned()),
+                batch_size: batch_size.cloned().unwrap_or(500),
+            })
+        }
+        Some(("restore", submatches)) => {
+            let input: Option<&String> = submatches.get_one("INPUT");
+            let batch_size: Option<&usize> = submatches.get_one("batch-size");
+            CLICommand::Restore(RestoreCommand {
+                input: input.cloned().unwrap(),
+                batch_size: batch_size.cloned().unwrap_or(500),
+                force: submatches.get_flag("force"),
+            })
         }
         Some(("run", submatches)) => {
             let name: Option<String> = submatches.get_one::<String>("NAME").map(|s| s.clone());
-            CLICommand::Run(RunCommand { name: name.unwrap() })
+            let args: Vec<String> = submatches.get_many::<String>("ARGS").map(|s| s.map(|v| v.to_string()).collect::<Vec<String>>()).unwrap_or_default();
+            CLICommand::Run(RunCommand { name: name.unwrap(), args })
         }
         _ => unreachable!()
     };
-    CLI { command, schema: schema.map(|s| s.to_string()), silent }
+    CLI { command, schema: schema.map(|s| s.to_string()), silent, env }
 }
\ No newline at end of file
@@ -1,112 +1,166 @@
 use teo_result::{Error, Result};
+use crate::app::callbacks::args::with_program_args;
 use crate::app::ctx::Ctx;
-use crate::app::database::connect_databases;
-use crate::cli::command::{CLI, CLICommand, GenerateCommand, SeedCommandAction};
+use crate::app::database::unreachable_models;
+use crate::cli::command::{CLICommand, GenerateCommand, SeedCommandAction};
+use crate::cli::outcome::Outcome;
 use crate::server::make::serve;
+use crate::server::watch::serve_and_watch;
 use teo_runtime::connection::transaction;
 use teo_runtime::schema::load::load_data_sets::load_data_sets;
+use crate::console::console;
+use crate::dump::{dump, restore};
+use crate::format::format;
+use crate::introspect::introspect;
+use crate::lint::lint;
+use crate::message::{info_message, warning_message};
 use crate::migrate::migrate;
-use crate::purge::purge;
+use crate::purge::purge_selected;
+use crate::routes::routes;
 use crate::seeder::seed::seed;
 
-pub async fn run(cli: &CLI) -> Result<()> {
+/// Run the command given on the command line.
+pub async fn run(ctx: &mut Ctx) -> Result<Outcome> {
+    let cli = ctx.cli().clone();
+    if let Some(environment) = ctx.environment() {
+        if !cli.silent {
+            info_message(format!("environment `{}`", environment));
+        }
+    }
     match &cli.command {
         CLICommand::Serve(serve_command) => {
-            connect_databases(Ctx::main_namespace_mut(), cli.silent).await?;
-            let conn_ctx = Ctx::conn_ctx();
-            // migrate
-            if !serve_command.no_migration {
-                migrate(false, false, cli.silent).await?;
-            }
-            // seed auto seed data sets
-            if !serve_command.no_autoseed {
-                if Ctx::main_namespace().database.is_some() {
-                    let data_sets = load_data_sets(Ctx::main_namespace(), None, false, Ctx::schema())?;
-                    let transaction_ctx = transaction::Ctx::new(Ctx::conn_ctx().clone());
-                    seed(SeedCommandAction::Seed, data_sets, transaction_ctx, false).await?;
-                }
-            }
-            // setup
-            if let Some(setup) = Ctx::setup() {
-                let transaction_ctx = transaction::Ctx::new(Ctx::conn_ctx().clone());
-                setup.call(transaction_ctx).await?;
-            }
+            prepare_for_serving(ctx, !serve_command.no_migration, !serve_command.no_autoseed, cli.silent).await?;
             // start server
-            serve(conn_ctx.namespace(), conn_ctx.namespace().server.as_ref().unwrap(), &Ctx::get().runtime_version, &Ctx::get().entrance, cli.silent).await
+            if serve_command.watch {
+                serve_and_watch(ctx.clone(), !serve_command.no_migration, cli.silent).await?;
+            } else {
+                serve(ctx.clone(), cli.silent).await?;
+            }
+            Ok(Outcome::Success)
         }
         CLICommand::Generate(generate_command) => {
+            let main_namespace = ctx.main_namespace();
             match generate_command {
                 GenerateCommand::GenerateClientCommand(command) => {
                     let names = if let Some(names) = command.names.as_ref() {
                         names.clone()
                     } else if command.all {
-                        Ctx::main_namespace().clients.keys().map(|k| k.clone()).collect()
+                        main_namespace.clients.keys().map(|k| k.clone()).collect()
                     } else {
-                        match Ctx::main_namespace().clients.len() {
+                        match main_namespace.clients.len() {
                             0 => Err(Error::new("no clients found"))?,
-                            1 => return teo_generator::client::generate(Ctx::main_namespace(), Ctx::main_namespace().clients.first_key_value().unwrap().1).await,
+                            1 => vec![main_namespace.clients.first_key_value().unwrap().0.clone()],
                             _ => Err(Error::new("requires client name"))?,
                         }
                     };
                     for name in names {
-                        if let Some(client) = Ctx::main_namespace().clients.get(&name) {
-                            teo_generator::client::generate(Ctx::main_namespace(), client).await?;
+                        if let Some(client) = main_namespace.clients.get(&name) {
+                            teo_generator::client::generate(main_namespace, client).await?;
                         } else {
                             Err(Error::new("client not found"))?
                         }
                     }
-                    Ok(())
+                    Ok(Outcome::Success)
                 }
                 GenerateCommand::GenerateEntityCommand(command) => {
                     let names = if let Some(names) = command.names.as_ref() {
                         names.clone()
                     } else if command.all {
-                        Ctx::main_namespace().entities.keys().map(|k| k.clone()).collect()
+                        main_namespace.entities.keys().map(|k| k.clone()).collect()
                     } else {
-                        match Ctx::main_namespace().entities.len() {
+                        match main_namespace.entities.len() {
                             0 => Err(Error::new("no entities found"))?,
-                            1 => return teo_generator::entity::generate(Ctx::main_namespace(), Ctx::main_namespace().entities.first_key_value().unwrap().1).await,
+                            1 => vec![main_namespace.entities.first_key_value().unwrap().0.clone()],
                             _ => Err(Error::new("requires entity name"))?,
                         }
                     };
                     for name in names {
-                        if let Some(entity) = Ctx::main_namespace().entities.get(&name) {
-                            teo_generator::entity::generate(Ctx::main_namespace(), entity).await?;
+                        if let Some(entity) = main_namespace.entities.get(&name) {
+                            teo_generator::entity::generate(main_namespace, entity).await?;
                         } else {
                             Err(Error::new("entity not found"))?
                         }
                     }
-                    Ok(())
+                    Ok(Outcome::Success)
                 }
             }
         }
         CLICommand::Migrate(migrate_command) => {
-            connect_databases(Ctx::main_namespace_mut(), cli.silent).await?;
-            migrate(migrate_command.dry, false, cli.silent).await?;
-            Ok(())
+            ctx.connect_databases(cli.silent).await?;
+            migrate(ctx, migrate_command.dry, false, cli.silent).await?;
+            Ok(Outcome::Success)
         }
         CLICommand::Seed(seed_command) => {
-            connect_databases(Ctx::main_namespace_mut(), cli.silent).await?;
-            let data_sets = load_data_sets(Ctx::main_namespace(), seed_command.names.as_ref(), seed_command.all, Ctx::schema())?;
-            let transaction_ctx = transaction::Ctx::new(Ctx::conn_ctx().clone());
-            seed(seed_command.action, data_sets, transaction_ctx, true).await?;
-            Ok(())
+            ctx.connect_databases(cli.silent).await?;
+            let data_sets = load_data_sets(ctx.main_namespace(), seed_command.names.as_ref(), seed_command.all, ctx.schema())?;
+            let transaction_ctx = transaction::Ctx::new(ctx.conn_ctx()?.clone());
+            seed(seed_command.action, data_sets, transaction_ctx).await?;
+            Ok(Outcome::Success)
         }
         CLICommand::Purge(purge_command) => {
-            connect_databases(Ctx::main_namespace_mut(), cli.silent).await?;
-            purge().await?;
-            Ok(())
+            ctx.connect_databases(cli.silent).await?;
+            purge_selected(ctx, purge_command).await
+        }
+        CLICommand::Lint(lint_command) => lint(ctx, lint_command),
+        CLICommand::Format(format_command) => format(ctx, format_command),
+        CLICommand::Introspect(introspect_command) => {
+            introspect(ctx, introspect_command).await?;
+            Ok(Outcome::Success)
+        }
+        CLICommand::Console(console_command) => {
+            console(ctx, console_command).await?;
+            Ok(Outcome::Success)
+        }
+        CLICommand::Routes(routes_command) => {
+            routes(ctx, routes_command)?;
+            Ok(Outcome::Success)
+        }
+        CLICommand::Dump(dump_command) => {
+            ctx.connect_databases(cli.silent).await?;
+            dump(ctx, dump_command).await?;
+            Ok(Outcome::Success)
+        }
+        CLICommand::Restore(restore_command) => {
+            ctx.connect_databases(cli.silent).await?;
+            restore(ctx, restore_command).await?;
+            Ok(Outcome::Success)
         }
-        CLICommand::Lint(lint_command) => Ok(()),
         CLICommand::Run(run_command) => {
-            connect_databases(Ctx::main_namespace_mut(), cli.silent).await?;
-            if let Some(program) = Ctx::get_mut().programs.get(&run_command.name) {
-                let transaction_ctx = transaction::Ctx::new(Ctx::conn_ctx().clone());
-                program.call(transaction_ctx).await?;
-                std::process::exit(0);
+            ctx.connect_databases(cli.silent).await?;
+            if let Some(program) = ctx.program(&run_command.name) {
+                let transaction_ctx = transaction::Ctx::new(ctx.conn_ctx()?.clone());
+                with_program_args(&run_command.name, run_command.args.clone(), program.call(transaction_ctx)).await?;
+                Ok(Outcome::Success)
             } else {
                 Err(Error::new(format!("program '{}' is not defined", &run_command.name)))
             }
         },
     }
-}
\ No newline at end of file
+}
+
+/// Connect databases, migrate, seed auto seed data sets and run the setup
+/// callback, which is everything `serve` does before it starts listening.
+pub(crate) async fn prepare_for_serving(ctx: &mut Ctx, migration: bool, autoseed: bool, silent: bool) -> Result<()> {
+    ctx.connect_databases(silent).await?;
+    for model in unreachable_models(ctx.main_namespace()) {
+        warning_message(format!("model `{}` has no database connection, its namespace and the namespaces above it declare no connector", model));
+    }
+    // migrate
+    if migration {
+        migrate(ctx, false, false, silent).await?;
+    }
+    // seed auto seed data sets
+    if autoseed {
+        if ctx.main_namespace().database.is_some() {
+            let data_sets = load_data_sets(ctx.main_namespace(), ctx.autoseed_data_sets(), false, ctx.schema())?;
+            let transaction_ctx = transaction::Ctx::new(ctx.conn_ctx()?.clone());
+            seed(SeedCommandAction::Seed, data_sets, transaction_ctx).await?;
+        }
+    }
+    // setup
+    if let Some(setup) = ctx.setup() {
+        let transaction_ctx = transaction::Ctx::new(ctx.conn_ctx()?.clone());
+        setup.call(transaction_ctx).await?;
+    }
+    ctx.set_running()
+}
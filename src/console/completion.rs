@@ -0,0 +1,83 @@
+use rustyline::completion::{Completer, Pair};
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::validate::Validator;
+use rustyline::{Context, Helper};
+use teo_runtime::namespace::Namespace;
+
+pub(crate) const ACTIONS: [&str; 15] = [
+    "findMany", "findFirst", "findUnique", "count", "aggregate", "groupBy",
+    "create", "update", "upsert", "copy", "delete", "createMany", "updateMany", "copyMany", "deleteMany",
+];
+
+pub(crate) const COMMANDS: [&str; 4] = [".help", ".models", ".fields", ".exit"];
+
+/// A model known to the console, with the names completed inside its
+/// arguments.
+pub(crate) struct ModelEntry {
+    pub(crate) path: String,
+    pub(crate) fields: Vec<String>,
+}
+
+pub(crate) fn collect_models(namespace: &Namespace, prefix: &str, result: &mut Vec<ModelEntry>) {
+    for (name, model) in &namespace.models {
+        let mut fields: Vec<String> = model.fields.keys().cloned().collect();
+        fields.extend(model.relations.keys().cloned());
+        result.push(ModelEntry { path: format!("{}{}", prefix, name), fields });
+    }
+    for (name, child) in &namespace.namespaces {
+        collect_models(child, &format!("{}{}.", prefix, name), result);
+    }
+}
+
+/// Completes dot commands, model paths, action names after a model path and
+/// field names inside the argument of an action.
+pub(crate) struct ConsoleHelper {
+    pub(crate) models: Vec<ModelEntry>,
+}
+
+impl ConsoleHelper {
+
+    fn candidates(&self, line: &str, word: &str) -> Vec<String> {
+        if line.trim_start().starts_with('.') && !line.contains('(') {
+            return COMMANDS.iter().filter(|c| c.starts_with(word)).map(|c| c.to_string()).collect();
+        }
+        if let Some(open) = line.find('(') {
+            let callee = line[..open].trim();
+            let model_path = callee.rsplit_once('.').map(|(path, _)| path).unwrap_or("");
+            return match self.models.iter().find(|m| m.path == model_path) {
+                Some(model) => model.fields.iter().filter(|f| f.starts_with(word)).cloned().collect(),
+                None => vec![],
+            };
+        }
+        if let Some((model_path, action)) = word.rsplit_once('.') {
+            if self.models.iter().any(|m| m.path == model_path) {
+                return ACTIONS.iter().filter(|a| a.starts_with(action)).map(|a| format!("{}.{}", model_path, a)).collect();
+            }
+        }
+        self.models.iter().filter(|m| m.path.starts_with(word)).map(|m| m.path.clone()).collect()
+    }
+}
+
+impl Completer for ConsoleHelper {
+    type Candidate = Pair;
+
+    fn complete(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let line = &line[..pos];
+        let inside_call = line.contains('(');
+        let start = line.rfind(|c: char| !(c.is_alphanumeric() || c == '_' || (c == '.' && !inside_call))).map(|i| i + 1).unwrap_or(0);
+        let word = &line[start..];
+        let pairs = self.candidates(line, word).into_iter().map(|c| Pair { display: c.clone(), replacement: c }).collect();
+        Ok((start, pairs))
+    }
+}
+
+impl Hinter for ConsoleHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ConsoleHelper { }
+
+impl Validator for ConsoleHelper { }
+
+impl Helper for ConsoleHelper { }
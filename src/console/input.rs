@@ -0,0 +1,264 @@
+use serde_json::{Map, Number, Value as JsonValue};
+use teo_result::{Error, Result};
+
+/// A line typed into the console.
+#[derive(Debug, PartialEq)]
+pub(crate) enum ConsoleInput {
+    /// `User.findMany({ take: 2 })` or `blog.Post.count()`.
+    Action { model_path: Vec<String>, action: String, argument: JsonValue },
+    /// `.help`, `.models` and the like.
+    Command(String),
+    Empty,
+}
+
+/// Whether `input` has unclosed brackets or strings, so the console should
+/// read another line before parsing.
+pub(crate) fn is_incomplete(input: &str) -> bool {
+    let mut depth = 0;
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+    for c in input.chars() {
+        if let Some(q) = quote {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == q {
+                quote = None;
+            }
+            continue;
+        }
+        match c {
+            '"' | '\'' => quote = Some(c),
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => depth -= 1,
+            _ => (),
+        }
+    }
+    depth > 0 || quote.is_some()
+}
+
+pub(crate) fn parse_input(input: &str) -> Result<ConsoleInput> {
+    let input = input.trim().trim_end_matches(';').trim();
+    if input.is_empty() {
+        return Ok(ConsoleInput::Empty);
+    }
+    if let Some(command) = input.strip_prefix('.') {
+        return Ok(ConsoleInput::Command(command.trim().to_owned()));
+    }
+    let open = match input.find('(') {
+        Some(open) if input.ends_with(')') => open,
+        _ => Err(Error::new("expect an action call like `User.findMany({ take: 10 })`"))?,
+    };
+    let mut path: Vec<String> = input[..open].trim().split('.').map(|s| s.trim().to_owned()).collect();
+    if path.len() < 2 || path.iter().any(|s| s.is_empty()) {
+        Err(Error::new("expect a model path and an action, like `User.findMany`"))?
+    }
+    let action = path.pop().unwrap();
+    let mut parser = LiteralParser { chars: input[open + 1..input.len() - 1].chars().collect(), pos: 0 };
+    parser.skip_whitespace();
+    let argument = if parser.at_end() { JsonValue::Object(Map::new()) } else { parser.value()? };
+    parser.skip_whitespace();
+    if !parser.at_end() {
+        Err(Error::new(format!("unexpected `{}` after the argument", parser.rest())))?
+    }
+    Ok(ConsoleInput::Action { model_path: path, action, argument })
+}
+
+/// Parse a single Teon literal, like a record of a Teon dump archive.
+pub(crate) fn parse_literal(input: &str) -> Result<JsonValue> {
+    let mut parser = LiteralParser { chars: input.chars().collect(), pos: 0 };
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    if !parser.at_end() {
+        Err(Error::new(format!("unexpected `{}` after the value", parser.rest())))?
+    }
+    Ok(value)
+}
+
+/// Parses the Teon literals an action takes: JSON extended with unquoted
+/// keys, single quoted strings, trailing commas and enum variants like
+/// `.asc`, which are passed as strings.
+struct LiteralParser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl LiteralParser {
+
+    fn at_end(&self) -> bool {
+        self.pos >= self.chars.len()
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).cloned()
+    }
+
+    fn rest(&self) -> String {
+        self.chars[self.pos..].iter().collect()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().map_or(false, |c| c.is_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<()> {
+        self.skip_whitespace();
+        if self.peek() == Some(expected) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(Error::new(format!("expect `{}` at `{}`", expected, self.rest())))
+        }
+    }
+
+    fn value(&mut self) -> Result<JsonValue> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('{') => self.object(),
+            Some('[') => self.array(),
+            Some('"') | Some('\'') => Ok(JsonValue::String(self.string()?)),
+            Some('.') => {
+                self.pos += 1;
+                Ok(JsonValue::String(self.identifier()?))
+            }
+            Some(c) if c == '-' || c.is_ascii_digit() => self.number(),
+            Some(c) if c.is_alphabetic() || c == '_' => match self.identifier()?.as_str() {
+                "true" => Ok(JsonValue::Bool(true)),
+                "false" => Ok(JsonValue::Bool(false)),
+                "null" => Ok(JsonValue::Null),
+                other => Err(Error::new(format!("unexpected `{}`", other))),
+            },
+            _ => Err(Error::new(format!("expect a value at `{}`", self.rest()))),
+        }
+    }
+
+    fn object(&mut self) -> Result<JsonValue> {
+        self.expect('{')?;
+        let mut map = Map::new();
+        loop {
+            self.skip_whitespace();
+            if self.peek() == Some('}') {
+                self.pos += 1;
+                return Ok(JsonValue::Object(map));
+            }
+            let key = match self.peek() {
+                Some('"') | Some('\'') => self.string()?,
+                _ => self.identifier()?,
+            };
+            self.expect(':')?;
+            let value = self.value()?;
+            map.insert(key, value);
+            self.skip_whitespace();
+            match self.peek() {
+                Some(',') => self.pos += 1,
+                Some('}') => (),
+                _ => Err(Error::new(format!("expect `,` or `}}` at `{}`", self.rest())))?,
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<JsonValue> {
+        self.expect('[')?;
+        let mut items = vec![];
+        loop {
+            self.skip_whitespace();
+            if self.peek() == Some(']') {
+                self.pos += 1;
+                return Ok(JsonValue::Array(items));
+            }
+            items.push(self.value()?);
+            self.skip_whitespace();
+            match self.peek() {
+                Some(',') => self.pos += 1,
+                Some(']') => (),
+                _ => Err(Error::new(format!("expect `,` or `]` at `{}`", self.rest())))?,
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String> {
+        let quote = self.peek().unwrap();
+        self.pos += 1;
+        let mut result = String::new();
+        while let Some(c) = self.peek() {
+            self.pos += 1;
+            match c {
+                '\\' => match self.peek() {
+                    Some('u') => {
+                        self.pos += 1;
+                        result.push(self.unicode_escape()?);
+                    }
+                    Some(escaped) => {
+                        self.pos += 1;
+                        result.push(match escaped {
+                            'n' => '\n',
+                            't' => '\t',
+                            'r' => '\r',
+                            'b' => '\u{8}',
+                            'f' => '\u{c}',
+                            other => other,
+                        });
+                    }
+                    None => break,
+                },
+                c if c == quote => return Ok(result),
+                c => result.push(c),
+            }
+        }
+        Err(Error::new("unterminated string"))
+    }
+
+    /// The character of a `\uXXXX` escape, the `\u` consumed already. A
+    /// surrogate pair is two escapes.
+    fn unicode_escape(&mut self) -> Result<char> {
+        let high = self.hex4()?;
+        let code = if (0xD800..0xDC00).contains(&high) && self.chars.get(self.pos..self.pos + 2) == Some(&['\\', 'u'][..]) {
+            self.pos += 2;
+            let low = self.hex4()?;
+            0x10000 + ((high - 0xD800) << 10) + (low.wrapping_sub(0xDC00) & 0x3FF)
+        } else {
+            high
+        };
+        char::from_u32(code).ok_or_else(|| Error::new(format!("invalid unicode escape {:x}", code)))
+    }
+
+    fn hex4(&mut self) -> Result<u32> {
+        let digits: String = self.chars.iter().skip(self.pos).take(4).collect();
+        match u32::from_str_radix(&digits, 16) {
+            Ok(code) if digits.len() == 4 => {
+                self.pos += 4;
+                Ok(code)
+            }
+            _ => Err(Error::new(format!("invalid unicode escape at `{}`", self.rest()))),
+        }
+    }
+
+    fn identifier(&mut self) -> Result<String> {
+        let start = self.pos;
+        while self.peek().map_or(false, |c| c.is_alphanumeric() || c == '_') {
+            self.pos += 1;
+        }
+        if start == self.pos {
+            Err(Error::new(format!("expect an identifier at `{}`", self.rest())))?
+        }
+        Ok(self.chars[start..self.pos].iter().collect())
+    }
+
+    fn number(&mut self) -> Result<JsonValue> {
+        let start = self.pos;
+        while self.peek().map_or(false, |c| c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E')) {
+            self.pos += 1;
+        }
+        let text: String = self.chars[start..self.pos].iter().collect();
+        if let Ok(i) = text.parse::<i64>() {
+            return Ok(JsonValue::Number(Number::from(i)));
+        }
+        match text.parse::<f64>().ok().and_then(Number::from_f64) {
+            Some(n) => Ok(JsonValue::Number(n)),
+            None => Err(Error::new(format!("invalid number `{}`", text))),
+        }
+    }
+}
@@ -0,0 +1,224 @@
+pub(crate) mod input;
+pub(crate) mod completion;
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use rustyline::error::ReadlineError;
+use rustyline::history::DefaultHistory;
+use rustyline::Editor;
+use serde_json::Value as JsonValue;
+use teo_result::{Error, Result};
+use teo_runtime::connection::transaction;
+use teo_runtime::handler::r#match::HandlerMatch;
+use teo_runtime::handler::action::builtin_action_handler_from_name;
+use teo_runtime::handler::input::validate_and_transform_json_input_for_builtin_action;
+use teo_runtime::request;
+use teo_runtime::response::body::BodyInner;
+use crate::app::ctx::Ctx;
+use crate::cli::command::ConsoleCommand;
+use crate::console::completion::{collect_models, ConsoleHelper, ModelEntry, ACTIONS};
+use crate::console::input::{is_incomplete, parse_input, ConsoleInput};
+use crate::message::error_message;
+use crate::server::make::{action_is_read_only, call_builtin_action};
+use crate::server::request::RequestImpl;
+
+const HELP: &str = "\
+Type a builtin action call with a Teon argument, for example:
+
+  User.findMany({ where: { email: { contains: \"x\" } }, take: 10 })
+  blog.Post.count()
+
+Commands:
+  .models          list models
+  .fields MODEL    list fields and relations of a model
+  .help            show this message
+  .exit            leave the console";
+
+/// Run builtin actions against the connected databases interactively.
+///
+/// Input is read line by line until brackets and strings are balanced, so
+/// arguments may span several lines. With `--read-only`, actions which
+/// write are rejected before they reach the database.
+pub(crate) async fn console(ctx: &mut Ctx, command: &ConsoleCommand) -> Result<()> {
+    let silent = ctx.cli().silent;
+    ctx.connect_databases(silent).await?;
+    let mut models = vec![];
+    collect_models(ctx.conn_ctx()?.namespace(), "", &mut models);
+    let mut editor: Editor<ConsoleHelper, DefaultHistory> = match Editor::new() {
+        Ok(editor) => editor,
+        Err(err) => Err(Error::new(format!("cannot start the console: {}", err)))?,
+    };
+    editor.set_helper(Some(ConsoleHelper { models }));
+    let history = history_path();
+    if let Some(history) = history.as_ref() {
+        let _ = editor.load_history(history);
+    }
+    if !silent {
+        println!("Teo console{}, type .help for help", if command.read_only { " (read only)" } else { "" });
+    }
+    let mut buffer = String::new();
+    loop {
+        let prompt = if buffer.is_empty() { "teo> " } else { "...> " };
+        let line = match editor.readline(prompt) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => {
+                buffer.clear();
+                continue;
+            }
+            Err(ReadlineError::Eof) => break,
+            Err(err) => Err(Error::new(format!("cannot read input: {}", err)))?,
+        };
+        if !buffer.is_empty() {
+            buffer.push('\n');
+        }
+        buffer.push_str(&line);
+        if is_incomplete(&buffer) {
+            continue;
+        }
+        let entry = std::mem::take(&mut buffer);
+        if !entry.trim().is_empty() {
+            let _ = editor.add_history_entry(entry.as_str());
+        }
+        match parse_input(&entry) {
+            Ok(ConsoleInput::Empty) => (),
+            Ok(ConsoleInput::Command(command)) => if !run_command(&command, &editor.helper().unwrap().models) {
+                break;
+            },
+            Ok(ConsoleInput::Action { model_path, action, argument }) => {
+                match execute(ctx, &model_path, &action, &argument, command.read_only).await {
+                    Ok(result) => println!("{}", serde_json::to_string_pretty(&result).unwrap()),
+                    Err(err) => error_message(err.to_string()),
+                }
+            }
+            Err(err) => error_message(err.to_string()),
+        }
+    }
+    if let Some(history) = history.as_ref() {
+        let _ = editor.save_history(history);
+    }
+    Ok(())
+}
+
+fn history_path() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".teo_console_history"))
+}
+
+/// Run a dot command. Returns false when the console should exit.
+fn run_command(command: &str, models: &Vec<ModelEntry>) -> bool {
+    let mut parts = command.split_whitespace();
+    match parts.next() {
+        Some("exit") | Some("quit") => return false,
+        Some("help") => println!("{}", HELP),
+        Some("models") => for model in models {
+            println!("{}", model.path);
+        },
+        Some("fields") => match parts.next().and_then(|path| models.iter().find(|m| m.path == path)) {
+            Some(model) => for field in &model.fields {
+                println!("{}", field);
+            },
+            None => error_message("usage: .fields MODEL"),
+        },
+        _ => error_message(format!("unknown command .{}, type .help for help", command)),
+    }
+    true
+}
+
+/// Run a builtin action through the same handler the server runs for
+/// `POST /<model path>/<action>`. Middlewares are not run, since there is
+/// no client request to check. The action runs with `ctx` as the current
+/// app ctx, like it does in a request.
+async fn execute(
+    ctx: &Ctx,
+    model_path: &Vec<String>,
+    action_name: &str,
+    argument: &JsonValue,
+    read_only: bool,
+) -> Result<JsonValue> {
+    let namespace = ctx.conn_ctx()?.namespace();
+    let Some(model) = namespace.model_at_path(&model_path.iter().map(AsRef::as_ref).collect()) else {
+        Err(Error::new(format!("model {} is not found", model_path.join("."))))?
+    };
+    let Some(action) = builtin_action_handler_from_name(action_name).filter(|_| ACTIONS.contains(&action_name)) else {
+        Err(Error::new(format!("{} is not a builtin action, expect one of {}", action_name, ACTIONS.join(", "))))?
+    };
+    if read_only && !action_is_read_only(action_name) {
+        Err(Error::new(format!("{} writes to the database and the console is read only", action_name)))?
+    }
+    let body = validate_and_transform_json_input_for_builtin_action(model, action, argument, namespace)?;
+    let request_impl = RequestImpl::detached(&format!("/{}/{}", model_path.join("/"), action_name), ctx.clone())?;
+    let request_ctx = request::Ctx::new(
+        request::Request::new(request_impl),
+        Arc::new(body),
+        transaction::Ctx::new(ctx.conn_ctx()?.clone()),
+        HandlerMatch::new(model_path.clone(), action_name.to_owned(), BTreeMap::new()),
+    );
+    let response = ctx.clone().scope(call_builtin_action(&request_ctx)).await?;
+    let BodyInner::Teon(value) = response.body().inner.as_ref() else {
+        Err(Error::new(format!("{} does not respond with a value", action_name)))?
+    };
+    match JsonValue::try_from(value) {
+        Ok(json) => Ok(json),
+        Err(err) => Err(Error::new(format!("cannot print the result: {}", err))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value as JsonValue};
+    use teo_result::Result;
+    use crate::test::schema::app_for_schema_source;
+    use crate::test::TestClient;
+    use super::execute;
+
+    const SCHEMA: &str = "connector {\n  provider .sqlite\n  url \"sqlite::memory:\"\n}\n\nserver {\n  bind (\"0.0.0.0\", 4000)\n}\n\nmodel Artist {\n  @id @autoIncrement @readonly\n  id: Int\n  name: String\n}\n";
+
+    async fn client() -> TestClient {
+        app_for_schema_source(SCHEMA).test_client_with_memory_database().await.unwrap()
+    }
+
+    async fn run(client: &TestClient, action: &str, argument: JsonValue, read_only: bool) -> Result<JsonValue> {
+        execute(client.ctx(), &vec!["Artist".to_owned()], action, &argument, read_only).await
+    }
+
+    #[actix_web::test]
+    async fn runs_actions_like_the_server() {
+        let client = client().await;
+        let created = run(&client, "create", json!({ "create": { "name": "Ada" } }), false).await.unwrap();
+        assert_eq!(created["data"]["name"], json!("Ada"));
+        run(&client, "createMany", json!({ "create": [{ "name": "Bea" }, { "name": "Cy" }] }), false).await.unwrap();
+        let count = run(&client, "count", json!({}), true).await.unwrap();
+        assert_eq!(count["data"], json!(3));
+        let found = run(&client, "findMany", json!({ "where": { "name": "Bea" } }), true).await.unwrap();
+        assert_eq!(found["data"][0]["name"], json!("Bea"));
+    }
+
+    #[actix_web::test]
+    async fn copies_records() {
+        let client = client().await;
+        let created = run(&client, "create", json!({ "create": { "name": "Ada" } }), false).await.unwrap();
+        let id = created["data"]["id"].clone();
+        let copied = run(&client, "copy", json!({ "where": { "id": id }, "copy": { "name": "Ada 2" } }), false).await.unwrap();
+        assert_eq!(copied["data"]["name"], json!("Ada 2"));
+        assert_ne!(copied["data"]["id"], id);
+        run(&client, "copyMany", json!({ "where": {}, "copy": {} }), false).await.unwrap();
+        let count = run(&client, "count", json!({}), true).await.unwrap();
+        assert_eq!(count["data"], json!(4));
+    }
+
+    #[actix_web::test]
+    async fn rejects_writes_when_read_only() {
+        let client = client().await;
+        let err = run(&client, "create", json!({ "create": { "name": "Ada" } }), true).await.unwrap_err();
+        assert!(err.to_string().contains("read only"), "{}", err);
+        let count = run(&client, "count", json!({}), true).await.unwrap();
+        assert_eq!(count["data"], json!(0));
+    }
+
+    #[actix_web::test]
+    async fn rejects_unknown_actions() {
+        let client = client().await;
+        let err = run(&client, "truncate", json!({}), false).await.unwrap_err();
+        assert!(err.to_string().contains("not a builtin action"), "{}", err);
+    }
+}
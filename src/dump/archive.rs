@@ -0,0 +1,211 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Lines, Write};
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value as JsonValue};
+use teo_parser::r#type::Type;
+use teo_result::{Error, Result};
+use teo_teon::value::Value;
+use crate::console::input::parse_literal;
+use crate::dump::teon::write_record;
+
+pub(crate) const ARCHIVE_VERSION: u32 = 1;
+
+/// How records are written, one per line.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum RecordFormat {
+    /// `{"model":"blog.Post","record":{...}}`
+    Ndjson,
+    /// `{ "model": "blog.Post", "record": { "kind": .draft, ... } }`
+    Teon,
+}
+
+/// The first line of an archive, always JSON.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Header {
+    pub(crate) teo_dump: u32,
+    pub(crate) format: RecordFormat,
+    pub(crate) checksum: String,
+    pub(crate) created_at: String,
+    /// Model paths in the order their records follow.
+    pub(crate) models: Vec<String>,
+}
+
+/// Writes a gzip compressed archive line by line.
+pub(crate) struct ArchiveWriter {
+    encoder: GzEncoder<BufWriter<File>>,
+    format: RecordFormat,
+}
+
+impl ArchiveWriter {
+
+    pub(crate) fn create(path: &str, header: &Header) -> Result<Self> {
+        let file = match File::create(path) {
+            Ok(file) => file,
+            Err(err) => Err(Error::new(format!("cannot create {}: {}", path, err)))?,
+        };
+        let mut writer = Self { encoder: GzEncoder::new(BufWriter::new(file), Compression::default()), format: header.format };
+        writer.write_line(&serde_json::to_string(header).unwrap())?;
+        Ok(writer)
+    }
+
+    /// Write a record of the model at `model_path`, given as its fields
+    /// with their types.
+    pub(crate) fn write_record(&mut self, model_path: &str, fields: &[(&str, &Type, Value)]) -> Result<()> {
+        let line = match self.format {
+            RecordFormat::Ndjson => {
+                let mut record = Map::new();
+                for (name, _, value) in fields {
+                    match JsonValue::try_from(value.clone()) {
+                        Ok(json) => record.insert(name.to_string(), json),
+                        Err(err) => Err(Error::new(format!("cannot dump {}.{}: {}", model_path, name, err)))?,
+                    };
+                }
+                json!({ "model": model_path, "record": record }).to_string()
+            }
+            RecordFormat::Teon => {
+                let mut record = String::new();
+                if let Err(err) = write_record(fields.iter().map(|(name, r#type, value)| (*name, *r#type, value)), &mut record) {
+                    Err(Error::new(format!("cannot dump {}: {}", model_path, err)))?
+                }
+                format!("{{ \"model\": {}, \"record\": {} }}", JsonValue::String(model_path.to_owned()), record)
+            }
+        };
+        self.write_line(&line)
+    }
+
+    /// Finish the compressed stream and flush it to the file.
+    pub(crate) fn finish(&mut self) -> Result<()> {
+        match self.encoder.try_finish().and_then(|_| self.encoder.get_mut().flush()) {
+            Ok(()) => Ok(()),
+            Err(err) => Err(Error::new(format!("cannot write the archive: {}", err))),
+        }
+    }
+
+    fn write_line(&mut self, line: &str) -> Result<()> {
+        match writeln!(self.encoder, "{}", line) {
+            Ok(()) => Ok(()),
+            Err(err) => Err(Error::new(format!("cannot write the archive: {}", err))),
+        }
+    }
+}
+
+/// Reads an archive written by `ArchiveWriter` without loading it whole.
+pub(crate) struct ArchiveReader {
+    lines: Lines<BufReader<GzDecoder<File>>>,
+    line_number: usize,
+    pub(crate) header: Header,
+}
+
+impl ArchiveReader {
+
+    pub(crate) fn open(path: &str) -> Result<Self> {
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(err) => Err(Error::new(format!("cannot open {}: {}", path, err)))?,
+        };
+        let mut lines = BufReader::new(GzDecoder::new(file)).lines();
+        let header: Header = match lines.next() {
+            Some(Ok(line)) => match serde_json::from_str(&line) {
+                Ok(header) => header,
+                Err(_) => Err(Error::new(format!("{} is not a teo dump archive", path)))?,
+            },
+            Some(Err(err)) => Err(Error::new(format!("cannot read {}: {}", path, err)))?,
+            None => Err(Error::new(format!("{} is empty", path)))?,
+        };
+        if header.teo_dump > ARCHIVE_VERSION {
+            Err(Error::new(format!("{} is written by a newer version of teo", path)))?
+        }
+        Ok(Self { lines, line_number: 1, header })
+    }
+
+    /// The next model path and record, or None at the end.
+    pub(crate) fn next_record(&mut self) -> Result<Option<(String, JsonValue)>> {
+        let line = match self.lines.next() {
+            Some(Ok(line)) => line,
+            Some(Err(err)) => Err(Error::new(format!("cannot read the archive: {}", err)))?,
+            None => return Ok(None),
+        };
+        self.line_number += 1;
+        if line.trim().is_empty() {
+            return self.next_record();
+        }
+        let value = match self.header.format {
+            RecordFormat::Ndjson => match serde_json::from_str(&line) {
+                Ok(value) => value,
+                Err(err) => Err(Error::new(format!("line {}: {}", self.line_number, err)))?,
+            },
+            RecordFormat::Teon => match parse_literal(&line) {
+                Ok(value) => value,
+                Err(err) => Err(Error::new(format!("line {}: {}", self.line_number, err)))?,
+            },
+        };
+        match value {
+            JsonValue::Object(mut map) => match (map.remove("model"), map.remove("record")) {
+                (Some(JsonValue::String(model)), Some(record @ JsonValue::Object(_))) => Ok(Some((model, record))),
+                _ => Err(Error::new(format!("line {}: expect a model and a record", self.line_number))),
+            },
+            _ => Err(Error::new(format!("line {}: expect a model and a record", self.line_number))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+    use serde_json::json;
+    use teo_parser::r#type::Type;
+    use teo_teon::value::Value;
+    use uuid::Uuid;
+    use crate::dump::value::decode;
+    use super::{ArchiveReader, ArchiveWriter, Header, RecordFormat, ARCHIVE_VERSION};
+
+    fn round_trip(format: RecordFormat) {
+        let path = std::env::temp_dir().join(format!("teo-dump-{}.gz", Uuid::new_v4()));
+        let path = path.to_str().unwrap();
+        let header = Header {
+            teo_dump: ARCHIVE_VERSION,
+            format,
+            checksum: "checksum".to_owned(),
+            created_at: "2024-01-01T00:00:00Z".to_owned(),
+            models: vec!["blog.Post".to_owned()],
+        };
+        let optional_date = Type::Optional(Box::new(Type::Date));
+        let tags = Type::Array(Box::new(Type::String));
+        let fields = vec![
+            ("id", &Type::Int, Value::Int(1)),
+            ("title", &Type::String, Value::String("Quotes \" and\nlines".to_owned())),
+            ("rating", &Type::Float, Value::Float(4.0)),
+            ("publishedOn", &optional_date, Value::Date(NaiveDate::from_ymd_opt(2024, 2, 29).unwrap())),
+            ("archivedOn", &optional_date, Value::Null),
+            ("tags", &tags, Value::Array(vec![Value::String("a".to_owned())])),
+        ];
+        let mut writer = ArchiveWriter::create(path, &header).unwrap();
+        writer.write_record("blog.Post", &fields).unwrap();
+        writer.finish().unwrap();
+        let mut reader = ArchiveReader::open(path).unwrap();
+        assert_eq!(reader.header.format, format);
+        assert_eq!(reader.header.models, vec!["blog.Post".to_owned()]);
+        let (model_path, record) = reader.next_record().unwrap().unwrap();
+        assert_eq!(model_path, "blog.Post");
+        for (name, r#type, value) in &fields {
+            assert_eq!(&decode(&record[*name], r#type).unwrap(), value, "{}", name);
+        }
+        assert_eq!(record["rating"], json!(4.0));
+        assert!(reader.next_record().unwrap().is_none());
+    }
+
+    #[test]
+    fn ndjson_round_trip() {
+        round_trip(RecordFormat::Ndjson);
+    }
+
+    #[test]
+    fn teon_round_trip() {
+        round_trip(RecordFormat::Teon);
+    }
+}
@@ -0,0 +1,204 @@
+use std::sync::Arc;
+use bson::{Bson, Document};
+use chrono::{SecondsFormat, TimeZone, Utc};
+use mongodb::Client;
+use teo_result::{Error, Result};
+use teo_runtime::connection::connection::Connection;
+use teo_runtime::database::database::Database;
+use teo_runtime::model::Model;
+use teo_runtime::namespace::Namespace;
+use teo_teon::value::Value;
+use crate::app::database::sql::{quote_identifier, quote_string};
+use crate::app::environment::interpolate;
+
+pub(crate) type Row = Vec<(String, Value)>;
+
+/// Inserts the records of models under one connector, a batch at a time.
+///
+/// SQL databases get one `INSERT` statement per batch, MongoDB gets one
+/// `insertMany` per batch. Records are written as they are, without running
+/// the pipelines of the fields, since they are already processed.
+pub(crate) struct Inserter {
+    database: Database,
+    connection: Arc<dyn Connection>,
+    mongo: Option<mongodb::Database>,
+}
+
+impl Inserter {
+
+    pub(crate) async fn new(namespace: &'static Namespace) -> Result<Self> {
+        let (Some(connector), Some(connection)) = (namespace.connector.as_ref(), namespace.connection.as_ref()) else {
+            Err(Error::new(format!("namespace `{}` is not connected", namespace.path.join("."))))?
+        };
+        let mongo = if connector.provider.is_mongo() {
+            let client = match Client::with_uri_str(interpolate(&connector.url)?).await {
+                Ok(client) => client,
+                Err(err) => Err(Error::new(format!("cannot connect to mongodb: {}", err)))?,
+            };
+            match client.default_database() {
+                Some(database) => Some(database),
+                None => Err(Error::new("database name is missing in the connector url"))?,
+            }
+        } else {
+            None
+        };
+        Ok(Self { database: connector.provider.clone(), connection: connection.clone(), mongo })
+    }
+
+    pub(crate) async fn insert(&self, model: &Model, rows: &[Row]) -> Result<()> {
+        // a statement takes rows with the same fields, which they all have
+        // unless the archive is restored into another schema with --force
+        for rows in rows.chunk_by(|a, b| same_fields(a, b)) {
+            match self.mongo.as_ref() {
+                Some(database) => self.insert_documents(database, model, rows).await?,
+                None => {
+                    let statement = insert_statement(&self.database, model, rows)?;
+                    self.connection.no_transaction().await?.query_raw(&Value::String(statement)).await?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Move the auto increment sequences of `model` past the restored
+    /// records. Only PostgreSQL keeps sequences apart from the tables.
+    pub(crate) async fn reset_sequences(&self, model: &Model) -> Result<()> {
+        if self.database != Database::PostgreSQL {
+            return Ok(());
+        }
+        let table = quote_identifier(&self.database, &model.table_name);
+        for field in model.fields.values().filter(|field| field.auto_increment) {
+            let column = quote_identifier(&self.database, &field.column_name);
+            let statement = format!(
+                "SELECT setval(pg_get_serial_sequence({}, {}), COALESCE(MAX({}), 0) + 1, false) FROM {}",
+                quote_string(&self.database, &table), quote_string(&self.database, &field.column_name), column, table,
+            );
+            self.connection.no_transaction().await?.query_raw(&Value::String(statement)).await?;
+        }
+        Ok(())
+    }
+
+    async fn insert_documents(&self, database: &mongodb::Database, model: &Model, rows: &[Row]) -> Result<()> {
+        let mut documents = vec![];
+        for row in rows {
+            let mut document = Document::new();
+            for (key, value) in row {
+                document.insert(column_name(model, key)?, bson_value(value)?);
+            }
+            documents.push(document);
+        }
+        match database.collection::<Document>(&model.table_name).insert_many(documents, None).await {
+            Ok(_) => Ok(()),
+            Err(err) => Err(Error::new(format!("cannot insert into {}: {}", model.table_name, err))),
+        }
+    }
+}
+
+fn same_fields(a: &Row, b: &Row) -> bool {
+    a.len() == b.len() && a.iter().zip(b).all(|((a, _), (b, _))| a == b)
+}
+
+fn column_name<'a>(model: &'a Model, key: &str) -> Result<&'a str> {
+    match model.field(key) {
+        Some(field) => Ok(field.column_name.as_str()),
+        None => Err(Error::new(format!("{} has no field {}", model.path.join("."), key))),
+    }
+}
+
+/// A multi row `INSERT` statement with the values of `rows` inlined.
+pub(crate) fn insert_statement(database: &Database, model: &Model, rows: &[Row]) -> Result<String> {
+    let Some(first) = rows.first() else {
+        Err(Error::new("no rows to insert"))?
+    };
+    let mut columns = vec![];
+    for (key, _) in first {
+        columns.push(quote_identifier(database, column_name(model, key)?));
+    }
+    let mut tuples = vec![];
+    for row in rows {
+        let mut literals = vec![];
+        for (key, value) in row {
+            match sql_literal(database, value) {
+                Ok(literal) => literals.push(literal),
+                Err(err) => Err(Error::new(format!("{}.{}: {}", model.path.join("."), key, err)))?,
+            }
+        }
+        tuples.push(format!("({})", literals.join(", ")));
+    }
+    Ok(format!("INSERT INTO {} ({}) VALUES {}", quote_identifier(database, &model.table_name), columns.join(", "), tuples.join(", ")))
+}
+
+fn sql_literal(database: &Database, value: &Value) -> Result<String> {
+    Ok(match value {
+        Value::Null => "NULL".to_owned(),
+        Value::Bool(b) => match database {
+            Database::PostgreSQL => if *b { "TRUE" } else { "FALSE" }.to_owned(),
+            _ => if *b { "1" } else { "0" }.to_owned(),
+        },
+        Value::Int(i) => i.to_string(),
+        Value::Int64(i) => i.to_string(),
+        Value::Float32(f) if f.is_finite() => format!("{:?}", f),
+        Value::Float(f) if f.is_finite() => format!("{:?}", f),
+        Value::Decimal(d) => d.to_string(),
+        Value::String(s) => quote_string(database, s),
+        Value::EnumVariant(variant) => quote_string(database, &variant.value),
+        Value::Date(date) => quote_string(database, &date.format("%Y-%m-%d").to_string()),
+        Value::DateTime(datetime) => match database {
+            Database::MySQL => quote_string(database, &datetime.format("%Y-%m-%d %H:%M:%S%.3f").to_string()),
+            _ => quote_string(database, &datetime.to_rfc3339_opts(SecondsFormat::Millis, true)),
+        },
+        Value::Array(items) if *database == Database::PostgreSQL => if items.is_empty() {
+            "'{}'".to_owned()
+        } else {
+            let mut literals = vec![];
+            for item in items {
+                literals.push(sql_literal(database, item)?);
+            }
+            format!("ARRAY[{}]", literals.join(", "))
+        },
+        _ => Err(Error::new(format!("{:?} cannot be inserted into {}", value, database.lowercase_desc())))?,
+    })
+}
+
+fn bson_value(value: &Value) -> Result<Bson> {
+    Ok(match value {
+        Value::Null => Bson::Null,
+        Value::Bool(b) => Bson::Boolean(*b),
+        Value::Int(i) => Bson::Int32(*i),
+        Value::Int64(i) => Bson::Int64(*i),
+        Value::Float32(f) => Bson::Double(*f as f64),
+        Value::Float(f) => Bson::Double(*f),
+        Value::Decimal(d) => Bson::String(d.to_string()),
+        Value::String(s) => Bson::String(s.clone()),
+        Value::EnumVariant(variant) => Bson::String(variant.value.clone()),
+        Value::ObjectId(id) => Bson::ObjectId(*id),
+        Value::Date(date) => Bson::DateTime(bson::DateTime::from_chrono(Utc.from_utc_datetime(&date.and_hms_opt(0, 0, 0).unwrap()))),
+        Value::DateTime(datetime) => Bson::DateTime(bson::DateTime::from_chrono(*datetime)),
+        Value::Array(items) => {
+            let mut values = vec![];
+            for item in items {
+                values.push(bson_value(item)?);
+            }
+            Bson::Array(values)
+        }
+        _ => Err(Error::new(format!("{:?} cannot be inserted into mongodb", value)))?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use teo_runtime::database::database::Database;
+    use teo_teon::value::Value;
+    use super::sql_literal;
+
+    #[test]
+    fn writes_literals_per_database() {
+        assert_eq!(sql_literal(&Database::PostgreSQL, &Value::Bool(true)).unwrap(), "TRUE");
+        assert_eq!(sql_literal(&Database::SQLite, &Value::Bool(true)).unwrap(), "1");
+        assert_eq!(sql_literal(&Database::MySQL, &Value::String("a'b\\".to_owned())).unwrap(), "'a''b\\\\'");
+        assert_eq!(sql_literal(&Database::PostgreSQL, &Value::Array(vec![Value::Int(1), Value::Int(2)])).unwrap(), "ARRAY[1, 2]");
+        assert_eq!(sql_literal(&Database::PostgreSQL, &Value::Array(vec![])).unwrap(), "'{}'");
+        assert!(sql_literal(&Database::SQLite, &Value::Array(vec![])).is_err());
+        assert!(sql_literal(&Database::SQLite, &Value::Float(f64::NAN)).is_err());
+    }
+}
@@ -0,0 +1,366 @@
+pub(crate) mod archive;
+pub(crate) mod insert;
+pub(crate) mod teon;
+pub(crate) mod value;
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use chrono::Utc;
+use indexmap::IndexMap;
+use itertools::Itertools;
+use key_path::path;
+use ring::digest;
+use serde_json::Value as JsonValue;
+use teo_result::{Error, Result};
+use teo_runtime::connection::transaction;
+use teo_runtime::model::Model;
+use teo_runtime::model::field::typed::Typed;
+use teo_runtime::namespace::Namespace;
+use teo_runtime::traits::named::Named;
+use teo_teon::teon;
+use teo_teon::value::Value;
+use crate::app::ctx::Ctx;
+use crate::cli::command::{DumpCommand, RestoreCommand};
+use crate::dump::archive::{ArchiveReader, ArchiveWriter, Header, RecordFormat, ARCHIVE_VERSION};
+use crate::dump::insert::{Inserter, Row};
+use crate::dump::value::decode;
+use crate::message::info_message;
+
+/// Write the records of every model under a connector into a gzip
+/// compressed archive.
+///
+/// Models are written in foreign key order, so an archive is restored front
+/// to back. Join tables of many to many relations are models of their own
+/// and are dumped like any other. Records are read a batch at a time in one
+/// transaction, paging on the primary key, so that records written during
+/// the dump are neither skipped nor repeated.
+pub(crate) async fn dump(ctx: &Ctx, command: &DumpCommand) -> Result<()> {
+    let models = order_by_foreign_keys(models_under_connectors(ctx)?)?;
+    let header = Header {
+        teo_dump: ARCHIVE_VERSION,
+        format: if command.format == "teon" { RecordFormat::Teon } else { RecordFormat::Ndjson },
+        checksum: schema_checksum(&models),
+        created_at: Utc::now().to_rfc3339(),
+        models: models.iter().map(|model| model.path.join(".")).collect(),
+    };
+    let writer = Arc::new(Mutex::new(ArchiveWriter::create(&command.output, &header)?));
+    let transaction_ctx = transaction::Ctx::new(ctx.conn_ctx()?.clone());
+    let batch_size = command.batch_size.max(1);
+    let total = transaction_ctx.run_transaction(|ctx| {
+        let models = models.clone();
+        let writer = writer.clone();
+        async move {
+            let mut total = 0;
+            for model in models {
+                total += dump_model(&ctx, model, batch_size, &writer).await?;
+            }
+            Ok(total)
+        }
+    }).await?;
+    writer.lock().unwrap().finish()?;
+    if !ctx.cli().silent {
+        info_message(format!("dumped {} record(s) of {} model(s) to {}", total, models.len(), command.output));
+    }
+    Ok(())
+}
+
+async fn dump_model(ctx: &transaction::Ctx, model: &'static Model, batch_size: usize, writer: &Mutex<ArchiveWriter>) -> Result<usize> {
+    let model_path = model.path.join(".");
+    let Some(keys) = model.primary_index().map(|index| index.keys()) else {
+        Err(Error::new(format!("{} has no primary key to dump by", model_path)))?
+    };
+    let order_by = Value::Array(keys.iter().map(|key| {
+        Value::Dictionary(IndexMap::from([(key.clone(), Value::String("asc".to_owned()))]))
+    }).collect());
+    let fields: Vec<_> = model.fields.values().filter(|field| !field.r#virtual).collect();
+    let mut cursor: Option<Vec<Value>> = None;
+    let mut total = 0;
+    loop {
+        let mut query = IndexMap::from([
+            ("orderBy".to_owned(), order_by.clone()),
+            ("take".to_owned(), Value::Int64(batch_size as i64)),
+        ]);
+        if let Some(cursor) = cursor.as_ref() {
+            query.insert("where".to_owned(), after_cursor(&keys, cursor));
+        }
+        let objects = ctx.find_many(model, &Value::Dictionary(query), None, path![]).await?;
+        for object in &objects {
+            let mut record = vec![];
+            for field in &fields {
+                record.push((field.name(), field.r#type(), object.get_value(field.name())?));
+            }
+            writer.lock().unwrap().write_record(&model_path, &record)?;
+        }
+        total += objects.len();
+        match objects.last() {
+            Some(last) if objects.len() == batch_size => {
+                let mut values = vec![];
+                for key in &keys {
+                    values.push(last.get_value(key)?);
+                }
+                cursor = Some(values);
+            }
+            _ => break,
+        }
+    }
+    Ok(total)
+}
+
+/// A filter for the records ordered after `cursor` by `keys`, ascending.
+fn after_cursor(keys: &Vec<String>, cursor: &Vec<Value>) -> Value {
+    let mut branches = vec![];
+    for (index, key) in keys.iter().enumerate() {
+        let mut branch = IndexMap::new();
+        for (key, value) in keys.iter().zip(cursor).take(index) {
+            branch.insert(key.clone(), value.clone());
+        }
+        branch.insert(key.clone(), teon!({ "gt": cursor[index].clone() }));
+        branches.push(Value::Dictionary(branch));
+    }
+    if branches.len() == 1 {
+        branches.remove(0)
+    } else {
+        teon!({ "OR": Value::Array(branches) })
+    }
+}
+
+/// Insert the records of an archive written by `dump`.
+///
+/// Records are inserted with one statement per batch, in the order of the
+/// archive. Optional foreign keys referencing a model which comes later,
+/// or the model itself, are set once every record is inserted. The archive
+/// must be dumped with the same schema unless `--force` is given.
+pub(crate) async fn restore(ctx: &Ctx, command: &RestoreCommand) -> Result<()> {
+    let mut reader = ArchiveReader::open(&command.input)?;
+    let namespace = ctx.conn_ctx()?.namespace();
+    let checksum = schema_checksum(&order_by_foreign_keys(models_under_connectors(ctx)?)?);
+    if reader.header.checksum != checksum && !command.force {
+        Err(Error::new(format!("{} is dumped with a different schema, pass --force to restore it anyway", command.input)))?
+    }
+    let mut positions: HashMap<String, usize> = HashMap::new();
+    for (index, model_path) in reader.header.models.iter().enumerate() {
+        positions.insert(model_path.clone(), index);
+    }
+    let mut inserters: HashMap<Vec<String>, Inserter> = HashMap::new();
+    for (namespace_path, namespace) in connector_namespaces(ctx)? {
+        inserters.insert(namespace_path, Inserter::new(namespace).await?);
+    }
+    let batch_size = command.batch_size.max(1);
+    let mut deferred = vec![];
+    let mut batch = vec![];
+    let mut current: Option<Restoring> = None;
+    let mut total = 0;
+    while let Some((model_path, record)) = reader.next_record()? {
+        if current.as_ref().map_or(true, |restoring| restoring.model.path.join(".") != model_path) {
+            if let Some(restoring) = current.take() {
+                restoring.insert(std::mem::take(&mut batch), &mut deferred).await?;
+                restoring.inserter.reset_sequences(restoring.model).await?;
+            }
+            let Some(model) = namespace.model_at_path(&model_path.split('.').collect()) else {
+                Err(Error::new(format!("model {} of the archive is not found", model_path)))?
+            };
+            let Some(inserter) = inserters.iter().filter(|(path, _)| model.path.starts_with(path)).max_by_key(|(path, _)| path.len()).map(|(_, inserter)| inserter) else {
+                Err(Error::new(format!("model {} is not under a connector", model_path)))?
+            };
+            current = Some(Restoring { model, inserter, deferred_fields: deferred_foreign_keys(model, &positions) });
+        }
+        batch.push(decode_record(current.as_ref().unwrap().model, &model_path, record)?);
+        total += 1;
+        if batch.len() >= batch_size {
+            current.as_ref().unwrap().insert(std::mem::take(&mut batch), &mut deferred).await?;
+        }
+    }
+    if let Some(restoring) = current.take() {
+        restoring.insert(batch, &mut deferred).await?;
+        restoring.inserter.reset_sequences(restoring.model).await?;
+    }
+    let transaction_ctx = transaction::Ctx::new(ctx.conn_ctx()?.clone());
+    for (model, identifier, values) in deferred {
+        let Some(object) = transaction_ctx.find_unique(model, &teon!({ "where": identifier }), None, path![]).await? else {
+            Err(Error::new(format!("restored record of {} is not found", model.path.join("."))))?
+        };
+        for (key, value) in values {
+            object.set_value(&key, value)?;
+        }
+        object.save_for_seed_without_required_relation().await?;
+    }
+    if !ctx.cli().silent {
+        info_message(format!("restored {} record(s) of {} model(s) from {}", total, reader.header.models.len(), command.input));
+    }
+    Ok(())
+}
+
+/// The model whose records are being restored.
+struct Restoring<'a> {
+    model: &'static Model,
+    inserter: &'a Inserter,
+    deferred_fields: Vec<String>,
+}
+
+impl Restoring<'_> {
+
+    /// Insert `rows` without their deferred foreign keys, which are kept in
+    /// `deferred` along with the primary key of their record.
+    async fn insert(&self, rows: Vec<Row>, deferred: &mut Vec<(&'static Model, Value, Row)>) -> Result<()> {
+        if rows.is_empty() {
+            return Ok(());
+        }
+        let keys = self.model.primary_index().map(|index| index.keys()).unwrap_or_default();
+        let mut inserted = vec![];
+        for row in rows {
+            let (now, later): (Row, Row) = row.into_iter().partition(|(key, _)| !self.deferred_fields.contains(key));
+            let later: Row = later.into_iter().filter(|(_, value)| !value.is_null()).collect();
+            if !later.is_empty() {
+                let identifier = now.iter().filter(|(key, _)| keys.contains(key)).map(|(key, value)| (key.clone(), value.clone())).collect();
+                deferred.push((self.model, Value::Dictionary(identifier), later));
+            }
+            inserted.push(now);
+        }
+        self.inserter.insert(self.model, &inserted).await
+    }
+}
+
+/// Namespaces with a connector, by their paths.
+fn connector_namespaces(ctx: &Ctx) -> Result<Vec<(Vec<String>, &'static Namespace)>> {
+    let conn_ctx = ctx.conn_ctx()?;
+    let namespace = ctx.conn_ctx()?.namespace();
+    let mut result = vec![];
+    for (namespace_path, _) in conn_ctx.connections_iter() {
+        result.push((namespace_path.clone(), namespace.namespace_at_path(&namespace_path.iter().map(AsRef::as_ref).collect()).unwrap()));
+    }
+    Ok(result)
+}
+
+/// Every model under a connector of a connected namespace.
+fn models_under_connectors(ctx: &Ctx) -> Result<Vec<&'static Model>> {
+    Ok(connector_namespaces(ctx)?.into_iter().flat_map(|(_, namespace)| namespace.models_under_connector()).collect())
+}
+
+/// Models ordered so that those referenced by required foreign keys come
+/// first.
+fn order_by_foreign_keys(models: Vec<&'static Model>) -> Result<Vec<&'static Model>> {
+    let mut deps: IndexMap<String, Vec<String>> = IndexMap::new();
+    for model in &models {
+        let model_path = model.path.join(".");
+        let model_deps = model.relations().into_iter()
+            .filter(|relation| relation.has_foreign_key && relation.is_required())
+            .map(|relation| relation.model_path().join("."))
+            .filter(|path| *path != model_path && models.iter().any(|m| m.path.join(".") == *path))
+            .collect();
+        deps.insert(model_path, model_deps);
+    }
+    let mut result: Vec<&'static Model> = vec![];
+    while !deps.is_empty() {
+        let ready: Vec<String> = deps.iter().filter(|(_, model_deps)| model_deps.is_empty()).map(|(path, _)| path.clone()).collect();
+        if ready.is_empty() {
+            Err(Error::new(format!("circular required relationship between these models: {}", deps.keys().join(", "))))?
+        }
+        for path in &ready {
+            deps.shift_remove(path);
+            result.push(*models.iter().find(|m| m.path.join(".") == *path).unwrap());
+        }
+        for model_deps in deps.values_mut() {
+            model_deps.retain(|path| !ready.contains(path));
+        }
+    }
+    Ok(result)
+}
+
+/// Fields of optional foreign keys referencing `model` itself or a model
+/// restored after it.
+fn deferred_foreign_keys(model: &Model, positions: &HashMap<String, usize>) -> Vec<String> {
+    let position = positions.get(&model.path.join(".")).cloned().unwrap_or(usize::MAX);
+    model.relations().into_iter()
+        .filter(|relation| relation.has_foreign_key && !relation.is_required())
+        .filter(|relation| positions.get(&relation.model_path().join(".")).map_or(true, |p| *p >= position))
+        .flat_map(|relation| relation.iter().map(|(field, _)| field.to_owned()).collect::<Vec<String>>())
+        .collect()
+}
+
+fn decode_record(model: &Model, model_path: &str, record: JsonValue) -> Result<Row> {
+    let mut values = vec![];
+    for (key, json) in record.as_object().unwrap() {
+        let Some(field) = model.field(key) else {
+            Err(Error::new(format!("{} has no field {}", model_path, key)))?
+        };
+        match decode(json, field.r#type()) {
+            Ok(value) => values.push((key.clone(), value)),
+            Err(err) => Err(Error::new(format!("{}.{}: {}", model_path, key, err)))?,
+        }
+    }
+    Ok(values)
+}
+
+/// A digest of the models, fields and field types, so that an archive is
+/// not restored into a different schema by accident.
+fn schema_checksum(models: &Vec<&'static Model>) -> String {
+    let mut description = String::new();
+    for model in models.iter().sorted_by_key(|model| model.path.join(".")) {
+        description.push_str(&model.path.join("."));
+        description.push('\n');
+        for field in model.fields.values().filter(|field| !field.r#virtual).sorted_by_key(|field| field.name().to_owned()) {
+            description.push_str(&format!("  {} {} {}\n", field.name(), field.column_name, field.r#type()));
+        }
+    }
+    digest::digest(&digest::SHA256, description.as_bytes()).as_ref().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use key_path::path;
+    use teo_runtime::connection::transaction;
+    use teo_teon::teon;
+    use teo_teon::value::Value;
+    use uuid::Uuid;
+    use crate::app::ctx::Ctx;
+    use crate::cli::command::{DumpCommand, RestoreCommand};
+    use crate::purge::purge;
+    use crate::test::schema::app_for_schema_source;
+    use super::{dump, restore};
+
+    const SCHEMA: &str = "connector {\n  provider .sqlite\n  url \"sqlite::memory:\"\n}\n\nserver {\n  bind (\"0.0.0.0\", 4000)\n}\n\nenum Genre {\n  jazz\n  rock\n}\n\nmodel Artist {\n  @id @autoIncrement\n  id: Int\n  name: String\n  genre: Genre\n  @relation(fields: .id, references: .artistId)\n  songs: Song[]\n}\n\nmodel Song {\n  @id @autoIncrement\n  id: Int\n  name: String\n  @foreignKey\n  artistId: Int\n  @relation(fields: .artistId, references: .id)\n  artist: Artist\n}\n";
+
+    async fn names(ctx: &Ctx, model: &str) -> Vec<String> {
+        let namespace = ctx.conn_ctx().unwrap().namespace();
+        let model = namespace.model_at_path(&vec![model]).unwrap();
+        let transaction_ctx = transaction::Ctx::new(ctx.conn_ctx().unwrap().clone());
+        let objects = transaction_ctx.find_many(model, &teon!({ "orderBy": { "id": "asc" } }), None, path![]).await.unwrap();
+        objects.iter().map(|object| match object.get_value("name").unwrap() {
+            Value::String(name) => name,
+            _ => panic!("name is not a string"),
+        }).collect()
+    }
+
+    async fn round_trip(format: &str) {
+        let client = app_for_schema_source(SCHEMA).test_client_with_memory_database().await.unwrap();
+        let ctx = client.ctx();
+        let namespace = ctx.conn_ctx().unwrap().namespace();
+        let transaction_ctx = transaction::Ctx::new(ctx.conn_ctx().unwrap().clone());
+        let artist = namespace.model_at_path(&vec!["Artist"]).unwrap();
+        let song = namespace.model_at_path(&vec!["Song"]).unwrap();
+        for (index, name) in ["Ada", "Bea", "Cy"].iter().enumerate() {
+            let object = transaction_ctx.create_object(artist, &teon!({ "name": *name, "genre": "jazz" }), None).await.unwrap();
+            object.save().await.unwrap();
+            let object = transaction_ctx.create_object(song, &teon!({ "name": format!("{} {}", name, index), "artistId": object.get_value("id").unwrap() }), None).await.unwrap();
+            object.save().await.unwrap();
+        }
+        let archive = std::env::temp_dir().join(format!("teo-dump-{}.gz", Uuid::new_v4())).to_str().unwrap().to_owned();
+        // one record per batch pages through every record
+        dump(ctx, &DumpCommand { output: archive.clone(), format: format.to_owned(), batch_size: 1 }).await.unwrap();
+        purge(ctx).await.unwrap();
+        assert!(names(ctx, "Artist").await.is_empty());
+        restore(ctx, &RestoreCommand { input: archive, batch_size: 2, force: false }).await.unwrap();
+        assert_eq!(names(ctx, "Artist").await, vec!["Ada", "Bea", "Cy"]);
+        assert_eq!(names(ctx, "Song").await, vec!["Ada 0", "Bea 1", "Cy 2"]);
+    }
+
+    #[actix_web::test]
+    async fn ndjson_round_trip() {
+        round_trip("ndjson").await;
+    }
+
+    #[actix_web::test]
+    async fn teon_round_trip() {
+        round_trip("teon").await;
+    }
+}
@@ -0,0 +1,120 @@
+use chrono::SecondsFormat;
+use serde_json::Value as JsonValue;
+use teo_parser::r#type::Type;
+use teo_result::{Error, Result};
+use teo_teon::value::Value;
+
+/// Write a field value in Teon notation.
+///
+/// Enum variants are written as `.name`. Values which have no literal in
+/// Teon, like dates, decimals and object ids, are written as strings and
+/// read back with the type of their field.
+pub(crate) fn write_value(value: &Value, r#type: &Type, out: &mut String) -> Result<()> {
+    match (value, r#type) {
+        (_, Type::Optional(inner)) => write_value(value, inner, out)?,
+        (Value::Null, _) => out.push_str("null"),
+        (Value::Bool(b), _) => out.push_str(if *b { "true" } else { "false" }),
+        (Value::Int(i), _) => out.push_str(&i.to_string()),
+        (Value::Int64(i), _) => out.push_str(&i.to_string()),
+        (Value::Float32(f), _) => write_float(*f as f64, out)?,
+        (Value::Float(f), _) => write_float(*f, out)?,
+        (Value::Decimal(d), _) => write_string(&d.to_string(), out),
+        (Value::ObjectId(id), _) => write_string(&id.to_hex(), out),
+        (Value::Date(date), _) => write_string(&date.format("%Y-%m-%d").to_string(), out),
+        (Value::DateTime(datetime), _) => write_string(&datetime.to_rfc3339_opts(SecondsFormat::Millis, true), out),
+        (Value::EnumVariant(variant), _) => write_variant(&variant.value, out),
+        (Value::String(s), Type::EnumVariant(..)) => write_variant(s, out),
+        (Value::String(s), _) => write_string(s, out),
+        (Value::Array(items), _) => {
+            let item_type = match r#type {
+                Type::Array(inner) => inner.as_ref(),
+                _ => &Type::Any,
+            };
+            out.push('[');
+            for (index, item) in items.iter().enumerate() {
+                if index > 0 {
+                    out.push_str(", ");
+                }
+                write_value(item, item_type, out)?;
+            }
+            out.push(']');
+        }
+        _ => Err(Error::new(format!("cannot write {:?} in Teon", value)))?,
+    }
+    Ok(())
+}
+
+/// Write a record as a Teon dictionary of its fields.
+pub(crate) fn write_record<'a>(fields: impl IntoIterator<Item = (&'a str, &'a Type, &'a Value)>, out: &mut String) -> Result<()> {
+    out.push('{');
+    for (index, (name, r#type, value)) in fields.into_iter().enumerate() {
+        out.push_str(if index > 0 { ", " } else { " " });
+        write_string(name, out);
+        out.push_str(": ");
+        write_value(value, r#type, out)?;
+    }
+    out.push_str(if out.ends_with('{') { "}" } else { " }" });
+    Ok(())
+}
+
+fn write_string(s: &str, out: &mut String) {
+    out.push_str(&JsonValue::String(s.to_owned()).to_string());
+}
+
+fn write_variant(name: &str, out: &mut String) {
+    out.push('.');
+    out.push_str(name);
+}
+
+fn write_float(f: f64, out: &mut String) -> Result<()> {
+    if !f.is_finite() {
+        Err(Error::new(format!("cannot write {} in Teon", f)))?
+    }
+    // keep the decimal point, so that the number is read back as a float
+    out.push_str(&format!("{:?}", f));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+    use teo_parser::r#type::Type;
+    use teo_teon::value::Value;
+    use super::{write_record, write_value};
+
+    fn write(value: &Value, r#type: &Type) -> String {
+        let mut out = String::new();
+        write_value(value, r#type, &mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn writes_scalars() {
+        assert_eq!(write(&Value::Int(3), &Type::Int), "3");
+        assert_eq!(write(&Value::Float(2.0), &Type::Float), "2.0");
+        assert_eq!(write(&Value::String("a \"b\"\n".to_owned()), &Type::String), "\"a \\\"b\\\"\\n\"");
+        assert_eq!(write(&Value::Null, &Type::Optional(Box::new(Type::String))), "null");
+        assert_eq!(write(&Value::Date(NaiveDate::from_ymd_opt(2024, 2, 29).unwrap()), &Type::Date), "\"2024-02-29\"");
+    }
+
+    #[test]
+    fn writes_arrays() {
+        let value = Value::Array(vec![Value::Int(1), Value::Int(2)]);
+        assert_eq!(write(&value, &Type::Array(Box::new(Type::Int))), "[1, 2]");
+    }
+
+    #[test]
+    fn rejects_non_finite_floats() {
+        assert!(write_value(&Value::Float(f64::NAN), &Type::Float, &mut String::new()).is_err());
+    }
+
+    #[test]
+    fn writes_records() {
+        let mut out = String::new();
+        write_record([("id", &Type::Int, &Value::Int(1)), ("name", &Type::String, &Value::String("Ada".to_owned()))], &mut out).unwrap();
+        assert_eq!(out, "{ \"id\": 1, \"name\": \"Ada\" }");
+        let mut out = String::new();
+        write_record([] as [(&str, &Type, &Value); 0], &mut out).unwrap();
+        assert_eq!(out, "{}");
+    }
+}
@@ -0,0 +1,42 @@
+use std::str::FromStr;
+use bigdecimal::BigDecimal;
+use bson::oid::ObjectId;
+use chrono::{DateTime, NaiveDate, Utc};
+use serde_json::Value as JsonValue;
+use teo_parser::r#type::Type;
+use teo_result::{Error, Result};
+use teo_teon::value::Value;
+
+/// A field value of a dumped record, read back with the field's type.
+pub(crate) fn decode(json: &JsonValue, r#type: &Type) -> Result<Value> {
+    if json.is_null() {
+        return Ok(Value::Null);
+    }
+    let value = match (r#type, json) {
+        (Type::Optional(inner), _) => return decode(json, inner),
+        (Type::Array(inner), JsonValue::Array(items)) => {
+            let mut values = vec![];
+            for item in items {
+                values.push(decode(item, inner)?);
+            }
+            Some(Value::Array(values))
+        }
+        (Type::Bool, JsonValue::Bool(b)) => Some(Value::Bool(*b)),
+        (Type::Int, JsonValue::Number(n)) => n.as_i64().and_then(|i| i32::try_from(i).ok()).map(Value::Int),
+        (Type::Int64, JsonValue::Number(n)) => n.as_i64().map(Value::Int64),
+        (Type::Float32, JsonValue::Number(n)) => n.as_f64().map(|f| Value::Float32(f as f32)),
+        (Type::Float, JsonValue::Number(n)) => n.as_f64().map(Value::Float),
+        (Type::Decimal, JsonValue::String(s)) => BigDecimal::from_str(s).ok().map(Value::Decimal),
+        (Type::Decimal, JsonValue::Number(n)) => BigDecimal::from_str(&n.to_string()).ok().map(Value::Decimal),
+        (Type::String, JsonValue::String(s)) => Some(Value::String(s.clone())),
+        (Type::EnumVariant(..), JsonValue::String(s)) => Some(Value::String(s.clone())),
+        (Type::ObjectId, JsonValue::String(s)) => ObjectId::parse_str(s).ok().map(Value::ObjectId),
+        (Type::Date, JsonValue::String(s)) => NaiveDate::parse_from_str(s, "%Y-%m-%d").ok().map(Value::Date),
+        (Type::DateTime, JsonValue::String(s)) => DateTime::parse_from_rfc3339(s).ok().map(|d| Value::DateTime(d.with_timezone(&Utc))),
+        _ => None,
+    };
+    match value {
+        Some(value) => Ok(value),
+        None => Err(Error::new(format!("{} is not a valid {}", json, r#type))),
+    }
+}
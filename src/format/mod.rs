@@ -0,0 +1,51 @@
+pub(crate) mod printer;
+
+use std::fs;
+use teo_result::{Error, Result};
+use crate::app::ctx::Ctx;
+use crate::cli::command::FormatCommand;
+use crate::cli::outcome::Outcome;
+use crate::format::printer::format_source;
+use crate::message::{error_message, info_message, warning_message};
+
+/// Format the schema sources in place. With `--check`, files are left
+/// untouched, unformatted files are listed and the outcome is a failure if
+/// there are any.
+///
+/// Sources are printed from the parsed schema, so only valid schemas are
+/// formatted.
+pub(crate) fn format(ctx: &Ctx, command: &FormatCommand) -> Result<Outcome> {
+    let mut unformatted = vec![];
+    for source in ctx.schema().sources.values() {
+        if source.builtin {
+            continue;
+        }
+        let content = match fs::read_to_string(&source.file_path) {
+            Ok(content) => content,
+            Err(err) => Err(Error::new(format!("cannot read {}: {}", source.file_path, err)))?,
+        };
+        let formatted = format_source(source, &content);
+        if formatted == content {
+            continue;
+        }
+        if command.check {
+            unformatted.push(source.file_path.clone());
+        } else {
+            if let Err(err) = fs::write(&source.file_path, formatted) {
+                Err(Error::new(format!("cannot write {}: {}", source.file_path, err)))?
+            }
+            if !ctx.cli().silent {
+                info_message(format!("formatted {}", source.file_path));
+            }
+        }
+    }
+    if !unformatted.is_empty() {
+        for file_path in &unformatted {
+            warning_message(format!("{} is not formatted", file_path));
+        }
+        let message = format!("{} schema file(s) are not formatted, run `teo format` to format them", unformatted.len());
+        error_message(&message);
+        return Ok(Outcome::Failure(message));
+    }
+    Ok(Outcome::Success)
+}
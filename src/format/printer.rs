@@ -0,0 +1,263 @@
+use teo_parser::ast::node::Node;
+use teo_parser::ast::source::Source;
+use teo_parser::traits::node_trait::NodeTrait;
+use teo_parser::traits::write::Write;
+
+const INDENT: &str = "  ";
+
+/// Decorators are ordered by this rank, unlisted decorators keep their
+/// relative order between the listed ones and the pipeline hooks.
+static DECORATOR_ORDER: &[&str] = &[
+    "id",
+    "auto",
+    "autoIncrement",
+    "map",
+    "unique",
+    "index",
+    "default",
+    "foreignKey",
+    "relation",
+    "readonly",
+    "writeonly",
+    "internal",
+];
+
+static PIPELINE_DECORATORS: &[&str] = &["onSet", "onSave", "onOutput"];
+
+/// Print a parsed schema source canonically.
+///
+/// Every member of a block is put on a line of its own, indented by two
+/// spaces per nesting level. The decorators of a declaration are put on one
+/// line above it in a canonical order, and runs of blank lines between
+/// members are collapsed into one. Comments are kept where they are, and
+/// expressions are printed by the parser's writer, so literals are kept as
+/// written. Lines end like the first line of `content`.
+pub(crate) fn format_source(source: &Source, content: &str) -> String {
+    let mut printer = Printer { lines: vec![], depth: 0 };
+    // nodes after the last line are injected by `parse_schema`
+    let lines = content.lines().count();
+    printer.members(source.children.values().filter(|node| node.span().start_position.0 <= lines).collect());
+    if printer.lines.is_empty() {
+        return String::new();
+    }
+    let newline = if content.split('\n').next().map_or(false, |line| line.ends_with('\r')) { "\r\n" } else { "\n" };
+    printer.lines.join(newline) + newline
+}
+
+struct Printer {
+    lines: Vec<String>,
+    depth: usize,
+}
+
+impl Printer {
+
+    fn push_line(&mut self, line: &str) {
+        self.lines.push(format!("{}{}", INDENT.repeat(self.depth), line.trim_end()));
+    }
+
+    /// Print the members of a block, or the declarations of a source, one
+    /// per line.
+    fn members(&mut self, nodes: Vec<&Node>) {
+        let mut previous_end: Option<usize> = None;
+        for node in nodes {
+            let start = node.span().start_position.0;
+            if is_punctuation(node, ",") {
+                if let Some(last) = self.lines.last_mut() {
+                    last.push(',');
+                }
+                continue;
+            }
+            if is_comment(node) && previous_end == Some(start) {
+                // a trailing comment stays on the line of the member
+                if let Some(last) = self.lines.last_mut() {
+                    last.push(' ');
+                    last.push_str(text(node).trim_end());
+                }
+                continue;
+            }
+            if previous_end.map_or(false, |end| start > end + 1) {
+                self.lines.push(String::new());
+            }
+            self.member(node);
+            previous_end = Some(node.span().end_position.0);
+        }
+    }
+
+    /// Print a declaration: its comments and sorted decorators on lines of
+    /// their own, then its header, then its block if it has one.
+    fn member(&mut self, node: &Node) {
+        let Some(children) = node.children() else {
+            self.push_line(&text(node));
+            return;
+        };
+        let mut decorators: Vec<String> = vec![];
+        let mut line = String::new();
+        let mut children = children.values().peekable();
+        while let Some(child) = children.next() {
+            if line.is_empty() && is_comment(child) {
+                self.flush_decorators(&mut decorators);
+                self.push_line(&text(child));
+            } else if line.is_empty() && matches!(child, Node::Decorator(_)) {
+                decorators.push(text(child));
+            } else if is_punctuation(child, "{") {
+                self.flush_decorators(&mut decorators);
+                let mut members = vec![];
+                for member in children.by_ref() {
+                    if is_punctuation(member, "}") {
+                        break;
+                    }
+                    members.push(member);
+                }
+                self.block(&line, members);
+                line.clear();
+            } else if is_block(child) {
+                self.flush_decorators(&mut decorators);
+                self.inline_block(&line, child);
+                line.clear();
+            } else {
+                join(&mut line, &text(child));
+            }
+        }
+        self.flush_decorators(&mut decorators);
+        if !line.is_empty() {
+            self.push_line(&line);
+        }
+    }
+
+    /// A block opened on the line of `header`. Empty blocks are kept on one
+    /// line.
+    fn block(&mut self, header: &str, members: Vec<&Node>) {
+        let opener = if header.is_empty() { "{".to_owned() } else { format!("{} {{", header) };
+        if members.is_empty() {
+            self.push_line(&format!("{}}}", opener));
+            return;
+        }
+        self.push_line(&opener);
+        self.depth += 1;
+        self.members(members);
+        self.depth -= 1;
+        self.push_line("}");
+    }
+
+    /// A child with a block of its own, like the dictionary of a config or a
+    /// data set record.
+    fn inline_block(&mut self, header: &str, node: &Node) {
+        let mut header = header.to_owned();
+        let mut members = vec![];
+        let mut in_block = false;
+        for child in node.children().into_iter().flat_map(|children| children.values()) {
+            if !in_block {
+                if is_punctuation(child, "{") {
+                    in_block = true;
+                } else {
+                    join(&mut header, &text(child));
+                }
+            } else if !is_punctuation(child, "}") {
+                members.push(child);
+            }
+        }
+        self.block(&header, members);
+    }
+
+    fn flush_decorators(&mut self, decorators: &mut Vec<String>) {
+        if decorators.is_empty() {
+            return;
+        }
+        decorators.sort_by_key(|decorator| decorator_rank(decorator));
+        let line = decorators.join(" ");
+        self.push_line(&line);
+        decorators.clear();
+    }
+}
+
+/// Append a token to a line, separated by a space unless punctuation
+/// attaches it to its neighbor.
+fn join(line: &mut String, token: &str) {
+    let token = token.trim();
+    let attaches_left = token.starts_with([',', ':', ')', ']', '?', '!', '.', '(', '[', '>']);
+    let attaches_right = line.ends_with(['(', '[', '.', '@', '<']);
+    if !line.is_empty() && !attaches_left && !attaches_right {
+        line.push(' ');
+    }
+    line.push_str(token);
+}
+
+fn text(node: &Node) -> String {
+    node.write_output_with_default_writer().trim_end_matches(['\r', '\n']).to_owned()
+}
+
+fn is_comment(node: &Node) -> bool {
+    matches!(node, Node::CodeComment(_) | Node::DocComment(_))
+}
+
+fn is_punctuation(node: &Node, content: &str) -> bool {
+    matches!(node, Node::Punctuation(punctuation) if punctuation.content() == content)
+}
+
+/// Whether the node has a `{ ... }` block of its own.
+fn is_block(node: &Node) -> bool {
+    !matches!(node, Node::Decorator(_)) && node.children().map_or(false, |children| children.values().any(|child| is_punctuation(child, "{")))
+}
+
+fn decorator_rank(decorator: &str) -> usize {
+    let name = decorator.trim_start_matches('@').split('(').next().unwrap_or("");
+    if let Some(index) = DECORATOR_ORDER.iter().position(|n| *n == name) {
+        index
+    } else if let Some(index) = PIPELINE_DECORATORS.iter().position(|n| *n == name) {
+        DECORATOR_ORDER.len() + 1 + index
+    } else {
+        DECORATOR_ORDER.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test::schema::parse_schema_source;
+    use super::format_source;
+
+    fn format(content: &str) -> String {
+        let schema = parse_schema_source(content);
+        let source = schema.sources.values().find(|source| !source.builtin).unwrap();
+        format_source(source, content)
+    }
+
+    /// Formatting is stable: the output parses and formats to itself.
+    fn assert_round_trip(content: &str) -> String {
+        let formatted = format(content);
+        assert_eq!(format(&formatted), formatted);
+        formatted
+    }
+
+    #[test]
+    fn indents_and_orders_decorators() {
+        let content = "connector {\nprovider .sqlite\n      url \"sqlite::memory:\"\n}\n\n\n\nmodel User {\n    @readonly @autoIncrement @id\n  id: Int\n}\n";
+        assert_eq!(assert_round_trip(content), "connector {\n  provider .sqlite\n  url \"sqlite::memory:\"\n}\n\nmodel User {\n  @id @autoIncrement @readonly\n  id: Int\n}\n");
+    }
+
+    #[test]
+    fn keeps_comments() {
+        let content = "// connection\nconnector {\n  provider .sqlite // for tests\n  url \"sqlite::memory:\"\n}\n\n/// A user.\nmodel User {\n  /* the key */\n  @id @autoIncrement\n  id: Int\n}\n";
+        assert_eq!(assert_round_trip(content), content);
+    }
+
+    #[test]
+    fn keeps_regex_literals() {
+        let content = "connector {\n  provider .sqlite\n  url \"sqlite::memory:\"\n}\n\nmodel User {\n  @id @autoIncrement\n  id: Int\n  @onSet($regexMatch(/^[a-z]+\\/\\d{2,}$/))\n  name: String\n}\n";
+        let formatted = assert_round_trip(content);
+        assert!(formatted.contains("/^[a-z]+\\/\\d{2,}$/"), "{}", formatted);
+    }
+
+    #[test]
+    fn keeps_crlf_line_endings() {
+        let content = "connector {\r\n  provider .sqlite\r\n  url \"sqlite::memory:\"\r\n}\r\n\r\nmodel User {\r\n  @autoIncrement @id\r\n  id: Int\r\n}\r\n";
+        let formatted = assert_round_trip(content);
+        assert_eq!(formatted, content.replace("@autoIncrement @id", "@id @autoIncrement"));
+        assert!(!formatted.replace("\r\n", "").contains('\r'));
+    }
+
+    #[test]
+    fn formatted_source_is_unchanged() {
+        let content = "connector {\n  provider .sqlite\n  url \"sqlite::memory:\"\n}\n\nenum Role {\n  admin\n  user\n}\n\nmodel User {\n  @id @autoIncrement\n  id: Int\n  @default(.user)\n  role: Role\n}\n";
+        assert_eq!(format(content), content);
+    }
+}
@@ -0,0 +1,288 @@
+use std::collections::BTreeMap;
+use crate::introspect::schema::{Column, ColumnType, DatabaseSchema, Table};
+
+/// A model to be printed, built from a table or a collection.
+#[derive(Debug)]
+pub(crate) struct ModelDef {
+    pub(crate) table: String,
+    pub(crate) name: String,
+    pub(crate) decorators: Vec<String>,
+    pub(crate) fields: Vec<FieldDef>,
+}
+
+#[derive(Debug)]
+pub(crate) struct FieldDef {
+    pub(crate) name: String,
+    /// The mapped column, relations don't have one.
+    pub(crate) column: Option<String>,
+    pub(crate) decorators: Vec<String>,
+    pub(crate) r#type: String,
+    /// Set for columns which cannot be represented, the field is printed as
+    /// a comment then.
+    pub(crate) unsupported: Option<String>,
+}
+
+#[derive(Debug)]
+pub(crate) struct EnumDef {
+    pub(crate) name: String,
+    pub(crate) members: Vec<String>,
+}
+
+#[derive(Debug)]
+pub(crate) struct Generated {
+    pub(crate) models: Vec<ModelDef>,
+    pub(crate) enums: Vec<EnumDef>,
+}
+
+pub(crate) fn generate(schema: &DatabaseSchema) -> Generated {
+    let model_names: BTreeMap<&str, String> = schema.tables.iter().map(|t| (t.name.as_str(), pascal_case(&singular(&t.name)))).collect();
+    let mut models: Vec<ModelDef> = schema.tables.iter().map(|table| model_for_table(schema, table, &model_names)).collect();
+    // relation fields owning foreign keys, by table index and foreign key index
+    let mut owner_fields: BTreeMap<(usize, usize), String> = BTreeMap::new();
+    for (table_index, table) in schema.tables.iter().enumerate() {
+        for (fk_index, foreign_key) in table.foreign_keys.iter().enumerate() {
+            let Some(target_index) = schema.tables.iter().position(|t| t.name == foreign_key.referenced_table) else { continue };
+            let referenced_model = &model_names[foreign_key.referenced_table.as_str()];
+            let base = match foreign_key.columns.as_slice() {
+                [column] => strip_id_suffix(column).map(camel_case).unwrap_or_else(|| camel_case(referenced_model)),
+                _ => camel_case(referenced_model),
+            };
+            let name = unique_field_name(&models[table_index], base);
+            let optional = foreign_key.columns.iter().any(|c| table.column(c).map_or(false, |c| c.optional));
+            let fields = enum_variant_list(&foreign_key.columns.iter().map(|c| field_name_of_column(&models[table_index], c)).collect());
+            let references = enum_variant_list(&foreign_key.referenced_columns.iter().map(|c| field_name_of_column(&models[target_index], c)).collect());
+            models[table_index].fields.push(FieldDef {
+                name: name.clone(),
+                column: None,
+                decorators: vec![format!("@relation(fields: {}, references: {})", fields, references)],
+                r#type: format!("{}{}", referenced_model, if optional { "?" } else { "" }),
+                unsupported: None,
+            });
+            owner_fields.insert((table_index, fk_index), name);
+        }
+    }
+    for (table_index, table) in schema.tables.iter().enumerate() {
+        let model_name = model_names[table.name.as_str()].clone();
+        if table.is_join_table() {
+            for (local, foreign) in [(0, 1), (1, 0)] {
+                let local_fk = &table.foreign_keys[local];
+                let foreign_fk = &table.foreign_keys[foreign];
+                let (Some(local_field), Some(foreign_field)) = (owner_fields.get(&(table_index, local)), owner_fields.get(&(table_index, foreign))) else { continue };
+                let Some(target_index) = schema.tables.iter().position(|t| t.name == local_fk.referenced_table) else { continue };
+                let other_model = &model_names[foreign_fk.referenced_table.as_str()];
+                let name = unique_field_name(&models[target_index], plural(&camel_case(other_model)));
+                models[target_index].fields.push(FieldDef {
+                    name,
+                    column: None,
+                    decorators: vec![format!("@relation(through: {}, local: .{}, foreign: .{})", model_name, local_field, foreign_field)],
+                    r#type: format!("{}[]", other_model),
+                    unsupported: None,
+                });
+            }
+            continue;
+        }
+        for foreign_key in table.foreign_keys.iter() {
+            let Some(target_index) = schema.tables.iter().position(|t| t.name == foreign_key.referenced_table) else { continue };
+            let one = table.is_unique(&foreign_key.columns);
+            let base = if one { camel_case(&model_name) } else { plural(&camel_case(&model_name)) };
+            let name = unique_field_name(&models[target_index], base);
+            let fields = enum_variant_list(&foreign_key.referenced_columns.iter().map(|c| field_name_of_column(&models[target_index], c)).collect());
+            let references = enum_variant_list(&foreign_key.columns.iter().map(|c| field_name_of_column(&models[table_index], c)).collect());
+            models[target_index].fields.push(FieldDef {
+                name,
+                column: None,
+                decorators: vec![format!("@relation(fields: {}, references: {})", fields, references)],
+                r#type: format!("{}{}", model_name, if one { "?" } else { "[]" }),
+                unsupported: None,
+            });
+        }
+    }
+    let enums = schema.enums.iter().map(|e| EnumDef { name: pascal_case(&e.name), members: e.members.clone() }).collect();
+    Generated { models, enums }
+}
+
+fn model_for_table(schema: &DatabaseSchema, table: &Table, model_names: &BTreeMap<&str, String>) -> ModelDef {
+    let name = model_names[table.name.as_str()].clone();
+    let mut decorators = vec![];
+    if name != table.name {
+        decorators.push(format!("@map({})", quoted(&table.name)));
+    }
+    let fields: Vec<FieldDef> = table.columns.iter().map(|column| field_for_column(schema, table, column)).collect();
+    let mut model = ModelDef { table: table.name.clone(), name, decorators, fields };
+    if table.primary_key.len() > 1 {
+        let columns = enum_variant_list(&table.primary_key.iter().map(|c| field_name_of_column(&model, c)).collect());
+        model.decorators.push(format!("@@id({})", columns));
+    }
+    for index in table.indexes.iter().filter(|i| i.columns.len() > 1) {
+        let columns = enum_variant_list(&index.columns.iter().map(|c| field_name_of_column(&model, c)).collect());
+        model.decorators.push(format!("@@{}({})", if index.unique { "unique" } else { "index" }, columns));
+    }
+    model
+}
+
+fn field_for_column(schema: &DatabaseSchema, table: &Table, column: &Column) -> FieldDef {
+    let name = camel_case(&column.name);
+    let r#type = match &column.r#type {
+        ColumnType::Teo(name) => name.clone(),
+        ColumnType::Enum(name) => pascal_case(name),
+        ColumnType::Unsupported(type_name) => return FieldDef {
+            name,
+            column: Some(column.name.clone()),
+            decorators: vec![],
+            r#type: "".to_owned(),
+            unsupported: Some(type_name.clone()),
+        },
+    };
+    let mut decorators = vec![];
+    if table.primary_key.len() == 1 && table.primary_key[0] == column.name {
+        decorators.push("@id".to_owned());
+        if schema.document && r#type == "ObjectId" {
+            decorators.push("@auto".to_owned());
+        }
+    }
+    if column.auto_increment {
+        decorators.push("@autoIncrement".to_owned());
+    }
+    if name != column.name {
+        decorators.push(format!("@map({})", quoted(&column.name)));
+    }
+    if decorators.iter().any(|d| d == "@auto" || d == "@autoIncrement") {
+        decorators.push("@readonly".to_owned());
+    }
+    for index in table.indexes.iter().filter(|i| i.columns.len() == 1 && i.columns[0] == column.name) {
+        decorators.push(if index.unique { "@unique" } else { "@index" }.to_owned());
+    }
+    if let Some(default) = &column.default {
+        decorators.push(format!("@default({})", default));
+    }
+    if table.foreign_keys.iter().any(|f| f.columns.contains(&column.name)) {
+        decorators.push("@foreignKey".to_owned());
+    }
+    FieldDef {
+        name,
+        column: Some(column.name.clone()),
+        decorators,
+        r#type: format!("{}{}", r#type, if column.optional { "?" } else { "" }),
+        unsupported: None,
+    }
+}
+
+pub(crate) fn render(generated: &Generated) -> String {
+    let mut blocks: Vec<String> = generated.enums.iter().map(render_enum).collect();
+    blocks.extend(generated.models.iter().map(render_model));
+    blocks.join("\n")
+}
+
+pub(crate) fn render_enum(r#enum: &EnumDef) -> String {
+    let mut lines = vec![format!("enum {} {{", r#enum.name)];
+    for member in &r#enum.members {
+        if is_identifier(member) {
+            lines.push(format!("  {}", member));
+        } else {
+            lines.push(format!("  // member {} is not a valid identifier", quoted(member)));
+        }
+    }
+    lines.push("}".to_owned());
+    lines.join("\n") + "\n"
+}
+
+pub(crate) fn render_model(model: &ModelDef) -> String {
+    let mut lines = vec![];
+    lines.extend(model.decorators.iter().filter(|d| !d.starts_with("@@")).cloned());
+    lines.push(format!("model {} {{", model.name));
+    for decorator in model.decorators.iter().filter(|d| d.starts_with("@@")) {
+        lines.push(format!("  {}", decorator));
+    }
+    for field in &model.fields {
+        lines.extend(render_field(field).into_iter().map(|l| format!("  {}", l)));
+    }
+    lines.push("}".to_owned());
+    lines.join("\n") + "\n"
+}
+
+pub(crate) fn render_field(field: &FieldDef) -> Vec<String> {
+    if let Some(r#type) = &field.unsupported {
+        return vec![format!("// {}: column {} has unsupported type {}", field.name, quoted(field.column.as_ref().unwrap()), r#type)];
+    }
+    let mut lines = vec![];
+    if !field.decorators.is_empty() {
+        lines.push(field.decorators.join(" "));
+    }
+    lines.push(format!("{}: {}", field.name, field.r#type));
+    lines
+}
+
+fn field_name_of_column(model: &ModelDef, column: &str) -> String {
+    model.fields.iter().find(|f| f.column.as_deref() == Some(column)).map(|f| f.name.clone()).unwrap_or_else(|| camel_case(column))
+}
+
+fn unique_field_name(model: &ModelDef, base: String) -> String {
+    let mut name = base.clone();
+    let mut suffix = 2;
+    while model.fields.iter().any(|f| f.name == name) {
+        name = format!("{}{}", base, suffix);
+        suffix += 1;
+    }
+    name
+}
+
+fn enum_variant_list(names: &Vec<String>) -> String {
+    match names.as_slice() {
+        [name] => format!(".{}", name),
+        _ => format!("[{}]", names.iter().map(|n| format!(".{}", n)).collect::<Vec<String>>().join(", ")),
+    }
+}
+
+fn strip_id_suffix(column: &str) -> Option<&str> {
+    ["_id", "Id", "ID", "_ID"].iter()
+        .find_map(|suffix| column.strip_suffix(suffix))
+        .filter(|stripped| !stripped.is_empty())
+}
+
+/// Tables are often named in plural, models are named in singular.
+fn singular(name: &str) -> String {
+    if let Some(stem) = name.strip_suffix("ies") {
+        format!("{}y", stem)
+    } else if name.ends_with('s') && !name.ends_with("ss") && name.len() > 1 {
+        name[..name.len() - 1].to_owned()
+    } else {
+        name.to_owned()
+    }
+}
+
+fn plural(name: &str) -> String {
+    if name.ends_with('s') { name.to_owned() } else { format!("{}s", name) }
+}
+
+fn quoted(s: &str) -> String {
+    serde_json::to_string(s).unwrap()
+}
+
+fn is_identifier(s: &str) -> bool {
+    s.chars().next().map_or(false, |c| c.is_alphabetic() || c == '_') && s.chars().all(|c| c.is_alphanumeric() || c == '_')
+}
+
+pub(crate) fn pascal_case(s: &str) -> String {
+    s.split(|c: char| !c.is_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let mut chars = part.chars();
+            let first = chars.next().unwrap();
+            let rest = chars.as_str();
+            if part.chars().all(|c| !c.is_lowercase()) {
+                first.to_uppercase().collect::<String>() + &rest.to_lowercase()
+            } else {
+                first.to_uppercase().collect::<String>() + rest
+            }
+        })
+        .collect()
+}
+
+pub(crate) fn camel_case(s: &str) -> String {
+    let pascal = pascal_case(s);
+    let mut chars = pascal.chars();
+    match chars.next() {
+        Some(first) => first.to_lowercase().collect::<String>() + chars.as_str(),
+        None => "".to_owned(),
+    }
+}
@@ -0,0 +1,224 @@
+use std::collections::BTreeSet;
+use teo_parser::ast::decorator::Decorator;
+use teo_parser::ast::model::Model;
+use teo_parser::ast::r#enum::Enum;
+use teo_parser::ast::schema::Schema;
+use teo_parser::ast::source::Source;
+use teo_parser::traits::identifiable::Identifiable;
+use teo_parser::traits::named_identifiable::NamedIdentifiable;
+use teo_parser::traits::node_trait::NodeTrait;
+use teo_parser::traits::resolved::Resolve;
+use teo_parser::traits::write::Write;
+use teo_teon::value::Value;
+use crate::introspect::generate::{render_enum, render_field, render_model, Generated};
+use crate::lint::rules::{decorator, location};
+
+/// What merging added to an existing schema.
+#[derive(Debug, Default)]
+pub(crate) struct MergeSummary {
+    pub(crate) models: usize,
+    pub(crate) enums: usize,
+    pub(crate) fields: usize,
+    pub(crate) members: usize,
+    /// Introspected models and enums which were left out, since their names
+    /// are taken by other declarations.
+    pub(crate) collisions: Vec<String>,
+}
+
+/// Merge introspected models and enums into `source`, an existing schema
+/// file whose content is `existing`.
+///
+/// Models and enums are matched by name or by their mapped table. Existing
+/// declarations are kept as written, only missing fields and enum members
+/// are added to them, and missing models and enums are appended. Nothing is
+/// removed, so hand edits survive a re-run. An introspected model whose name
+/// is taken by a declaration of another table is reported in the summary
+/// instead of being appended twice.
+pub(crate) fn merge(schema: &Schema, source: &Source, existing: &str, generated: &Generated) -> (String, MergeSummary) {
+    let models: Vec<&Model> = schema.models().into_iter()
+        .filter(|model| model.source_id() == source.id && model.string_path().len() == 1)
+        .collect();
+    let enums: Vec<&Enum> = schema.enums().into_iter()
+        .filter(|r#enum| r#enum.source_id() == source.id && r#enum.string_path().len() == 1)
+        .collect();
+    let mut summary = MergeSummary::default();
+    let mut insertions: Vec<(usize, String)> = vec![];
+    let mut appended: Vec<String> = vec![];
+    for r#enum in &generated.enums {
+        if let Some(declared) = enums.iter().find(|e| e.name() == r#enum.name) {
+            let existing_members: BTreeSet<&str> = declared.members().map(|member| member.name()).collect();
+            let missing: Vec<&String> = r#enum.members.iter().filter(|m| !existing_members.contains(m.as_str())).collect();
+            if !missing.is_empty() {
+                summary.members += missing.len();
+                insertions.push((closing_line(existing, declared.span().end), missing.iter().map(|m| format!("  {}\n", m)).collect()));
+            }
+        } else if let Some(model) = models.iter().find(|m| m.name() == r#enum.name) {
+            summary.collisions.push(collision(schema, &format!("enum `{}`", r#enum.name), "model", *model));
+        } else {
+            summary.enums += 1;
+            appended.push(render_enum(r#enum));
+        }
+    }
+    for model in &generated.models {
+        let found = models.iter().find(|m| {
+            let map = map_argument(m.decorators());
+            map.as_deref() == Some(model.table.as_str()) || (m.name() == model.name && map.is_none()) || m.name() == model.table
+        });
+        if let Some(declared) = found {
+            let span = declared.span();
+            let body = &existing[span.start..span.end];
+            let existing_fields: BTreeSet<&str> = declared.fields().map(|field| field.name()).collect();
+            let mapped: BTreeSet<String> = declared.fields().filter_map(|field| map_argument(field.decorators())).collect();
+            let relations: BTreeSet<String> = declared.fields()
+                .filter_map(|field| decorator(field.decorators(), "relation"))
+                .map(|relation| normalize(&relation.write_output_with_default_writer()))
+                .collect();
+            let mut lines = String::new();
+            for field in &model.fields {
+                let exists = existing_fields.contains(field.name.as_str())
+                    || field.column.as_ref().map_or(false, |c| mapped.contains(c) || existing_fields.contains(c.as_str()))
+                    || field.decorators.iter().any(|d| relations.contains(&normalize(d)))
+                    || (field.unsupported.is_some() && body.contains(&format!("// {}:", field.name)));
+                if exists {
+                    continue;
+                }
+                summary.fields += 1;
+                for line in render_field(field) {
+                    lines.push_str(&format!("  {}\n", line));
+                }
+            }
+            if !lines.is_empty() {
+                insertions.push((closing_line(existing, span.end), lines));
+            }
+        } else if let Some(declared) = models.iter().find(|m| m.name() == model.name) {
+            summary.collisions.push(collision(schema, &format!("model `{}` of table `{}`", model.name, model.table), "model", *declared));
+        } else if let Some(declared) = enums.iter().find(|e| e.name() == model.name) {
+            summary.collisions.push(collision(schema, &format!("model `{}` of table `{}`", model.name, model.table), "enum", *declared));
+        } else {
+            summary.models += 1;
+            appended.push(render_model(model));
+        }
+    }
+    let mut result = existing.to_owned();
+    insertions.sort_by_key(|(position, _)| *position);
+    for (position, text) in insertions.into_iter().rev() {
+        result.insert_str(position, &text);
+    }
+    for block in appended {
+        if !result.is_empty() && !result.ends_with("\n\n") {
+            result.push_str(if result.ends_with('\n') { "\n" } else { "\n\n" });
+        }
+        result.push_str(&block);
+    }
+    (result, summary)
+}
+
+fn collision<N: Identifiable + NodeTrait + NamedIdentifiable>(schema: &Schema, introspected: &str, keyword: &str, declared: &N) -> String {
+    let location = location(schema, declared);
+    format!(
+        "{} is left out, its name is taken by {} `{}` at {}:{}; rename one of them",
+        introspected, keyword, declared.name(), location.file, location.line,
+    )
+}
+
+/// Where to insert members before the `}` closing a block which ends at
+/// `end`: the start of its line, if only whitespace precedes it there.
+fn closing_line(content: &str, end: usize) -> usize {
+    let index = content[..end].rfind('}').unwrap_or(end);
+    let start = content[..index].rfind('\n').map(|i| i + 1).unwrap_or(0);
+    if content[start..index].trim().is_empty() { start } else { index }
+}
+
+/// The table or column name of a `@map` decorator.
+fn map_argument<'a>(decorators: impl Iterator<Item = &'a Decorator>) -> Option<String> {
+    let map = decorator(decorators, "map")?;
+    let argument = map.argument_list()?.arguments().next()?;
+    argument.value().resolved().value.as_ref().and_then(Value::as_str).map(ToOwned::to_owned)
+}
+
+fn normalize(decorator: &str) -> String {
+    decorator.chars().filter(|c| !c.is_whitespace()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::introspect::generate::{EnumDef, FieldDef, Generated, ModelDef};
+    use crate::test::schema::parse_schema_source;
+    use super::{merge, MergeSummary};
+
+    const CONNECTOR: &str = "connector {\n  provider .sqlite\n  url \"sqlite::memory:\"\n}\n\n";
+
+    fn run(existing: &str, generated: &Generated) -> (String, MergeSummary) {
+        let schema = parse_schema_source(existing);
+        let source = schema.sources.values().find(|source| !source.builtin).unwrap();
+        merge(&schema, source, existing, generated)
+    }
+
+    fn field(name: &str, column: &str, decorators: &[&str], r#type: &str) -> FieldDef {
+        FieldDef {
+            name: name.to_owned(),
+            column: Some(column.to_owned()),
+            decorators: decorators.iter().map(|d| d.to_string()).collect(),
+            r#type: r#type.to_owned(),
+            unsupported: None,
+        }
+    }
+
+    fn users() -> ModelDef {
+        ModelDef {
+            table: "users".to_owned(),
+            name: "User".to_owned(),
+            decorators: vec!["@map(\"users\")".to_owned()],
+            fields: vec![
+                field("id", "id", &["@id"], "Int"),
+                field("email", "email", &[], "String"),
+                field("createdAt", "created_at", &["@map(\"created_at\")"], "DateTime"),
+            ],
+        }
+    }
+
+    #[test]
+    fn keeps_hand_edits_and_adds_missing_fields() {
+        let existing = format!("{}// accounts of the app\n@map(\"users\")\nmodel Account {{\n  @id @autoIncrement\n  id: Int\n  /// login\n  @unique\n  email: String\n  @map(\"created_at\") @readonly\n  created: DateTime\n}}\n", CONNECTOR);
+        let mut model = users();
+        model.fields.push(field("name", "name", &[], "String?"));
+        let (merged, summary) = run(&existing, &Generated { models: vec![model], enums: vec![] });
+        assert_eq!(summary.models, 0);
+        assert_eq!(summary.fields, 1);
+        assert!(summary.collisions.is_empty());
+        assert!(merged.starts_with(&existing[..existing.len() - 2]), "{}", merged);
+        assert!(merged.ends_with("  created: DateTime\n  name: String?\n}\n"), "{}", merged);
+    }
+
+    #[test]
+    fn adds_missing_enum_members_and_declarations() {
+        let existing = format!("{}enum Role {{\n  admin // can do anything\n}}\n", CONNECTOR);
+        let generated = Generated {
+            models: vec![users()],
+            enums: vec![EnumDef { name: "Role".to_owned(), members: vec!["admin".to_owned(), "user".to_owned()] }],
+        };
+        let (merged, summary) = run(&existing, &generated);
+        assert_eq!((summary.members, summary.models, summary.enums), (1, 1, 0));
+        assert!(merged.contains("enum Role {\n  admin // can do anything\n  user\n}\n\n@map(\"users\")\nmodel User {"), "{}", merged);
+        let (again, summary) = run(&merged, &generated);
+        assert_eq!(again, merged);
+        assert_eq!((summary.members, summary.models, summary.fields), (0, 0, 0));
+    }
+
+    #[test]
+    fn reports_name_collisions() {
+        let existing = format!("{}@map(\"members\")\nmodel User {{\n  @id @autoIncrement\n  id: Int\n}}\n\nenum Post {{\n  draft\n}}\n", CONNECTOR);
+        let post = ModelDef {
+            table: "Post".to_owned(),
+            name: "Post".to_owned(),
+            decorators: vec![],
+            fields: vec![field("id", "id", &["@id"], "Int")],
+        };
+        let (merged, summary) = run(&existing, &Generated { models: vec![users(), post], enums: vec![] });
+        assert_eq!(merged, existing);
+        assert_eq!(summary.models, 0);
+        assert_eq!(summary.collisions.len(), 2);
+        assert!(summary.collisions[0].starts_with("model `User` of table `users` is left out, its name is taken by model `User`"), "{}", summary.collisions[0]);
+        assert!(summary.collisions[1].contains("enum `Post`"), "{}", summary.collisions[1]);
+    }
+}
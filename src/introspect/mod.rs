@@ -0,0 +1,98 @@
+pub(crate) mod schema;
+pub(crate) mod sql;
+pub(crate) mod mongo;
+pub(crate) mod generate;
+pub(crate) mod merge;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use teo_parser::ast::schema::Schema;
+use teo_parser::ast::source::Source;
+use teo_parser::{parse as schema_parse};
+use teo_result::{Error, Result};
+use crate::app::ctx::Ctx;
+use crate::app::database::connection_for_connector;
+use crate::app::environment::interpolate;
+use crate::cli::command::IntrospectCommand;
+use crate::introspect::generate::{generate, render};
+use crate::introspect::merge::merge;
+use crate::introspect::mongo::read_mongo_schema;
+use crate::introspect::sql::read_sql_schema;
+use crate::message::{info_message, warning_message};
+
+/// Read the database of the main connector and print it as Teo models.
+///
+/// With `--output`, the models are written to a file. If the file exists,
+/// new models, fields and enum members are merged into it and everything
+/// else is kept, unless `--overwrite` is given.
+pub(crate) async fn introspect(ctx: &Ctx, command: &IntrospectCommand) -> Result<()> {
+    let connector = match ctx.main_namespace().connector.as_ref() {
+        Some(connector) => connector,
+        None => Err(Error::new("introspection requires a connector in the main namespace"))?,
+    };
+    let mut schema = if connector.provider.is_mongo() {
+        read_mongo_schema(&interpolate(&connector.url)?, command.sample_size).await?
+    } else {
+        let connection = connection_for_connector(connector).await?;
+        read_sql_schema(connection.no_transaction().await?, &connector.provider).await?
+    };
+    // tables of teo's own bookkeeping
+    schema.tables.retain(|t| !t.name.starts_with("__"));
+    let generated = generate(&schema);
+    let Some(output) = command.output.as_ref() else {
+        print!("{}", render(&generated));
+        return Ok(());
+    };
+    let path = PathBuf::from(output);
+    let existing = if command.overwrite { None } else { fs::read_to_string(&path).ok() };
+    let (content, message) = match existing {
+        Some(existing) => {
+            let parsed;
+            let (schema, source) = match loaded_source(ctx.schema(), &path) {
+                Some(source) => (ctx.schema(), source),
+                None => {
+                    parsed = parse_existing(&path, output)?;
+                    let Some(source) = loaded_source(&parsed, &path) else {
+                        Err(Error::new(format!("cannot merge into {}, it cannot be parsed", output)))?
+                    };
+                    (&parsed, source)
+                }
+            };
+            let (content, summary) = merge(schema, source, &existing, &generated);
+            if !ctx.cli().silent {
+                for collision in &summary.collisions {
+                    warning_message(collision);
+                }
+            }
+            (content, format!(
+                "merged into {}: {} model(s), {} enum(s), {} field(s) and {} enum member(s) added",
+                output, summary.models, summary.enums, summary.fields, summary.members,
+            ))
+        }
+        None => (render(&generated), format!("wrote {} model(s) and {} enum(s) to {}", generated.models.len(), generated.enums.len(), output)),
+    };
+    if let Err(err) = fs::write(&path, content) {
+        Err(Error::new(format!("cannot write {}: {}", output, err)))?
+    }
+    if !ctx.cli().silent {
+        info_message(message);
+    }
+    Ok(())
+}
+
+/// The source of `schema` read from `path`.
+fn loaded_source<'a>(schema: &'a Schema, path: &Path) -> Option<&'a Source> {
+    let path = fs::canonicalize(path).ok()?;
+    schema.sources.values().find(|source| {
+        !source.builtin && fs::canonicalize(&source.file_path).map_or(false, |file| file == path)
+    })
+}
+
+/// Parse an output file which is not a part of the app's schema on its own.
+fn parse_existing(path: &Path, output: &str) -> Result<Schema> {
+    let (schema, diagnostics) = schema_parse(path.to_string_lossy().as_ref(), None, None);
+    if diagnostics.has_errors() {
+        Err(Error::new(format!("cannot merge into {}, it has errors; fix them or use --overwrite", output)))?
+    }
+    Ok(schema)
+}
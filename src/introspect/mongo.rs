@@ -0,0 +1,118 @@
+use bson::{doc, Bson, Document};
+use futures_util::TryStreamExt;
+use indexmap::IndexMap;
+use mongodb::Client;
+use teo_result::{Error, Result};
+use crate::app::environment::{mask_secrets, mask_url};
+use crate::introspect::schema::{Column, ColumnType, DatabaseSchema, Index, Table};
+
+/// Infer the structure of a MongoDB database from up to `sample_size`
+/// documents of each collection.
+pub(crate) async fn read_mongo_schema(url: &str, sample_size: usize) -> Result<DatabaseSchema> {
+    let client = match Client::with_uri_str(url).await {
+        Ok(client) => client,
+        Err(err) => Err(Error::new(format!("cannot connect to {}: {}", mask_url(url), mask_secrets(&err.to_string(), url))))?,
+    };
+    let database = match client.default_database() {
+        Some(database) => database,
+        None => Err(Error::new("database name is missing in the connector url"))?,
+    };
+    let mut collection_names = match database.list_collection_names(None).await {
+        Ok(names) => names,
+        Err(err) => Err(Error::new(format!("cannot list collections: {}", err)))?,
+    };
+    collection_names.retain(|name| !name.starts_with("system."));
+    collection_names.sort();
+    let mut schema = DatabaseSchema { document: true, ..Default::default() };
+    for collection_name in collection_names {
+        let collection = database.collection::<Document>(&collection_name);
+        let mut cursor = match collection.aggregate([doc!{ "$sample": { "size": sample_size as i64 } }], None).await {
+            Ok(cursor) => cursor,
+            Err(err) => Err(Error::new(format!("cannot sample {}: {}", collection_name, err)))?,
+        };
+        let mut fields: IndexMap<String, FieldSample> = IndexMap::new();
+        let mut documents = 0;
+        loop {
+            let document = match cursor.try_next().await {
+                Ok(Some(document)) => document,
+                Ok(None) => break,
+                Err(err) => Err(Error::new(format!("cannot sample {}: {}", collection_name, err)))?,
+            };
+            documents += 1;
+            for (key, value) in document.iter() {
+                let sample = fields.entry(key.clone()).or_default();
+                sample.seen += 1;
+                match bson_type(value) {
+                    None => sample.nullable = true,
+                    Some(r#type) => if !sample.types.contains(&r#type) {
+                        sample.types.push(r#type);
+                    },
+                }
+            }
+        }
+        let mut table = Table { name: collection_name.clone(), ..Default::default() };
+        if !fields.contains_key("_id") {
+            fields.insert("_id".to_owned(), FieldSample { seen: documents, nullable: false, types: vec![ColumnType::Teo("ObjectId".to_owned())] });
+        }
+        for (name, sample) in fields {
+            let r#type = match sample.types.as_slice() {
+                [r#type] => r#type.clone(),
+                [] => ColumnType::Unsupported("null".to_owned()),
+                _ => ColumnType::Unsupported("mixed".to_owned()),
+            };
+            let optional = name != "_id" && (sample.nullable || sample.seen < documents);
+            table.columns.push(Column { name, r#type, optional, auto_increment: false, default: None });
+        }
+        table.primary_key = vec!["_id".to_owned()];
+        let mut indexes = match collection.list_indexes(None).await {
+            Ok(cursor) => cursor,
+            Err(err) => Err(Error::new(format!("cannot list indexes of {}: {}", collection_name, err)))?,
+        };
+        loop {
+            let index = match indexes.try_next().await {
+                Ok(Some(index)) => index,
+                Ok(None) => break,
+                Err(err) => Err(Error::new(format!("cannot list indexes of {}: {}", collection_name, err)))?,
+            };
+            let columns: Vec<String> = index.keys.keys().cloned().collect();
+            if columns == vec!["_id".to_owned()] {
+                continue;
+            }
+            let unique = index.options.as_ref().and_then(|o| o.unique).unwrap_or(false);
+            table.indexes.push(Index { columns, unique });
+        }
+        schema.tables.push(table);
+    }
+    Ok(schema)
+}
+
+#[derive(Default)]
+struct FieldSample {
+    seen: usize,
+    nullable: bool,
+    types: Vec<ColumnType>,
+}
+
+fn bson_type(value: &Bson) -> Option<ColumnType> {
+    let name = match value {
+        Bson::Null | Bson::Undefined => return None,
+        Bson::ObjectId(_) => "ObjectId",
+        Bson::String(_) => "String",
+        Bson::Boolean(_) => "Bool",
+        Bson::Int32(_) => "Int",
+        Bson::Int64(_) => "Int64",
+        Bson::Double(_) => "Float",
+        Bson::Decimal128(_) => "Decimal",
+        Bson::DateTime(_) => "DateTime",
+        Bson::Array(items) => {
+            let item_types: Vec<ColumnType> = items.iter().filter_map(bson_type).collect();
+            return match item_types.first() {
+                Some(ColumnType::Teo(name)) if item_types.iter().all(|t| t == &item_types[0]) => Some(ColumnType::Teo(format!("{}[]", name))),
+                _ => Some(ColumnType::Unsupported("array".to_owned())),
+            };
+        }
+        Bson::Document(_) => return Some(ColumnType::Unsupported("document".to_owned())),
+        other => return Some(ColumnType::Unsupported(format!("{:?}", other.element_type()).to_lowercase())),
+    };
+    Some(ColumnType::Teo(name.to_owned()))
+}
@@ -0,0 +1,84 @@
+/// The structure of a database as read by introspection.
+#[derive(Debug, Default)]
+pub(crate) struct DatabaseSchema {
+    pub(crate) tables: Vec<Table>,
+    pub(crate) enums: Vec<EnumType>,
+    /// Whether records are documents identified by `_id`.
+    pub(crate) document: bool,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Table {
+    pub(crate) name: String,
+    pub(crate) columns: Vec<Column>,
+    pub(crate) primary_key: Vec<String>,
+    pub(crate) indexes: Vec<Index>,
+    pub(crate) foreign_keys: Vec<ForeignKey>,
+}
+
+#[derive(Debug)]
+pub(crate) struct Column {
+    pub(crate) name: String,
+    pub(crate) r#type: ColumnType,
+    pub(crate) optional: bool,
+    pub(crate) auto_increment: bool,
+    /// The default value as a Teo expression.
+    pub(crate) default: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum ColumnType {
+    /// A Teo type, e.g. `Int` or `String[]`.
+    Teo(String),
+    /// The name of an enum in `DatabaseSchema::enums`.
+    Enum(String),
+    /// A database type without a Teo equivalent.
+    Unsupported(String),
+}
+
+#[derive(Debug)]
+pub(crate) struct Index {
+    pub(crate) columns: Vec<String>,
+    pub(crate) unique: bool,
+}
+
+#[derive(Debug)]
+pub(crate) struct ForeignKey {
+    pub(crate) columns: Vec<String>,
+    pub(crate) referenced_table: String,
+    pub(crate) referenced_columns: Vec<String>,
+}
+
+#[derive(Debug)]
+pub(crate) struct EnumType {
+    pub(crate) name: String,
+    pub(crate) members: Vec<String>,
+}
+
+impl DatabaseSchema {
+
+    pub(crate) fn table(&self, name: &str) -> Option<&Table> {
+        self.tables.iter().find(|t| t.name == name)
+    }
+}
+
+impl Table {
+
+    pub(crate) fn column(&self, name: &str) -> Option<&Column> {
+        self.columns.iter().find(|c| c.name == name)
+    }
+
+    /// Whether a record is identified by `columns`.
+    pub(crate) fn is_unique(&self, columns: &Vec<String>) -> bool {
+        &self.primary_key == columns || self.indexes.iter().any(|i| i.unique && &i.columns == columns)
+    }
+
+    /// A join table of a many to many relation only consists of the foreign
+    /// keys to both sides.
+    pub(crate) fn is_join_table(&self) -> bool {
+        self.foreign_keys.len() == 2
+            && self.foreign_keys[0].referenced_table != self.name
+            && self.foreign_keys[1].referenced_table != self.name
+            && self.columns.iter().all(|c| self.foreign_keys.iter().any(|f| f.columns.contains(&c.name)))
+    }
+}
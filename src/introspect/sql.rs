@@ -0,0 +1,383 @@
+use std::sync::Arc;
+use indexmap::IndexMap;
+use teo_result::{Error, Result};
+use teo_runtime::connection::transaction::Transaction;
+use teo_runtime::database::database::Database;
+use teo_teon::value::Value;
+use crate::introspect::schema::{Column, ColumnType, DatabaseSchema, EnumType, ForeignKey, Index, Table};
+
+type Row = IndexMap<String, Value>;
+
+/// Read the structure of a SQL database through its catalog.
+pub(crate) async fn read_sql_schema(transaction: Arc<dyn Transaction>, database: &Database) -> Result<DatabaseSchema> {
+    let reader = SQLReader { transaction };
+    match database {
+        Database::PostgreSQL => reader.read_postgres().await,
+        Database::MySQL => reader.read_mysql().await,
+        Database::SQLite => reader.read_sqlite().await,
+        Database::MongoDB => unreachable!(),
+    }
+}
+
+struct SQLReader {
+    transaction: Arc<dyn Transaction>,
+}
+
+impl SQLReader {
+
+    async fn query(&self, sql: &str) -> Result<Vec<Row>> {
+        let result = self.transaction.query_raw(&Value::String(sql.to_owned())).await?;
+        match result {
+            Value::Array(rows) => Ok(rows.into_iter().filter_map(|row| match row {
+                Value::Dictionary(row) => Some(row),
+                _ => None,
+            }).collect()),
+            _ => Err(Error::new("unexpected result of catalog query")),
+        }
+    }
+
+    async fn read_postgres(&self) -> Result<DatabaseSchema> {
+        let mut schema = DatabaseSchema::default();
+        for row in self.query("SELECT t.typname::text AS name, e.enumlabel::text AS member FROM pg_type t \
+            JOIN pg_enum e ON e.enumtypid = t.oid JOIN pg_namespace n ON n.oid = t.typnamespace \
+            WHERE n.nspname = current_schema() ORDER BY t.typname, e.enumsortorder").await? {
+            add_enum_member(&mut schema, text(&row, "name"), text(&row, "member"));
+        }
+        for row in self.query("SELECT table_name::text AS table_name FROM information_schema.tables \
+            WHERE table_schema = current_schema() AND table_type = 'BASE TABLE' ORDER BY table_name").await? {
+            schema.tables.push(Table { name: text(&row, "table_name"), ..Default::default() });
+        }
+        for row in self.query("SELECT table_name::text AS table_name, column_name::text AS column_name, \
+            data_type::text AS data_type, udt_name::text AS udt_name, is_nullable::text AS is_nullable, \
+            column_default::text AS column_default, is_identity::text AS is_identity FROM information_schema.columns \
+            WHERE table_schema = current_schema() ORDER BY table_name, ordinal_position").await? {
+            let r#type = postgres_type(&schema, &text(&row, "data_type"), &text(&row, "udt_name"));
+            let raw_default = text(&row, "column_default");
+            let auto_increment = raw_default.starts_with("nextval(") || flag(&row, "is_identity");
+            let default = if auto_increment { None } else { default_expression(&raw_default, &r#type, false) };
+            if let Some(table) = table_mut(&mut schema, &text(&row, "table_name")) {
+                table.columns.push(Column { name: text(&row, "column_name"), r#type, optional: flag(&row, "is_nullable"), auto_increment, default });
+            }
+        }
+        let mut last_index: Option<(String, String)> = None;
+        for row in self.query("SELECT t.relname::text AS table_name, i.relname::text AS index_name, \
+            ix.indisunique AS is_unique, ix.indisprimary AS is_primary, a.attname::text AS column_name FROM pg_class t \
+            JOIN pg_index ix ON t.oid = ix.indrelid JOIN pg_class i ON i.oid = ix.indexrelid \
+            JOIN pg_attribute a ON a.attrelid = t.oid AND a.attnum = ANY(ix.indkey) \
+            JOIN pg_namespace n ON n.oid = t.relnamespace WHERE n.nspname = current_schema() \
+            ORDER BY t.relname, i.relname, array_position(ix.indkey::int2[], a.attnum)").await? {
+            let key = (text(&row, "table_name"), text(&row, "index_name"));
+            let same_index = last_index.as_ref() == Some(&key);
+            last_index = Some(key.clone());
+            if let Some(table) = table_mut(&mut schema, &key.0) {
+                add_index_column(table, same_index, flag(&row, "is_primary"), flag(&row, "is_unique"), text(&row, "column_name"));
+            }
+        }
+        let mut last_constraint: Option<(String, String)> = None;
+        for row in self.query("SELECT con.conname::text AS constraint_name, cl.relname::text AS table_name, \
+            att.attname::text AS column_name, fcl.relname::text AS referenced_table, fatt.attname::text AS referenced_column \
+            FROM pg_constraint con JOIN pg_class cl ON cl.oid = con.conrelid JOIN pg_class fcl ON fcl.oid = con.confrelid \
+            JOIN pg_namespace n ON n.oid = cl.relnamespace \
+            CROSS JOIN LATERAL unnest(con.conkey, con.confkey) WITH ORDINALITY AS k(attnum, fattnum, ord) \
+            JOIN pg_attribute att ON att.attrelid = con.conrelid AND att.attnum = k.attnum \
+            JOIN pg_attribute fatt ON fatt.attrelid = con.confrelid AND fatt.attnum = k.fattnum \
+            WHERE con.contype = 'f' AND n.nspname = current_schema() ORDER BY cl.relname, con.conname, k.ord").await? {
+            let key = (text(&row, "table_name"), text(&row, "constraint_name"));
+            let same_constraint = last_constraint.as_ref() == Some(&key);
+            last_constraint = Some(key.clone());
+            if let Some(table) = table_mut(&mut schema, &key.0) {
+                add_foreign_key_column(table, same_constraint, text(&row, "column_name"), text(&row, "referenced_table"), text(&row, "referenced_column"));
+            }
+        }
+        Ok(schema)
+    }
+
+    async fn read_mysql(&self) -> Result<DatabaseSchema> {
+        let mut schema = DatabaseSchema::default();
+        for row in self.query("SELECT CAST(TABLE_NAME AS CHAR) AS table_name FROM information_schema.TABLES \
+            WHERE TABLE_SCHEMA = DATABASE() AND TABLE_TYPE = 'BASE TABLE' ORDER BY TABLE_NAME").await? {
+            schema.tables.push(Table { name: text(&row, "table_name"), ..Default::default() });
+        }
+        for row in self.query("SELECT CAST(TABLE_NAME AS CHAR) AS table_name, CAST(COLUMN_NAME AS CHAR) AS column_name, \
+            CAST(DATA_TYPE AS CHAR) AS data_type, CAST(COLUMN_TYPE AS CHAR) AS column_type, \
+            CAST(IS_NULLABLE AS CHAR) AS is_nullable, CAST(COLUMN_DEFAULT AS CHAR) AS column_default, \
+            CAST(EXTRA AS CHAR) AS extra FROM information_schema.COLUMNS WHERE TABLE_SCHEMA = DATABASE() \
+            ORDER BY TABLE_NAME, ORDINAL_POSITION").await? {
+            let table_name = text(&row, "table_name");
+            let column_name = text(&row, "column_name");
+            let column_type = text(&row, "column_type");
+            let r#type = match text(&row, "data_type").as_str() {
+                "enum" => {
+                    let name = format!("{}_{}", table_name, column_name);
+                    for member in mysql_enum_members(&column_type) {
+                        add_enum_member(&mut schema, name.clone(), member);
+                    }
+                    ColumnType::Enum(name)
+                }
+                data_type => mysql_type(data_type, &column_type),
+            };
+            let auto_increment = text(&row, "extra").contains("auto_increment");
+            let default = match row.get("column_default") {
+                Some(Value::Null) | None => None,
+                _ => default_expression(&text(&row, "column_default"), &r#type, true),
+            };
+            if let Some(table) = table_mut(&mut schema, &table_name) {
+                table.columns.push(Column { name: column_name, r#type, optional: flag(&row, "is_nullable"), auto_increment, default });
+            }
+        }
+        let mut last_index: Option<(String, String)> = None;
+        for row in self.query("SELECT CAST(TABLE_NAME AS CHAR) AS table_name, CAST(INDEX_NAME AS CHAR) AS index_name, \
+            CAST(NON_UNIQUE AS SIGNED) AS non_unique, CAST(COLUMN_NAME AS CHAR) AS column_name \
+            FROM information_schema.STATISTICS WHERE TABLE_SCHEMA = DATABASE() \
+            ORDER BY TABLE_NAME, INDEX_NAME, SEQ_IN_INDEX").await? {
+            let key = (text(&row, "table_name"), text(&row, "index_name"));
+            let same_index = last_index.as_ref() == Some(&key);
+            last_index = Some(key.clone());
+            if let Some(table) = table_mut(&mut schema, &key.0) {
+                add_index_column(table, same_index, key.1 == "PRIMARY", !flag(&row, "non_unique"), text(&row, "column_name"));
+            }
+        }
+        let mut last_constraint: Option<(String, String)> = None;
+        for row in self.query("SELECT CAST(CONSTRAINT_NAME AS CHAR) AS constraint_name, CAST(TABLE_NAME AS CHAR) AS table_name, \
+            CAST(COLUMN_NAME AS CHAR) AS column_name, CAST(REFERENCED_TABLE_NAME AS CHAR) AS referenced_table, \
+            CAST(REFERENCED_COLUMN_NAME AS CHAR) AS referenced_column FROM information_schema.KEY_COLUMN_USAGE \
+            WHERE TABLE_SCHEMA = DATABASE() AND REFERENCED_TABLE_NAME IS NOT NULL \
+            ORDER BY TABLE_NAME, CONSTRAINT_NAME, ORDINAL_POSITION").await? {
+            let key = (text(&row, "table_name"), text(&row, "constraint_name"));
+            let same_constraint = last_constraint.as_ref() == Some(&key);
+            last_constraint = Some(key.clone());
+            if let Some(table) = table_mut(&mut schema, &key.0) {
+                add_foreign_key_column(table, same_constraint, text(&row, "column_name"), text(&row, "referenced_table"), text(&row, "referenced_column"));
+            }
+        }
+        Ok(schema)
+    }
+
+    async fn read_sqlite(&self) -> Result<DatabaseSchema> {
+        let mut schema = DatabaseSchema::default();
+        let table_names: Vec<String> = self.query("SELECT name FROM sqlite_master WHERE type = 'table' \
+            AND name NOT LIKE 'sqlite_%' ORDER BY name").await?.iter().map(|row| text(row, "name")).collect();
+        for table_name in table_names {
+            let mut table = Table { name: table_name.clone(), ..Default::default() };
+            let mut primary_key: Vec<(i64, String)> = vec![];
+            for row in self.query(&format!("PRAGMA table_info(\"{}\")", table_name)).await? {
+                let name = text(&row, "name");
+                let declared_type = text(&row, "type").to_uppercase();
+                let r#type = sqlite_type(&declared_type);
+                if int(&row, "pk") > 0 {
+                    primary_key.push((int(&row, "pk"), name.clone()));
+                }
+                let default = default_expression(&text(&row, "dflt_value"), &r#type, false);
+                let auto_increment = declared_type == "INTEGER" && int(&row, "pk") > 0;
+                table.columns.push(Column { name, r#type, optional: !flag(&row, "notnull") && int(&row, "pk") == 0, auto_increment, default });
+            }
+            primary_key.sort();
+            table.primary_key = primary_key.into_iter().map(|(_, name)| name).collect();
+            if table.primary_key.len() != 1 {
+                table.columns.iter_mut().for_each(|c| c.auto_increment = false);
+            }
+            for row in self.query(&format!("PRAGMA index_list(\"{}\")", table_name)).await? {
+                if text(&row, "origin") == "pk" {
+                    continue;
+                }
+                let index_name = text(&row, "name");
+                let columns = self.query(&format!("PRAGMA index_info(\"{}\")", index_name)).await?.iter().map(|row| text(row, "name")).collect();
+                table.indexes.push(Index { columns, unique: flag(&row, "unique") });
+            }
+            let mut last_id = None;
+            for row in self.query(&format!("PRAGMA foreign_key_list(\"{}\")", table_name)).await? {
+                let id = int(&row, "id");
+                let same_constraint = last_id == Some(id);
+                last_id = Some(id);
+                add_foreign_key_column(&mut table, same_constraint, text(&row, "from"), text(&row, "table"), text(&row, "to"));
+            }
+            schema.tables.push(table);
+        }
+        Ok(schema)
+    }
+}
+
+fn table_mut<'a>(schema: &'a mut DatabaseSchema, name: &str) -> Option<&'a mut Table> {
+    schema.tables.iter_mut().find(|t| t.name == name)
+}
+
+fn add_enum_member(schema: &mut DatabaseSchema, name: String, member: String) {
+    match schema.enums.iter_mut().find(|e| e.name == name) {
+        Some(r#enum) => r#enum.members.push(member),
+        None => schema.enums.push(EnumType { name, members: vec![member] }),
+    }
+}
+
+/// Rows of an index come column by column, `continues` tells whether the row
+/// belongs to the same index as the previous one.
+fn add_index_column(table: &mut Table, continues: bool, primary: bool, unique: bool, column: String) {
+    if primary {
+        table.primary_key.push(column);
+    } else if continues && !table.indexes.is_empty() {
+        table.indexes.last_mut().unwrap().columns.push(column);
+    } else {
+        table.indexes.push(Index { columns: vec![column], unique });
+    }
+}
+
+fn add_foreign_key_column(table: &mut Table, continues: bool, column: String, referenced_table: String, referenced_column: String) {
+    if continues && !table.foreign_keys.is_empty() {
+        let foreign_key = table.foreign_keys.last_mut().unwrap();
+        foreign_key.columns.push(column);
+        foreign_key.referenced_columns.push(referenced_column);
+    } else {
+        table.foreign_keys.push(ForeignKey { columns: vec![column], referenced_table, referenced_columns: vec![referenced_column] });
+    }
+}
+
+fn text(row: &Row, key: &str) -> String {
+    match row.get(key) {
+        Some(Value::String(s)) => s.clone(),
+        Some(Value::Int(i)) => i.to_string(),
+        Some(Value::Int64(i)) => i.to_string(),
+        Some(Value::Bool(b)) => b.to_string(),
+        _ => "".to_owned(),
+    }
+}
+
+fn int(row: &Row, key: &str) -> i64 {
+    match row.get(key) {
+        Some(Value::Int(i)) => *i as i64,
+        Some(Value::Int64(i)) => *i,
+        Some(Value::String(s)) => s.parse().unwrap_or(0),
+        Some(Value::Bool(b)) => *b as i64,
+        _ => 0,
+    }
+}
+
+fn flag(row: &Row, key: &str) -> bool {
+    match row.get(key) {
+        Some(Value::Bool(b)) => *b,
+        Some(Value::String(s)) => matches!(s.as_str(), "YES" | "t" | "true" | "1"),
+        _ => int(row, key) != 0,
+    }
+}
+
+fn teo(name: &str) -> ColumnType {
+    ColumnType::Teo(name.to_owned())
+}
+
+fn postgres_type(schema: &DatabaseSchema, data_type: &str, udt_name: &str) -> ColumnType {
+    match data_type {
+        "USER-DEFINED" if schema.enums.iter().any(|e| e.name == udt_name) => ColumnType::Enum(udt_name.to_owned()),
+        "ARRAY" => match postgres_type(schema, postgres_udt_data_type(udt_name.trim_start_matches('_')), udt_name.trim_start_matches('_')) {
+            ColumnType::Teo(name) => ColumnType::Teo(format!("{}[]", name)),
+            ColumnType::Enum(_) | ColumnType::Unsupported(_) => ColumnType::Unsupported(udt_name.to_owned()),
+        },
+        "smallint" | "integer" => teo("Int"),
+        "bigint" => teo("Int64"),
+        "real" => teo("Float32"),
+        "double precision" => teo("Float"),
+        "numeric" => teo("Decimal"),
+        "boolean" => teo("Bool"),
+        "text" | "character varying" | "character" => teo("String"),
+        "date" => teo("Date"),
+        "timestamp without time zone" | "timestamp with time zone" => teo("DateTime"),
+        _ => ColumnType::Unsupported(udt_name.to_owned()),
+    }
+}
+
+fn postgres_udt_data_type(udt_name: &str) -> &str {
+    match udt_name {
+        "int2" => "smallint",
+        "int4" => "integer",
+        "int8" => "bigint",
+        "float4" => "real",
+        "float8" => "double precision",
+        "numeric" => "numeric",
+        "bool" => "boolean",
+        "text" | "varchar" | "bpchar" => "text",
+        "date" => "date",
+        "timestamp" | "timestamptz" => "timestamp with time zone",
+        _ => "USER-DEFINED",
+    }
+}
+
+fn mysql_type(data_type: &str, column_type: &str) -> ColumnType {
+    match data_type {
+        "tinyint" if column_type == "tinyint(1)" => teo("Bool"),
+        "bool" | "boolean" => teo("Bool"),
+        "tinyint" | "smallint" | "mediumint" | "int" => teo("Int"),
+        "bigint" => teo("Int64"),
+        "float" => teo("Float32"),
+        "double" => teo("Float"),
+        "decimal" => teo("Decimal"),
+        "char" | "varchar" | "tinytext" | "text" | "mediumtext" | "longtext" => teo("String"),
+        "date" => teo("Date"),
+        "datetime" | "timestamp" => teo("DateTime"),
+        _ => ColumnType::Unsupported(column_type.to_owned()),
+    }
+}
+
+/// The members of a column type like `enum('draft','published')`.
+fn mysql_enum_members(column_type: &str) -> Vec<String> {
+    let inner = column_type.trim_start_matches("enum(").trim_end_matches(')');
+    inner.split("','").map(|m| m.trim_matches('\'').replace("''", "'")).collect()
+}
+
+fn sqlite_type(declared_type: &str) -> ColumnType {
+    if declared_type.contains("BOOL") {
+        teo("Bool")
+    } else if declared_type.contains("BIGINT") {
+        teo("Int64")
+    } else if declared_type.contains("INT") {
+        teo("Int")
+    } else if declared_type.contains("CHAR") || declared_type.contains("CLOB") || declared_type.contains("TEXT") {
+        teo("String")
+    } else if declared_type.contains("REAL") || declared_type.contains("FLOA") || declared_type.contains("DOUB") {
+        teo("Float")
+    } else if declared_type.contains("DECIMAL") || declared_type.contains("NUMERIC") {
+        teo("Decimal")
+    } else if declared_type.contains("DATETIME") || declared_type.contains("TIMESTAMP") {
+        teo("DateTime")
+    } else if declared_type.contains("DATE") {
+        teo("Date")
+    } else {
+        ColumnType::Unsupported(declared_type.to_owned())
+    }
+}
+
+/// Convert a column default to a Teo expression. `literal_strings` tells
+/// that string defaults are given without quotes, as MySQL does.
+fn default_expression(raw: &str, r#type: &ColumnType, literal_strings: bool) -> Option<String> {
+    let raw = raw.trim();
+    if raw.is_empty() || raw.eq_ignore_ascii_case("null") {
+        return None;
+    }
+    let lower = raw.to_lowercase();
+    if ["now()", "current_timestamp", "current_timestamp()", "localtimestamp"].contains(&lower.as_str()) {
+        return Some("$now".to_owned());
+    }
+    if ["current_date", "curdate()"].contains(&lower.as_str()) {
+        return Some("$today".to_owned());
+    }
+    let string = if raw.starts_with('\'') {
+        let end = raw[1..].find("'::").map(|i| i + 1).unwrap_or(raw.len() - 1);
+        Some(raw[1..end.max(1)].replace("''", "'"))
+    } else if literal_strings {
+        Some(raw.to_owned())
+    } else {
+        None
+    };
+    let value = string.clone().unwrap_or_else(|| raw.split("::").next().unwrap_or(raw).to_owned());
+    match r#type {
+        ColumnType::Enum(_) => string.map(|s| format!(".{}", s)),
+        ColumnType::Teo(name) => match name.as_str() {
+            "String" => string.map(|s| serde_json::to_string(&s).unwrap()),
+            "Bool" => match value.to_lowercase().as_str() {
+                "true" | "1" | "t" => Some("true".to_owned()),
+                "false" | "0" | "f" => Some("false".to_owned()),
+                _ => None,
+            },
+            "Int" | "Int64" => value.parse::<i64>().ok().map(|v| v.to_string()),
+            "Float" | "Float32" => value.parse::<f64>().ok().map(|_| if value.contains('.') { value.clone() } else { format!("{}.0", value) }),
+            _ => None,
+        },
+        ColumnType::Unsupported(_) => None,
+    }
+}
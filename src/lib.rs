@@ -4,6 +4,13 @@ pub mod server;
 pub mod migrate;
 pub mod purge;
 pub mod seeder;
+pub mod test;
+pub mod lint;
+pub mod format;
+pub mod introspect;
+pub mod console;
+pub mod routes;
+pub mod dump;
 mod message;
 
 pub mod prelude {
@@ -11,7 +18,21 @@ pub mod prelude {
     pub use crate::app;
     pub use crate::cli::entrance::Entrance;
     pub use crate::cli::runtime_version::RuntimeVersion;
+    pub use crate::cli::outcome::Outcome;
     pub use crate::server::static_files::serve_static_files;
+    pub use crate::server::error::{ErrorFormatter, ProblemJsonErrorFormatter};
+    pub use crate::server::request::RequestExt;
+    pub use crate::server::session::{Session, SessionConfig};
+    pub use crate::server::session::store::{SessionStore, MemorySessionStore};
+    pub use crate::server::session::database::DatabaseSessionStore;
+    pub use crate::server::rate_limit::{RateLimiter, RateLimitKey};
+    pub use crate::server::conditional::CachePolicy;
+    pub use crate::server::compression::{CompressionConfig, Encoding};
+    pub use crate::server::rate_limit::store::{Quota, RateLimitStore, MemoryRateLimitStore};
+    pub use crate::test::{TestClient, TestResponse};
+    pub use crate::server::mount::actix_scope;
+    #[cfg(feature = "tower")]
+    pub use crate::server::tower::TowerService;
     pub use teo_runtime::namespace::Namespace;
     pub extern crate teo_result;
     pub use teo_result::{Error, Result, ResultExt};
@@ -47,6 +68,7 @@ pub mod prelude {
     pub use teo_runtime::pipeline::ctx::extract::ExtractFromPipelineCtx;
     pub use teo_runtime::middleware::middleware::middleware_wrap_fn;
     pub use teo_runtime::connection::transaction::ExtractFromTransactionCtx;
+    pub use crate::app::callbacks::args::{Args, ExtractFromCallbackCtx, ProgramArgs};
     pub use teo_runtime::arguments::Arguments;
     pub use teo_runtime::middleware::next::Next;
     pub use teo_runtime::middleware::middleware::Middleware;
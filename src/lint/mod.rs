@@ -0,0 +1,51 @@
+pub(crate) mod rules;
+pub(crate) mod output;
+
+use std::collections::BTreeMap;
+use teo_result::{Error, Result};
+use crate::app::ctx::Ctx;
+use crate::cli::command::LintCommand;
+use crate::cli::entrance::Entrance;
+use crate::cli::outcome::Outcome;
+use crate::lint::output::{format_diagnostics, LintFormat};
+use crate::lint::rules::{check, rule, RuleCtx, Severity};
+
+/// Lint the parsed schema and print the report. The outcome is a failure
+/// when errors are found, or warnings are found and `--deny-warnings` is set.
+pub(crate) fn lint(ctx: &Ctx, command: &LintCommand) -> Result<Outcome> {
+    let format = match LintFormat::from_name(&command.format) {
+        Some(format) => format,
+        None => Err(Error::new(format!("unknown lint format '{}'", command.format)))?,
+    };
+    let mut severities = BTreeMap::new();
+    for setting in &command.rules {
+        let (name, severity) = match setting.split_once('=') {
+            Some((name, severity)) => (name.trim(), severity.trim()),
+            None => Err(Error::new(format!("invalid rule setting '{}', expect NAME=off|warning|error", setting)))?,
+        };
+        if rule(name).is_none() {
+            Err(Error::new(format!("unknown lint rule '{}'", name)))?
+        }
+        match Severity::from_name(severity) {
+            Some(severity) => severities.insert(name.to_owned(), severity),
+            None => Err(Error::new(format!("invalid severity '{}' of lint rule '{}'", severity, name)))?,
+        };
+    }
+    // the CLI entrance doesn't run the app code, so defined handlers are unknown
+    let code_defined_handlers = match ctx.entrance() {
+        Entrance::APP => Some(ctx.code_defined_handlers()),
+        Entrance::CLI => None,
+    };
+    let rule_ctx = RuleCtx { schema: ctx.schema(), code_defined_handlers };
+    let diagnostics = check(&rule_ctx, &severities);
+    println!("{}", format_diagnostics(&diagnostics, format));
+    let fails = diagnostics.iter().filter(|d| match d.severity {
+        Severity::Error => true,
+        Severity::Warning => command.deny_warnings,
+        Severity::Off => false,
+    }).count();
+    if fails > 0 {
+        return Ok(Outcome::Failure(format!("lint found {} problem(s)", fails)));
+    }
+    Ok(Outcome::Success)
+}
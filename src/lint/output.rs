@@ -0,0 +1,102 @@
+use colored::Colorize;
+use serde_json::{json, Value as JsonValue};
+use crate::lint::rules::{Diagnostic, Severity, RULES};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub(crate) enum LintFormat {
+    Text,
+    Json,
+    Sarif,
+}
+
+impl LintFormat {
+
+    pub(crate) fn from_name(s: &str) -> Option<Self> {
+        match s {
+            "text" => Some(LintFormat::Text),
+            "json" => Some(LintFormat::Json),
+            "sarif" => Some(LintFormat::Sarif),
+            _ => None,
+        }
+    }
+}
+
+pub(crate) fn format_diagnostics(diagnostics: &Vec<Diagnostic>, format: LintFormat) -> String {
+    match format {
+        LintFormat::Text => text(diagnostics),
+        LintFormat::Json => serde_json::to_string_pretty(&json(diagnostics)).unwrap(),
+        LintFormat::Sarif => serde_json::to_string_pretty(&sarif(diagnostics)).unwrap(),
+    }
+}
+
+fn count(diagnostics: &Vec<Diagnostic>, severity: Severity) -> usize {
+    diagnostics.iter().filter(|d| d.severity == severity).count()
+}
+
+fn text(diagnostics: &Vec<Diagnostic>) -> String {
+    let mut lines = vec![];
+    for diagnostic in diagnostics {
+        let severity = match diagnostic.severity {
+            Severity::Error => "error".red().bold(),
+            _ => "warning".yellow().bold(),
+        };
+        lines.push(format!(
+            "{}:{}: {}[{}] {}",
+            diagnostic.location.file, diagnostic.location.line, severity, diagnostic.rule, diagnostic.message,
+        ));
+    }
+    let errors = count(diagnostics, Severity::Error);
+    let warnings = count(diagnostics, Severity::Warning);
+    if errors == 0 && warnings == 0 {
+        lines.push("no problems found".to_owned());
+    } else {
+        lines.push(format!("{} error(s), {} warning(s)", errors, warnings));
+    }
+    lines.join("\n")
+}
+
+fn json(diagnostics: &Vec<Diagnostic>) -> JsonValue {
+    json!({
+        "diagnostics": diagnostics.iter().map(|d| json!({
+            "rule": d.rule,
+            "severity": d.severity.as_str(),
+            "message": d.message,
+            "file": d.location.file,
+            "line": d.location.line,
+        })).collect::<Vec<JsonValue>>(),
+        "errors": count(diagnostics, Severity::Error),
+        "warnings": count(diagnostics, Severity::Warning),
+    })
+}
+
+/// SARIF 2.1.0, which code scanning services of CI providers accept.
+fn sarif(diagnostics: &Vec<Diagnostic>) -> JsonValue {
+    json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "teo lint",
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "rules": RULES.iter().map(|r| json!({
+                        "id": r.name,
+                        "shortDescription": { "text": r.description },
+                        "defaultConfiguration": { "level": r.default_severity.as_str() },
+                    })).collect::<Vec<JsonValue>>(),
+                }
+            },
+            "results": diagnostics.iter().map(|d| json!({
+                "ruleId": d.rule,
+                "level": d.severity.as_str(),
+                "message": { "text": d.message },
+                "locations": [{
+                    "physicalLocation": {
+                        "artifactLocation": { "uri": d.location.file },
+                        "region": { "startLine": d.location.line },
+                    }
+                }],
+            })).collect::<Vec<JsonValue>>(),
+        }]
+    })
+}
@@ -0,0 +1,544 @@
+use std::collections::{BTreeMap, BTreeSet};
+use teo_parser::ast::decorator::Decorator;
+use teo_parser::ast::expression::{Expression, ExpressionKind};
+use teo_parser::ast::handler::HandlerDeclaration;
+use teo_parser::ast::model::Model;
+use teo_parser::ast::schema::Schema;
+use teo_parser::r#type::Type;
+use teo_parser::traits::identifiable::Identifiable;
+use teo_parser::traits::named_identifiable::NamedIdentifiable;
+use teo_parser::traits::node_trait::NodeTrait;
+use teo_parser::traits::resolved::Resolve;
+use teo_teon::value::Value;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Severity {
+    Off,
+    Warning,
+    Error,
+}
+
+impl Severity {
+
+    pub(crate) fn from_name(s: &str) -> Option<Self> {
+        match s {
+            "off" => Some(Severity::Off),
+            "warning" | "warn" => Some(Severity::Warning),
+            "error" => Some(Severity::Error),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Off => "off",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        }
+    }
+}
+
+/// Where a declaration is found in the schema sources.
+#[derive(Debug, Clone)]
+pub(crate) struct Location {
+    pub(crate) file: String,
+    pub(crate) line: usize,
+}
+
+pub(crate) struct Rule {
+    pub(crate) name: &'static str,
+    pub(crate) description: &'static str,
+    pub(crate) default_severity: Severity,
+    check: fn(&RuleCtx, &mut Vec<(String, Location)>),
+}
+
+/// What rules look at: the parsed schema, builtin sources included, and the
+/// handlers defined in code. Handlers are unknown when linting from the CLI
+/// entrance, which doesn't run the app code.
+pub(crate) struct RuleCtx<'a> {
+    pub(crate) schema: &'a Schema,
+    pub(crate) code_defined_handlers: Option<&'a BTreeSet<String>>,
+}
+
+#[derive(Debug)]
+pub(crate) struct Diagnostic {
+    pub(crate) rule: &'static str,
+    pub(crate) severity: Severity,
+    pub(crate) message: String,
+    pub(crate) location: Location,
+}
+
+pub(crate) static RULES: &[Rule] = &[
+    Rule {
+        name: "relation-foreign-key-index",
+        description: "Foreign keys of relations should be indexed.",
+        default_severity: Severity::Warning,
+        check: relation_foreign_key_index,
+    },
+    Rule {
+        name: "naming-convention",
+        description: "Models, enums and interfaces are PascalCase. Fields, enum members and handlers are camelCase.",
+        default_severity: Severity::Warning,
+        check: naming_convention,
+    },
+    Rule {
+        name: "unused-enum",
+        description: "Enums should be referenced.",
+        default_severity: Severity::Warning,
+        check: unused_enum,
+    },
+    Rule {
+        name: "unused-interface",
+        description: "Interfaces should be referenced.",
+        default_severity: Severity::Warning,
+        check: unused_interface,
+    },
+    Rule {
+        name: "unimplemented-handler",
+        description: "Declared handlers should be defined in code.",
+        default_severity: Severity::Error,
+        check: unimplemented_handler,
+    },
+    Rule {
+        name: "data-set-reference",
+        description: "Data set records should only reference existing records.",
+        default_severity: Severity::Error,
+        check: data_set_reference,
+    },
+    Rule {
+        name: "risky-on-set",
+        description: "`@onSet` pipelines should not generate values, which change on every update.",
+        default_severity: Severity::Warning,
+        check: risky_on_set,
+    },
+];
+
+pub(crate) fn rule(name: &str) -> Option<&'static Rule> {
+    RULES.iter().find(|r| r.name == name)
+}
+
+/// Run every rule which is not turned off. `severities` overrides default
+/// severities by rule name.
+pub(crate) fn check(ctx: &RuleCtx, severities: &BTreeMap<String, Severity>) -> Vec<Diagnostic> {
+    let mut result = vec![];
+    for rule in RULES {
+        let severity = severities.get(rule.name).cloned().unwrap_or(rule.default_severity);
+        if severity == Severity::Off {
+            continue;
+        }
+        let mut found = vec![];
+        (rule.check)(ctx, &mut found);
+        result.extend(found.into_iter().map(|(message, location)| Diagnostic { rule: rule.name, severity, message, location }));
+    }
+    result.sort_by(|a, b| (&a.location.file, a.location.line).cmp(&(&b.location.file, b.location.line)));
+    result
+}
+
+static INDEX_DECORATORS: &[&str] = &["id", "unique", "index"];
+
+fn relation_foreign_key_index(ctx: &RuleCtx, found: &mut Vec<(String, Location)>) {
+    for model in user_models(ctx.schema) {
+        for field in model.fields() {
+            let Some(relation) = decorator(field.decorators(), "relation") else { continue };
+            let foreign_keys = argument(relation, "fields").map(variant_names).unwrap_or_default();
+            if foreign_keys.is_empty() || is_indexed(model, &foreign_keys) {
+                continue;
+            }
+            found.push((format!("relation `{}.{}` has no index on `{}`", model.name(), field.name(), foreign_keys.join(", ")), location(ctx.schema, field)));
+        }
+    }
+}
+
+fn is_indexed(model: &Model, fields: &Vec<String>) -> bool {
+    if fields.len() == 1 {
+        if let Some(field) = model.fields().find(|f| f.name() == fields[0]) {
+            if INDEX_DECORATORS.iter().any(|name| decorator(field.decorators(), name).is_some()) {
+                return true;
+            }
+        }
+    }
+    model.decorators()
+        .filter(|d| INDEX_DECORATORS.contains(&decorator_name(d)))
+        .any(|d| first_argument(d).map_or(false, |keys| variant_names(keys).starts_with(fields)))
+}
+
+fn naming_convention(ctx: &RuleCtx, found: &mut Vec<(String, Location)>) {
+    let schema = ctx.schema;
+    let mut pascal = |kind: &str, name: &str, location: Location| if !is_pascal_case(name) {
+        found.push((format!("{} `{}` should be PascalCase", kind, name), location));
+    };
+    for model in user_models(schema) {
+        pascal("model", model.name(), location(schema, model));
+    }
+    for r#enum in schema.enums().into_iter().filter(|e| is_user_declared(schema, *e)) {
+        pascal("enum", r#enum.name(), location(schema, r#enum));
+    }
+    for interface in schema.interfaces().into_iter().filter(|i| is_user_declared(schema, *i)) {
+        pascal("interface", interface.name(), location(schema, interface));
+    }
+    let mut camel = |kind: &str, name: &str, location: Location| if !is_camel_case(name) {
+        found.push((format!("{} `{}` should be camelCase", kind, name), location));
+    };
+    for model in user_models(schema) {
+        for field in model.fields() {
+            camel("field", field.name(), location(schema, field));
+        }
+    }
+    for r#enum in schema.enums().into_iter().filter(|e| is_user_declared(schema, *e)) {
+        for member in r#enum.members() {
+            camel("enum member", member.name(), location(schema, member));
+        }
+    }
+    for handler in handler_declarations(schema).values() {
+        camel("handler", handler.name(), location(schema, *handler));
+    }
+}
+
+fn is_pascal_case(name: &str) -> bool {
+    name.chars().next().map_or(false, |c| c.is_ascii_uppercase()) && name.chars().all(|c| c.is_ascii_alphanumeric())
+}
+
+fn is_camel_case(name: &str) -> bool {
+    name.chars().next().map_or(false, |c| c.is_ascii_lowercase()) && name.chars().all(|c| c.is_ascii_alphanumeric())
+}
+
+fn unused_enum(ctx: &RuleCtx, found: &mut Vec<(String, Location)>) {
+    let used = used_types(ctx.schema);
+    for r#enum in ctx.schema.enums().into_iter().filter(|e| is_user_declared(ctx.schema, *e)) {
+        if !used.contains(&r#enum.string_path().join(".")) {
+            found.push((format!("enum `{}` is never used", r#enum.name()), location(ctx.schema, r#enum)));
+        }
+    }
+}
+
+fn unused_interface(ctx: &RuleCtx, found: &mut Vec<(String, Location)>) {
+    let used = used_types(ctx.schema);
+    for interface in ctx.schema.interfaces().into_iter().filter(|i| is_user_declared(ctx.schema, *i)) {
+        if !used.contains(&interface.string_path().join(".")) {
+            found.push((format!("interface `{}` is never used", interface.name()), location(ctx.schema, interface)));
+        }
+    }
+}
+
+/// Paths of the enums and interfaces referenced by resolved types of model
+/// fields, interface fields and extends, and handler inputs and outputs.
+fn used_types(schema: &Schema) -> BTreeSet<String> {
+    let mut used = BTreeSet::new();
+    for model in schema.models() {
+        for field in model.fields() {
+            collect_type_references(field.type_expr().resolved(), &mut used);
+        }
+    }
+    for interface in schema.interfaces() {
+        for field in interface.fields() {
+            collect_type_references(field.type_expr().resolved(), &mut used);
+        }
+        for extend in interface.extends() {
+            collect_type_references(extend.resolved(), &mut used);
+        }
+    }
+    for handler in handler_declarations(schema).values() {
+        if let Some(input_type) = handler.input_type() {
+            collect_type_references(input_type.resolved(), &mut used);
+        }
+        collect_type_references(handler.output_type().resolved(), &mut used);
+    }
+    used
+}
+
+fn collect_type_references(r#type: &Type, used: &mut BTreeSet<String>) {
+    match r#type {
+        Type::EnumVariant(reference) => {
+            used.insert(reference.string_path().join("."));
+        }
+        Type::InterfaceObject(reference, generics) => {
+            used.insert(reference.string_path().join("."));
+            for generic in generics {
+                collect_type_references(generic, used);
+            }
+        }
+        Type::Optional(inner) | Type::Array(inner) | Type::Dictionary(inner) => collect_type_references(inner, used),
+        Type::Union(types) | Type::Tuple(types) => for r#type in types {
+            collect_type_references(r#type, used);
+        },
+        _ => (),
+    }
+}
+
+fn unimplemented_handler(ctx: &RuleCtx, found: &mut Vec<(String, Location)>) {
+    let Some(code_defined_handlers) = ctx.code_defined_handlers else { return };
+    for (key, handler) in handler_declarations(ctx.schema) {
+        if !code_defined_handlers.contains(&key) {
+            found.push((format!("handler `{}` is declared but not implemented", key), location(ctx.schema, handler)));
+        }
+    }
+}
+
+/// Handlers declared in the schema sources by dotted path, e.g.
+/// `Artist.publish`, those of handler groups included.
+pub(crate) fn handler_declarations(schema: &Schema) -> BTreeMap<String, &HandlerDeclaration> {
+    let mut result = BTreeMap::new();
+    let groups = schema.handler_groups().into_iter().flat_map(|group| group.handler_declarations());
+    for handler in schema.handler_declarations().into_iter().chain(groups) {
+        if is_user_declared(schema, handler) {
+            result.insert(handler.string_path().join("."), handler);
+        }
+    }
+    result
+}
+
+fn data_set_reference(ctx: &RuleCtx, found: &mut Vec<(String, Location)>) {
+    let schema = ctx.schema;
+    for data_set in schema.data_sets().into_iter().filter(|d| is_user_declared(schema, *d)) {
+        let groups: Vec<_> = data_set.groups().filter_map(|group| {
+            let names = group.identifier_path().names();
+            let model = schema.models().into_iter().find(|model| ends_with(model.string_path(), &names))?;
+            Some((model, group))
+        }).collect();
+        for (model, group) in &groups {
+            for record in group.records() {
+                let Some(values) = record.dictionary().resolved().value.as_ref().and_then(Value::as_dictionary) else { continue };
+                for field in model.fields() {
+                    if decorator(field.decorators(), "relation").is_none() {
+                        continue;
+                    }
+                    let Some(value) = values.get(field.name()) else { continue };
+                    let Some(target_path) = model_reference(field.type_expr().resolved()) else { continue };
+                    let target = groups.iter().find(|(m, _)| m.string_path() == target_path);
+                    let mut names = vec![];
+                    collect_variant_names(value, &mut names);
+                    for name in names {
+                        if !target.map_or(false, |(_, g)| g.records().any(|r| r.name() == name)) {
+                            found.push((format!(
+                                "record `{}` of `{}` in data set `{}` references nonexistent `{}` record `{}`",
+                                record.name(), model.name(), data_set.name(), target_path.last().unwrap(), name,
+                            ), location(schema, record)));
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The path of the model a relation field refers to.
+fn model_reference(r#type: &Type) -> Option<&Vec<String>> {
+    match r#type {
+        Type::ModelObject(reference) => Some(reference.string_path()),
+        Type::Optional(inner) | Type::Array(inner) => model_reference(inner),
+        _ => None,
+    }
+}
+
+fn ends_with(path: &Vec<String>, names: &Vec<&str>) -> bool {
+    path.len() >= names.len() && path.iter().rev().zip(names.iter().rev()).all(|(a, b)| a == b)
+}
+
+static GENERATING_PIPELINE_ITEMS: &[&str] = &["now", "today", "uuid", "cuid", "cuid2", "slug", "randomInt", "randomFloat", "randomDigits"];
+
+fn risky_on_set(ctx: &RuleCtx, found: &mut Vec<(String, Location)>) {
+    for model in user_models(ctx.schema) {
+        for field in model.fields() {
+            let Some(on_set) = decorator(field.decorators(), "onSet") else { continue };
+            let Some(argument_list) = on_set.argument_list() else { continue };
+            for argument in argument_list.arguments() {
+                for item in pipeline_items(argument.value()) {
+                    if GENERATING_PIPELINE_ITEMS.contains(&item) {
+                        found.push((format!(
+                            "`@onSet` of `{}.{}` uses `${}`, which overwrites the value on every update",
+                            model.name(), field.name(), item,
+                        ), location(ctx.schema, field)));
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The items of a pipeline literal, e.g. `now` and `toString` for
+/// `$now.toString`.
+fn pipeline_items(expression: &Expression) -> Vec<&str> {
+    let ExpressionKind::Pipeline(pipeline) = &expression.kind else {
+        return vec![];
+    };
+    pipeline.unit().expressions().filter_map(|item| match &item.kind {
+        ExpressionKind::Identifier(identifier) => Some(identifier.name()),
+        ExpressionKind::Call(call) => Some(call.identifier().name()),
+        _ => None,
+    }).collect()
+}
+
+fn user_models(schema: &Schema) -> impl Iterator<Item = &Model> {
+    schema.models().into_iter().filter(|model| is_user_declared(schema, *model))
+}
+
+/// Whether `node` is declared in the schema sources rather than the
+/// standard library.
+pub(crate) fn is_user_declared<N: Identifiable>(schema: &Schema, node: &N) -> bool {
+    schema.source(node.source_id()).map_or(false, |source| !source.builtin)
+}
+
+pub(crate) fn location<N: Identifiable + NodeTrait>(schema: &Schema, node: &N) -> Location {
+    Location {
+        file: schema.source(node.source_id()).map(|source| source.file_path.clone()).unwrap_or_default(),
+        line: node.span().start_position.0,
+    }
+}
+
+fn decorator_name(decorator: &Decorator) -> &str {
+    decorator.identifier_path().names().last().cloned().unwrap_or_default()
+}
+
+pub(crate) fn decorator<'a>(mut decorators: impl Iterator<Item = &'a Decorator>, name: &str) -> Option<&'a Decorator> {
+    decorators.find(|d| decorator_name(d) == name)
+}
+
+/// The value of the argument named `name`.
+pub(crate) fn argument<'a>(decorator: &'a Decorator, name: &str) -> Option<&'a Expression> {
+    decorator.argument_list()?.arguments()
+        .find(|argument| argument.name().map_or(false, |n| n.name() == name))
+        .map(|argument| argument.value())
+}
+
+fn first_argument(decorator: &Decorator) -> Option<&Expression> {
+    decorator.argument_list()?.arguments().next().map(|argument| argument.value())
+}
+
+/// Enum variant literals of a resolved expression, e.g. `authorId` for
+/// `.authorId` or `[.authorId]`.
+fn variant_names(expression: &Expression) -> Vec<String> {
+    let mut names = vec![];
+    if let Some(value) = expression.resolved().value.as_ref() {
+        collect_variant_names(value, &mut names);
+    }
+    names
+}
+
+fn collect_variant_names(value: &Value, names: &mut Vec<String>) {
+    match value {
+        Value::EnumVariant(variant) => names.push(variant.value.clone()),
+        Value::Array(items) => for item in items {
+            collect_variant_names(item, names);
+        },
+        _ => (),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+    use crate::test::schema::parse_schema_source;
+    use std::collections::BTreeMap;
+    use super::{check, rule, RuleCtx};
+
+    const CONNECTOR: &str = "connector {\n  provider .sqlite\n  url \"sqlite::memory:\"\n}\n";
+
+    fn messages(rule_name: &str, source: &str, handlers: &[&str]) -> Vec<String> {
+        let schema = parse_schema_source(&format!("{}{}", CONNECTOR, source));
+        let code_defined_handlers: BTreeSet<String> = handlers.iter().map(|h| h.to_string()).collect();
+        let ctx = RuleCtx { schema: &schema, code_defined_handlers: Some(&code_defined_handlers) };
+        let mut found = vec![];
+        (rule(rule_name).unwrap().check)(&ctx, &mut found);
+        found.into_iter().map(|(message, _)| message).collect()
+    }
+
+    const POSTS: &str = r#"
+model User {
+  @id @autoIncrement
+  id: Int
+  @relation(fields: .id, references: .authorId)
+  posts: Post[]
+}
+
+model Post {
+  @id @autoIncrement
+  id: Int
+  authorId: Int
+  @relation(fields: .authorId, references: .id)
+  author: User
+}
+"#;
+
+    #[test]
+    fn relation_foreign_key_index() {
+        assert_eq!(messages("relation-foreign-key-index", POSTS, &[]), vec!["relation `Post.author` has no index on `authorId`"]);
+        let indexed = POSTS.replace("  authorId: Int", "  @index\n  authorId: Int");
+        assert!(messages("relation-foreign-key-index", &indexed, &[]).is_empty());
+    }
+
+    #[test]
+    fn naming_convention() {
+        let source = "model user_account {\n  @id @autoIncrement\n  id: Int\n  FullName: String\n}\n\nenum Role {\n  admin\n  Guest\n}\n";
+        assert_eq!(messages("naming-convention", source, &[]), vec![
+            "model `user_account` should be PascalCase",
+            "field `FullName` should be camelCase",
+            "enum member `Guest` should be camelCase",
+        ]);
+    }
+
+    #[test]
+    fn unused_enum() {
+        let source = "enum Role {\n  admin\n}\n\nenum Color {\n  red\n}\n\nmodel User {\n  @id @autoIncrement\n  id: Int\n  role: Role\n}\n";
+        assert_eq!(messages("unused-enum", source, &[]), vec!["enum `Color` is never used"]);
+    }
+
+    #[test]
+    fn unused_enum_ignores_comments_and_strings() {
+        let source = "// Color is not used\nenum Color {\n  red\n}\n\nmodel User {\n  @id @autoIncrement\n  id: Int\n  @default(\"Color\")\n  name: String\n}\n";
+        assert_eq!(messages("unused-enum", source, &[]), vec!["enum `Color` is never used"]);
+    }
+
+    #[test]
+    fn unused_interface() {
+        let source = "interface EchoInput {\n  message: String\n}\n\ninterface Unused {\n  message: String\n}\n\n@map(.post, \"/echo\")\ndeclare nonapi handler echo(EchoInput): Any\n";
+        assert_eq!(messages("unused-interface", source, &["echo"]), vec!["interface `Unused` is never used"]);
+    }
+
+    #[test]
+    fn unimplemented_handler() {
+        let source = "@map(.get, \"/a\")\ndeclare nonapi handler a(): Any\n\n@map(.get, \"/b\")\ndeclare nonapi handler b(): Any\n";
+        assert_eq!(messages("unimplemented-handler", source, &["a"]), vec!["handler `b` is declared but not implemented"]);
+    }
+
+    #[test]
+    fn unimplemented_handler_is_skipped_without_app_code() {
+        let source = "@map(.get, \"/a\")\ndeclare nonapi handler a(): Any\n";
+        let schema = parse_schema_source(&format!("{}{}", CONNECTOR, source));
+        let ctx = RuleCtx { schema: &schema, code_defined_handlers: None };
+        assert!(check(&ctx, &BTreeMap::new()).iter().all(|diagnostic| diagnostic.rule != "unimplemented-handler"));
+    }
+
+    #[test]
+    fn data_set_reference() {
+        let source = format!("{}{}", POSTS, r#"
+dataset default {
+  group User {
+    record john {
+      "id": 1,
+    }
+  }
+  group Post {
+    record first {
+      "id": 1,
+      "author": .john,
+    }
+    record second {
+      "id": 2,
+      "author": .peter,
+    }
+  }
+}
+"#);
+        assert_eq!(messages("data-set-reference", &source, &[]), vec![
+            "record `second` of `Post` in data set `default` references nonexistent `User` record `peter`",
+        ]);
+    }
+
+    #[test]
+    fn risky_on_set() {
+        let source = "model Post {\n  @id @autoIncrement\n  id: Int\n  @onSave($now)\n  savedAt: DateTime\n  @onSet($now)\n  setAt: DateTime\n}\n";
+        assert_eq!(messages("risky-on-set", source, &[]), vec![
+            "`@onSet` of `Post.setAt` uses `$now`, which overwrites the value on every update",
+        ]);
+    }
+}
@@ -13,6 +13,14 @@ pub fn info_message(content: impl AsRef<str>) {
     println!("{} {}", timestamp(), content.as_ref())
 }
 
+pub fn warning_message(content: impl AsRef<str>) {
+    eprintln!("{} {} {}", timestamp(), "warning".yellow().bold(), content.as_ref())
+}
+
+pub fn error_message(content: impl AsRef<str>) {
+    eprintln!("{} {} {}", timestamp(), "error".red().bold(), content.as_ref())
+}
+
 pub fn request_message(
     time_elapsed: Duration,
     method: &str,
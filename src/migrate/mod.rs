@@ -1,8 +1,8 @@
-use teo_result::{Error, Result};
+use teo_result::Result;
 use crate::app::ctx::Ctx;
 
-pub async fn migrate(dry_run: bool, reset: bool, silent: bool) -> Result<()> {
-    let ctx = Ctx::conn_ctx();
+pub async fn migrate(ctx: &Ctx, dry_run: bool, reset: bool, silent: bool) -> Result<()> {
+    let ctx = ctx.conn_ctx()?;
     for (namespace_path, connection) in ctx.connections_iter() {
         let namespace = ctx.namespace().namespace_at_path(&namespace_path.iter().map(AsRef::as_ref).collect()).unwrap();
         let transaction = connection.no_transaction().await?;
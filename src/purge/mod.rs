@@ -1,8 +1,25 @@
-use teo_result::Result;
+use std::io::{stdin, stdout, Write};
+use key_path::path;
+use teo_result::{Error, Result};
+use teo_runtime::connection::transaction;
+use teo_runtime::database::database::Database;
+use teo_runtime::model::Model;
+use teo_runtime::schema::load::load_data_sets::load_data_sets;
+use teo_teon::teon;
+use teo_teon::value::Value;
 use crate::app::ctx::Ctx;
+use crate::app::database::sql::{quote_identifier, quote_string};
+use crate::cli::command::{PurgeCommand, SeedCommandAction};
+use crate::cli::outcome::Outcome;
+use crate::message::info_message;
+use crate::seeder::seed::seed;
 
-pub(crate) async fn purge() -> Result<()> {
-    let ctx = Ctx::conn_ctx();
+/// What `--namespace` calls the main namespace.
+const MAIN_NAMESPACE: &str = "main";
+
+/// Clear every model under every connector.
+pub(crate) async fn purge(ctx: &Ctx) -> Result<()> {
+    let ctx = ctx.conn_ctx()?;
     for (namespace_path, connection) in ctx.connections_iter() {
         let namespace = ctx.namespace().namespace_at_path(&namespace_path.iter().map(AsRef::as_ref).collect()).unwrap();
         let transaction = connection.no_transaction().await?;
@@ -10,3 +27,180 @@ pub(crate) async fn purge() -> Result<()> {
     }
     Ok(())
 }
+
+/// Clear the models selected by `teo purge`.
+///
+/// The row counts of the selected models are shown and confirmed before
+/// anything is removed, unless `--yes` is given. In the `production`
+/// environment nothing is removed without `--force`.
+pub(crate) async fn purge_selected(ctx: &Ctx, command: &PurgeCommand) -> Result<Outcome> {
+    check_environment(ctx.environment(), command.force)?;
+    let conn_ctx = ctx.conn_ctx()?;
+    let transaction_ctx = transaction::Ctx::new(conn_ctx.clone());
+    let mut selected = vec![];
+    for (namespace_path, connection) in conn_ctx.connections_iter() {
+        let namespace = conn_ctx.namespace().namespace_at_path(&namespace_path.iter().map(AsRef::as_ref).collect()).unwrap();
+        let models: Vec<&Model> = namespace.models_under_connector()
+            .into_iter()
+            .filter(|model| is_selected(&model.path, command))
+            .collect();
+        if !models.is_empty() {
+            selected.push((namespace, connection.clone(), models));
+        }
+    }
+    if selected.is_empty() {
+        Err(Error::new("no models match the given namespaces and models"))?
+    }
+    if !command.yes {
+        println!("The following models will be purged:");
+        for model in selected.iter().flat_map(|(_, _, models)| models) {
+            let count = transaction_ctx.count(*model, &teon!({}), path![]).await?;
+            println!("  {:<40} {} row(s)", model.path.join("."), count);
+        }
+        if !confirm("Purge these models? [y/N] ")? {
+            return Ok(Outcome::Failure("purge is cancelled".to_owned()));
+        }
+    }
+    for (namespace, connection, models) in &selected {
+        let transaction = connection.no_transaction().await?;
+        transaction.purge(models.clone()).await?;
+        if command.reset_sequences {
+            if let Some(database) = namespace.database.as_ref() {
+                for model in models {
+                    for sql in reset_sequence_statements(database, model) {
+                        transaction.query_raw(&Value::String(sql)).await?;
+                    }
+                }
+            }
+        }
+    }
+    if !ctx.cli().silent {
+        info_message(format!("purged {} model(s)", selected.iter().map(|(_, _, models)| models.len()).sum::<usize>()));
+    }
+    if command.reseed {
+        let data_sets = load_data_sets(ctx.main_namespace(), ctx.autoseed_data_sets(), false, ctx.schema())?;
+        seed(SeedCommandAction::Reseed, data_sets, transaction_ctx).await?;
+    }
+    Ok(Outcome::Success)
+}
+
+fn check_environment(environment: Option<&str>, force: bool) -> Result<()> {
+    if environment == Some("production") && !force {
+        Err(Error::new("refusing to purge in the production environment, pass --force to do it anyway"))?
+    }
+    Ok(())
+}
+
+/// Whether the model at `model_path` is selected by the `--namespace` and
+/// `--model` filters. `--namespace main` selects the models declared in the
+/// main namespace itself.
+fn is_selected(model_path: &[String], command: &PurgeCommand) -> bool {
+    let Some((name, namespace_path)) = model_path.split_last() else {
+        return false;
+    };
+    let dotted_namespace_path = namespace_path.join(".");
+    let namespace_matches = command.namespaces.is_empty() || command.namespaces.iter().any(|namespace| {
+        (namespace == MAIN_NAMESPACE && namespace_path.is_empty())
+            || dotted_namespace_path == *namespace
+            || dotted_namespace_path.starts_with(&format!("{}.", namespace))
+    });
+    let model_matches = command.models.is_empty() || command.models.iter().any(|model| {
+        model_path.join(".") == *model || name == model
+    });
+    namespace_matches && model_matches
+}
+
+fn confirm(prompt: &str) -> Result<bool> {
+    print!("{}", prompt);
+    let _ = stdout().flush();
+    let mut answer = String::new();
+    if let Err(err) = stdin().read_line(&mut answer) {
+        Err(Error::new(format!("cannot read the confirmation: {}", err)))?
+    }
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+/// Statements restarting the auto increment counters of `model`'s table.
+fn reset_sequence_statements(database: &Database, model: &Model) -> Vec<String> {
+    let columns: Vec<&str> = model.fields.values().filter(|f| f.auto_increment).map(|f| f.column_name.as_str()).collect();
+    if columns.is_empty() {
+        return vec![];
+    }
+    reset_statements(database, &model.table_name, &columns)
+}
+
+fn reset_statements(database: &Database, table: &str, columns: &[&str]) -> Vec<String> {
+    match database {
+        Database::SQLite => vec![format!("DELETE FROM sqlite_sequence WHERE name = {}", quote_string(database, table))],
+        Database::MySQL => vec![format!("ALTER TABLE {} AUTO_INCREMENT = 1", quote_identifier(database, table))],
+        Database::PostgreSQL => columns.iter().map(|column| format!(
+            "SELECT setval(pg_get_serial_sequence({}, {}), 1, false)",
+            quote_string(database, &quote_identifier(database, table)), quote_string(database, column),
+        )).collect(),
+        Database::MongoDB => vec![],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use teo_runtime::database::database::Database;
+    use crate::cli::command::PurgeCommand;
+    use super::{check_environment, is_selected, reset_statements};
+
+    fn command(namespaces: &[&str], models: &[&str]) -> PurgeCommand {
+        PurgeCommand {
+            namespaces: namespaces.iter().map(|n| n.to_string()).collect(),
+            models: models.iter().map(|m| m.to_string()).collect(),
+            yes: true,
+            force: false,
+            reset_sequences: false,
+            reseed: false,
+        }
+    }
+
+    fn path(path: &str) -> Vec<String> {
+        path.split('.').map(ToOwned::to_owned).collect()
+    }
+
+    #[test]
+    fn refuses_production_without_force() {
+        assert!(check_environment(Some("production"), false).is_err());
+        assert!(check_environment(Some("production"), true).is_ok());
+        assert!(check_environment(Some("staging"), false).is_ok());
+        assert!(check_environment(None, false).is_ok());
+    }
+
+    #[test]
+    fn selects_by_namespace() {
+        let filter = command(&["blog"], &[]);
+        assert!(is_selected(&path("blog.Post"), &filter));
+        assert!(is_selected(&path("blog.admin.Log"), &filter));
+        assert!(!is_selected(&path("blogs.Post"), &filter));
+        assert!(!is_selected(&path("User"), &filter));
+        let filter = command(&["main"], &[]);
+        assert!(is_selected(&path("User"), &filter));
+        assert!(!is_selected(&path("blog.Post"), &filter));
+    }
+
+    #[test]
+    fn selects_by_model() {
+        let filter = command(&[], &["Post", "blog.admin.Log"]);
+        assert!(is_selected(&path("blog.Post"), &filter));
+        assert!(is_selected(&path("blog.admin.Log"), &filter));
+        assert!(!is_selected(&path("Log"), &filter));
+        assert!(!is_selected(&path("User"), &filter));
+        let filter = command(&["main"], &["User"]);
+        assert!(is_selected(&path("User"), &filter));
+        assert!(!is_selected(&path("blog.User"), &filter));
+    }
+
+    #[test]
+    fn escapes_table_names() {
+        assert_eq!(reset_statements(&Database::SQLite, "a'b", &["id"]), vec!["DELETE FROM sqlite_sequence WHERE name = 'a''b'"]);
+        assert_eq!(reset_statements(&Database::MySQL, "a`b", &["id"]), vec!["ALTER TABLE `a``b` AUTO_INCREMENT = 1"]);
+        assert_eq!(
+            reset_statements(&Database::PostgreSQL, "a\"b", &["i'd"]),
+            vec!["SELECT setval(pg_get_serial_sequence('\"a\"\"b\"', 'i''d'), 1, false)"],
+        );
+    }
+}
@@ -0,0 +1,195 @@
+use colored::Colorize;
+use serde_json::{json, Value as JsonValue};
+use teo_parser::ast::handler::HandlerInputFormat;
+use teo_result::{Error, Result};
+use std::collections::BTreeMap;
+use teo_runtime::handler::Handler;
+use teo_runtime::handler::handler::Method;
+use teo_runtime::handler::r#match::HandlerMatch;
+use teo_runtime::namespace::Namespace;
+use crate::app::ctx::Ctx;
+use crate::cli::command::RoutesCommand;
+
+/// Builtin actions every model accepts, in the order the server dispatches
+/// them.
+const BUILTIN_ACTIONS: [&str; 15] = [
+    "findMany", "findFirst", "findUnique", "create", "delete", "update", "upsert", "copy",
+    "createMany", "updateMany", "copyMany", "deleteMany", "count", "aggregate", "groupBy",
+];
+
+/// An HTTP endpoint of the app.
+#[derive(Debug)]
+pub(crate) struct Route {
+    pub(crate) method: &'static str,
+    pub(crate) path: String,
+    /// The dotted handler key, e.g. `blog.Post.findMany`.
+    pub(crate) key: String,
+    pub(crate) namespace: Vec<String>,
+    /// The model of a model handler group.
+    pub(crate) model: Option<String>,
+    pub(crate) builtin: bool,
+    pub(crate) format: &'static str,
+    /// Middlewares from the outermost namespace inwards.
+    pub(crate) middlewares: Vec<String>,
+}
+
+/// Print every endpoint of the main namespace and its child namespaces.
+///
+/// Custom handlers are listed as the handler map of the main namespace
+/// matches them. Builtin actions are matched by the default route of their
+/// model, unless a custom handler of the model handler group shadows them,
+/// as they are when serving.
+pub(crate) fn routes(ctx: &Ctx, command: &RoutesCommand) -> Result<()> {
+    let main_namespace = ctx.main_namespace();
+    let prefix = main_namespace.server.as_ref().and_then(|s| s.path_prefix.clone()).unwrap_or_default();
+    let prefix = prefix.trim_end_matches('/');
+    let mut middlewares = BTreeMap::new();
+    collect_middlewares(main_namespace, &mut vec![], &vec![], &mut middlewares);
+    let mut routes = vec![];
+    for ((method, url), handler_match) in main_namespace.handler_map.records() {
+        if let Some(route) = custom_route(main_namespace, method, url, handler_match, prefix, &middlewares) {
+            routes.push(route);
+        }
+    }
+    collect_builtin_routes(main_namespace, prefix, &mut vec![], &middlewares, &mut routes);
+    routes.sort_by(|a, b| a.namespace.cmp(&b.namespace));
+    if let Some(namespace) = command.namespace.as_ref() {
+        let filter: Vec<&str> = namespace.split('.').filter(|s| !s.is_empty()).collect();
+        routes.retain(|r| r.namespace.len() >= filter.len() && r.namespace.iter().zip(filter.iter()).all(|(a, b)| a == b));
+    }
+    if let Some(model) = command.model.as_ref() {
+        routes.retain(|r| r.model.as_ref().map_or(false, |m| m == model || model_path(r) == *model));
+    }
+    match command.format.as_str() {
+        "text" => println!("{}", text(&routes)),
+        "json" => println!("{}", serde_json::to_string_pretty(&json(&routes)).unwrap()),
+        format => Err(Error::new(format!("unknown routes format '{}'", format)))?,
+    }
+    Ok(())
+}
+
+fn model_path(route: &Route) -> String {
+    route.namespace.iter().chain(route.model.iter()).cloned().collect::<Vec<String>>().join(".")
+}
+
+/// The middlewares each namespace applies, from the outermost namespace
+/// inwards, by namespace path.
+fn collect_middlewares(namespace: &Namespace, path: &mut Vec<String>, parent: &Vec<String>, result: &mut BTreeMap<Vec<String>, Vec<String>>) {
+    let mut middlewares = parent.clone();
+    if let Some(block) = namespace.middlewares_block.as_ref() {
+        middlewares.extend(block.uses.iter().map(|r#use| r#use.path.join(".")));
+    }
+    for (name, child) in &namespace.namespaces {
+        path.push(name.clone());
+        collect_middlewares(child, path, &middlewares, result);
+        path.pop();
+    }
+    result.insert(path.clone(), middlewares);
+}
+
+fn collect_builtin_routes(namespace: &Namespace, prefix: &str, path: &mut Vec<String>, middlewares: &BTreeMap<Vec<String>, Vec<String>>, routes: &mut Vec<Route>) {
+    for model_name in namespace.models.keys() {
+        let group = namespace.model_handler_groups.get(model_name);
+        for action in BUILTIN_ACTIONS {
+            if group.map_or(false, |g| g.handlers.contains_key(action)) {
+                continue;
+            }
+            let segments: Vec<&str> = path.iter().map(AsRef::as_ref).chain([model_name.as_str(), action]).collect();
+            routes.push(Route {
+                method: "POST",
+                path: format!("{}/{}", prefix, segments.join("/")),
+                key: segments.join("."),
+                namespace: path.clone(),
+                model: Some(model_name.clone()),
+                builtin: true,
+                format: "json",
+                middlewares: middlewares.get(path).cloned().unwrap_or_default(),
+            });
+        }
+    }
+    for (name, child) in &namespace.namespaces {
+        path.push(name.clone());
+        collect_builtin_routes(child, prefix, path, middlewares, routes);
+        path.pop();
+    }
+}
+
+/// The route of a handler map record. The namespace and the handler are
+/// found like the server finds them.
+fn custom_route(
+    main_namespace: &Namespace,
+    method: &Method,
+    url: &str,
+    handler_match: &HandlerMatch,
+    prefix: &str,
+    middlewares: &BTreeMap<Vec<String>, Vec<String>>,
+) -> Option<Route> {
+    let (namespace, namespace_path) = match main_namespace.namespace_at_path(&handler_match.path()) {
+        Some(namespace) => (namespace, handler_match.path()),
+        None => (main_namespace.namespace_at_path(&handler_match.path_without_last())?, handler_match.path_without_last()),
+    };
+    let group_name = handler_match.group_name();
+    let handler_name = handler_match.handler_name();
+    let (handler, group, model): (&Handler, Option<&str>, Option<&str>) = if let Some(group) = namespace.model_handler_groups.get(group_name) {
+        (group.handlers.get(handler_name)?, Some(group_name), Some(group_name))
+    } else if let Some(group) = namespace.handler_groups.get(group_name) {
+        (group.handlers.get(handler_name)?, Some(group_name), None)
+    } else {
+        (namespace.handlers.get(handler_name)?, None, None)
+    };
+    let namespace_path: Vec<String> = namespace_path.iter().map(|s| s.to_string()).collect();
+    Some(Route {
+        method: method_name(method),
+        path: format!("{}/{}", prefix, url.trim_start_matches('/')),
+        key: namespace_path.iter().map(AsRef::as_ref).chain(group).chain([handler_name]).collect::<Vec<&str>>().join("."),
+        model: model.map(ToOwned::to_owned),
+        builtin: false,
+        format: match handler.format {
+            HandlerInputFormat::Json => "json",
+            HandlerInputFormat::Form => "form",
+        },
+        middlewares: middlewares.get(&namespace_path).cloned().unwrap_or_default(),
+        namespace: namespace_path,
+    })
+}
+
+fn method_name(method: &Method) -> &'static str {
+    match method {
+        Method::Get => "GET",
+        Method::Post => "POST",
+        Method::Patch => "PATCH",
+        Method::Put => "PUT",
+        Method::Delete => "DELETE",
+        Method::Options => "OPTIONS",
+    }
+}
+
+fn text(routes: &Vec<Route>) -> String {
+    if routes.is_empty() {
+        return "no routes found".to_owned();
+    }
+    let path_width = routes.iter().map(|r| r.path.len()).max().unwrap_or(0);
+    let key_width = routes.iter().map(|r| r.key.len()).max().unwrap_or(0);
+    routes.iter().map(|route| {
+        let kind = if route.builtin { "builtin" } else { "custom" };
+        let middlewares = if route.middlewares.is_empty() { "".to_owned() } else { format!(" [{}]", route.middlewares.join(", ")) };
+        format!(
+            "{} {:<path_width$}  {:<key_width$}  {:<7} {}{}",
+            format!("{:<7}", route.method).bold(), route.path, route.key, kind, route.format, middlewares.dimmed(),
+            path_width = path_width, key_width = key_width,
+        )
+    }).collect::<Vec<String>>().join("\n")
+}
+
+fn json(routes: &Vec<Route>) -> JsonValue {
+    JsonValue::Array(routes.iter().map(|route| json!({
+        "method": route.method,
+        "path": route.path,
+        "handler": route.key,
+        "namespace": route.namespace,
+        "model": route.model,
+        "kind": if route.builtin { "builtin" } else { "custom" },
+        "format": route.format,
+        "middlewares": route.middlewares,
+    })).collect())
+}
@@ -0,0 +1,220 @@
+use std::io::{Read, Write};
+use actix_http::body::{BodySize, BoxBody, MessageBody};
+use actix_http::header::{self, HeaderValue};
+use actix_web::dev::ServiceResponse;
+use actix_web::{HttpRequest, web};
+use actix_web::web::Bytes;
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use teo_result::{Error, Result};
+use teo_runtime::error_runtime_ext::ErrorRuntimeExt;
+
+/// A content encoding for responses.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Encoding {
+    Zstd,
+    Brotli,
+    Gzip,
+}
+
+impl Encoding {
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Encoding::Zstd => "zstd",
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gzip",
+        }
+    }
+
+    fn compress(&self, bytes: &[u8]) -> std::io::Result<Vec<u8>> {
+        match self {
+            Encoding::Zstd => zstd::stream::encode_all(bytes, 3),
+            Encoding::Brotli => {
+                let mut writer = brotli::CompressorWriter::new(Vec::new(), 4096, 5, 22);
+                writer.write_all(bytes)?;
+                Ok(writer.into_inner())
+            }
+            Encoding::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(bytes)?;
+                encoder.finish()
+            }
+        }
+    }
+}
+
+/// Response compression settings.
+#[derive(Debug, Clone)]
+pub struct CompressionConfig {
+    /// Responses smaller than this are sent as they are.
+    pub min_size: usize,
+    /// Only responses whose content type starts with one of these are
+    /// compressed.
+    pub content_types: Vec<String>,
+    /// Encodings in the order of preference.
+    pub encodings: Vec<Encoding>,
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        Self {
+            min_size: 1024,
+            content_types: vec![
+                "application/json".to_owned(),
+                "application/problem+json".to_owned(),
+                "text/".to_owned(),
+            ],
+            encodings: vec![Encoding::Zstd, Encoding::Brotli, Encoding::Gzip],
+        }
+    }
+}
+
+impl CompressionConfig {
+
+    fn negotiate(&self, accept_encoding: &str) -> Option<Encoding> {
+        let accepted: Vec<(&str, f32)> = accept_encoding.split(',').filter_map(|item| {
+            let mut parts = item.split(';');
+            let name = parts.next()?.trim();
+            let quality = parts.find_map(|p| p.trim().strip_prefix("q=")).and_then(|q| q.parse().ok()).unwrap_or(1.0);
+            Some((name, quality))
+        }).collect();
+        self.encodings.iter().find(|encoding| {
+            accepted.iter().find(|(name, _)| name.eq_ignore_ascii_case(encoding.as_str()))
+                .or(accepted.iter().find(|(name, _)| *name == "*"))
+                .map_or(false, |(_, quality)| *quality > 0.0)
+        }).copied()
+    }
+
+    fn allows_content_type(&self, content_type: &str) -> bool {
+        self.content_types.iter().any(|allowed| content_type.starts_with(allowed.as_str()))
+    }
+}
+
+/// Compress `res` if the client accepts it and `config` allows it.
+pub(crate) async fn compress_response<B>(config: &CompressionConfig, res: ServiceResponse<B>) -> actix_web::Result<ServiceResponse<BoxBody>> where B: MessageBody + 'static {
+    let encoding = match res.request().headers().get(header::ACCEPT_ENCODING).and_then(|v| v.to_str().ok()) {
+        Some(accept_encoding) => config.negotiate(accept_encoding),
+        None => None,
+    };
+    let content_type_allowed = res.response().headers().get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map_or(false, |content_type| config.allows_content_type(content_type));
+    let large_enough = match res.response().body().size() {
+        BodySize::Sized(size) => size as usize >= config.min_size,
+        _ => false,
+    };
+    let encoding = match encoding {
+        Some(encoding) if content_type_allowed && large_enough && !res.response().headers().contains_key(header::CONTENT_ENCODING) => encoding,
+        _ => return Ok(res.map_into_boxed_body()),
+    };
+    let (http_request, response) = res.into_parts();
+    let (mut response, body) = response.into_parts();
+    let bytes = match actix_web::body::to_bytes(body).await {
+        Ok(bytes) => bytes,
+        Err(_) => return Err(actix_web::error::ErrorInternalServerError("cannot read response body")),
+    };
+    let compressed = web::block(move || encoding.compress(&bytes)).await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    // the compressed body is another representation, so a strong ETag of
+    // the uncompressed one only holds for it weakly
+    if let Some(weak) = response.headers().get(header::ETAG).and_then(|v| v.to_str().ok()).and_then(weak_etag) {
+        response.headers_mut().insert(header::ETAG, weak);
+    }
+    response.headers_mut().insert(header::CONTENT_ENCODING, HeaderValue::from_static(encoding.as_str()));
+    response.headers_mut().append(header::VARY, HeaderValue::from_static("accept-encoding"));
+    response.headers_mut().remove(header::CONTENT_LENGTH);
+    Ok(ServiceResponse::new(http_request, response.set_body(BoxBody::new(compressed))))
+}
+
+fn weak_etag(etag: &str) -> Option<HeaderValue> {
+    if etag.starts_with("W/") {
+        return None;
+    }
+    HeaderValue::from_str(&format!("W/{}", etag)).ok()
+}
+
+/// Decompress a request body according to its `Content-Encoding`. The
+/// decompressed body is limited to `limit` bytes.
+pub(crate) fn decompress_request_body(http_request: &HttpRequest, body: Bytes, limit: usize) -> Result<Bytes> {
+    let content_encoding = match http_request.headers().get(header::CONTENT_ENCODING).and_then(|v| v.to_str().ok()) {
+        Some(content_encoding) => content_encoding.trim().to_ascii_lowercase(),
+        None => return Ok(body),
+    };
+    let reader: Box<dyn Read + '_> = match content_encoding.as_str() {
+        "identity" | "" => return Ok(body),
+        "gzip" | "x-gzip" => Box::new(GzDecoder::new(body.as_ref())),
+        "br" => Box::new(brotli::Decompressor::new(body.as_ref(), 4096)),
+        "zstd" => match zstd::stream::read::Decoder::new(body.as_ref()) {
+            Ok(decoder) => Box::new(decoder),
+            Err(_) => return Err(Error::value_error_message_only("incorrect request body encoding")),
+        },
+        _ => return Err(Error::value_error_message_only(format!("unsupported content encoding {}", content_encoding))),
+    };
+    let mut decompressed = vec![];
+    if reader.take(limit as u64 + 1).read_to_end(&mut decompressed).is_err() {
+        return Err(Error::value_error_message_only("incorrect request body encoding"));
+    }
+    if decompressed.len() > limit {
+        return Err(Error::internal_server_error_message_only("memory overflow"));
+    }
+    Ok(Bytes::from(decompressed))
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_http::header;
+    use actix_web::test::TestRequest;
+    use actix_web::web::Bytes;
+    use flate2::Compression;
+    use flate2::write::GzEncoder;
+    use std::io::Write;
+    use super::{CompressionConfig, Encoding, decompress_request_body, weak_etag};
+
+    #[test]
+    fn negotiates_by_preference_and_quality() {
+        let config = CompressionConfig::default();
+        assert_eq!(config.negotiate("gzip, br"), Some(Encoding::Brotli));
+        assert_eq!(config.negotiate("gzip, br;q=0, zstd;q=0"), Some(Encoding::Gzip));
+        assert_eq!(config.negotiate("*"), Some(Encoding::Zstd));
+        assert_eq!(config.negotiate("*;q=0, GZIP"), Some(Encoding::Gzip));
+        assert_eq!(config.negotiate("identity"), None);
+        assert_eq!(config.negotiate(""), None);
+    }
+
+    #[test]
+    fn allows_configured_content_types() {
+        let config = CompressionConfig::default();
+        assert!(config.allows_content_type("application/json; charset=utf-8"));
+        assert!(config.allows_content_type("text/html"));
+        assert!(!config.allows_content_type("image/png"));
+    }
+
+    #[test]
+    fn compresses_and_decompresses() {
+        let body = b"teo ".repeat(100);
+        for encoding in [Encoding::Zstd, Encoding::Brotli, Encoding::Gzip] {
+            let compressed = encoding.compress(&body).unwrap();
+            let request = TestRequest::post().insert_header((header::CONTENT_ENCODING, encoding.as_str())).to_http_request();
+            assert_eq!(decompress_request_body(&request, Bytes::from(compressed), 1024).unwrap(), Bytes::from(body.clone()));
+        }
+    }
+
+    #[test]
+    fn limits_decompressed_request_bodies() {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&[0; 4096]).unwrap();
+        let request = TestRequest::post().insert_header((header::CONTENT_ENCODING, "gzip")).to_http_request();
+        assert!(decompress_request_body(&request, Bytes::from(encoder.finish().unwrap()), 1024).is_err());
+        let request = TestRequest::post().insert_header((header::CONTENT_ENCODING, "deflate")).to_http_request();
+        assert!(decompress_request_body(&request, Bytes::from_static(b"x"), 1024).is_err());
+    }
+
+    #[test]
+    fn weakens_strong_etags() {
+        assert_eq!(weak_etag("\"abc\"").unwrap(), "W/\"abc\"");
+        assert!(weak_etag("W/\"abc\"").is_none());
+    }
+}
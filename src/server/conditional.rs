@@ -0,0 +1,164 @@
+use actix_http::body::{BoxBody, MessageBody};
+use actix_http::header::{self, HeaderValue};
+use actix_http::Method;
+use actix_web::{HttpRequest, HttpResponse};
+use chrono::{DateTime, Utc};
+use ring::digest;
+use serde_json::Value as JsonValue;
+use teo_runtime::response::body::BodyInner;
+use teo_runtime::response::Response;
+
+/// Caching headers of a handler.
+#[derive(Debug, Clone, Default)]
+pub struct CachePolicy {
+    /// The value of the `Cache-Control` header, e.g. `"private, max-age=60"`.
+    pub cache_control: Option<String>,
+    /// A date time field of the returned records. The latest value is sent
+    /// as `Last-Modified`, e.g. `"updatedAt"`.
+    pub last_modified_field: Option<String>,
+}
+
+/// Whether `conditional_response` applies to `response`. Only Teon bodies
+/// are; files and strings are left to their handlers.
+pub(crate) fn is_cacheable(response: &Response) -> bool {
+    matches!(response.body().inner.as_ref(), BodyInner::Teon(_))
+}
+
+/// Add `ETag`, `Cache-Control` and `Last-Modified` to a successful response
+/// and answer with 304 if the client already has it.
+///
+/// Only `GET` and `HEAD` requests are answered with 304 (RFC 9110 §13.1.2).
+/// Builtin read actions are also sent with `POST`; those get the headers but
+/// always the full response.
+pub(crate) fn conditional_response(http_request: &HttpRequest, response: HttpResponse, policy: Option<&CachePolicy>) -> HttpResponse {
+    if !response.status().is_success() {
+        return response;
+    }
+    let (mut response, body) = response.into_parts();
+    let bytes = match body.try_into_bytes() {
+        Ok(bytes) => bytes,
+        Err(body) => return response.set_body(body),
+    };
+    let etag = format!("\"{}\"", digest::digest(&digest::SHA256, &bytes).as_ref()[..16].iter().map(|b| format!("{:02x}", b)).collect::<String>());
+    let last_modified = policy.and_then(|p| p.last_modified_field.as_ref()).and_then(|field| last_modified(&bytes, field));
+    if let Ok(value) = HeaderValue::from_str(&etag) {
+        response.headers_mut().insert(header::ETAG, value);
+    }
+    if let Some(cache_control) = policy.and_then(|p| p.cache_control.as_ref()) {
+        if let Ok(value) = HeaderValue::from_str(cache_control) {
+            response.headers_mut().insert(header::CACHE_CONTROL, value);
+        }
+    }
+    if let Some(last_modified) = last_modified {
+        if let Ok(value) = HeaderValue::from_str(&last_modified.format("%a, %d %b %Y %H:%M:%S GMT").to_string()) {
+            response.headers_mut().insert(header::LAST_MODIFIED, value);
+        }
+    }
+    let safe = matches!(*http_request.method(), Method::GET | Method::HEAD);
+    let not_modified = safe && match http_request.headers().get(header::IF_NONE_MATCH).and_then(|v| v.to_str().ok()) {
+        Some(if_none_match) => if_none_match.split(',').any(|tag| {
+            let tag = tag.trim();
+            tag == "*" || tag.trim_start_matches("W/") == etag
+        }),
+        None => match (last_modified, http_request.headers().get(header::IF_MODIFIED_SINCE).and_then(|v| v.to_str().ok())) {
+            (Some(last_modified), Some(if_modified_since)) => match DateTime::parse_from_rfc2822(if_modified_since) {
+                Ok(since) => last_modified.timestamp() <= since.timestamp(),
+                Err(_) => false,
+            },
+            _ => false,
+        },
+    };
+    if not_modified {
+        let mut builder = HttpResponse::NotModified();
+        for name in [header::ETAG, header::CACHE_CONTROL, header::LAST_MODIFIED] {
+            if let Some(value) = response.headers().get(&name) {
+                builder.insert_header((name.clone(), value.clone()));
+            }
+        }
+        return builder.finish();
+    }
+    response.set_body(BoxBody::new(bytes))
+}
+
+fn last_modified(body: &[u8], field: &str) -> Option<DateTime<Utc>> {
+    let json: JsonValue = serde_json::from_slice(body).ok()?;
+    let records: Vec<&JsonValue> = match json.get("data")? {
+        JsonValue::Array(records) => records.iter().collect(),
+        record => vec![record],
+    };
+    records.iter()
+        .filter_map(|record| record.get(field)?.as_str())
+        .filter_map(|value| DateTime::parse_from_rfc3339(value).ok())
+        .map(|value| value.with_timezone(&Utc))
+        .max()
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_http::header;
+    use actix_http::StatusCode;
+    use actix_web::HttpResponse;
+    use actix_web::test::TestRequest;
+    use serde_json::json;
+    use teo_runtime::response::Response;
+    use teo_teon::teon;
+    use crate::test::schema::app_for_schema_source;
+    use super::{CachePolicy, conditional_response, is_cacheable};
+
+    fn ok() -> HttpResponse {
+        HttpResponse::Ok().content_type("application/json").body("{\"data\":[{\"updatedAt\":\"2024-05-01T10:00:00Z\"}]}")
+    }
+
+    #[test]
+    fn only_teon_responses_are_cacheable() {
+        assert!(is_cacheable(&Response::teon(teon!({ "data": 1 }))));
+        assert!(!is_cacheable(&Response::string("a".to_owned(), "text/plain")));
+    }
+
+    #[test]
+    fn answers_matching_requests_with_304() {
+        let policy = CachePolicy { cache_control: Some("private, max-age=60".to_owned()), last_modified_field: Some("updatedAt".to_owned()) };
+        let response = conditional_response(&TestRequest::get().to_http_request(), ok(), Some(&policy));
+        assert_eq!(response.status(), StatusCode::OK);
+        let etag = response.headers().get(header::ETAG).unwrap().to_str().unwrap().to_owned();
+        assert_eq!(response.headers().get(header::CACHE_CONTROL).unwrap(), "private, max-age=60");
+        assert_eq!(response.headers().get(header::LAST_MODIFIED).unwrap(), "Wed, 01 May 2024 10:00:00 GMT");
+        for if_none_match in [etag.clone(), format!("W/{}", etag), format!("\"other\", {}", etag)] {
+            let request = TestRequest::get().insert_header((header::IF_NONE_MATCH, if_none_match)).to_http_request();
+            let response = conditional_response(&request, ok(), Some(&policy));
+            assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+            assert_eq!(response.headers().get(header::ETAG).unwrap(), etag.as_str());
+        }
+        let request = TestRequest::get().insert_header((header::IF_NONE_MATCH, "\"other\"")).to_http_request();
+        assert_eq!(conditional_response(&request, ok(), Some(&policy)).status(), StatusCode::OK);
+    }
+
+    #[test]
+    fn sends_full_responses_to_post_requests() {
+        let request = TestRequest::get().to_http_request();
+        let etag = conditional_response(&request, ok(), None).headers().get(header::ETAG).unwrap().clone();
+        let request = TestRequest::post().insert_header((header::IF_NONE_MATCH, etag.clone())).to_http_request();
+        let response = conditional_response(&request, ok(), None);
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers().get(header::ETAG).unwrap(), &etag);
+    }
+
+    #[test]
+    fn answers_unmodified_requests_with_304() {
+        let policy = CachePolicy { cache_control: None, last_modified_field: Some("updatedAt".to_owned()) };
+        let request = TestRequest::get().insert_header((header::IF_MODIFIED_SINCE, "Wed, 01 May 2024 10:00:00 GMT")).to_http_request();
+        assert_eq!(conditional_response(&request, ok(), Some(&policy)).status(), StatusCode::NOT_MODIFIED);
+        let request = TestRequest::get().insert_header((header::IF_MODIFIED_SINCE, "Tue, 30 Apr 2024 10:00:00 GMT")).to_http_request();
+        assert_eq!(conditional_response(&request, ok(), Some(&policy)).status(), StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn serves_a_schema_with_cache_decorators() {
+        let mut app = app_for_schema_source("connector {\n  provider .sqlite\n  url \"sqlite::memory:\"\n}\n\nserver {\n  bind (\"0.0.0.0\", 4000)\n}\n\n@cache(control: \"private, max-age=60\")\nmodel Artist {\n  @id @autoIncrement @readonly\n  id: Int\n  name: String\n}\n");
+        let client = app.test_client_with_memory_database().await.unwrap();
+        let response = client.model("Artist").find_many(json!({})).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.header("Cache-Control"), Some("private, max-age=60"));
+        assert!(response.header("ETag").is_some());
+    }
+}
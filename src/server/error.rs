@@ -2,12 +2,83 @@ use std::fmt::{Display, Formatter};
 use actix_http::body::BoxBody;
 use actix_http::StatusCode;
 use actix_web::{HttpResponse, ResponseError};
-use serde_json::json;
+use serde_json::{json, Value as JsonValue};
 use teo_teon::Value;
 use teo_result::Error;
+use crate::app::ctx::Ctx;
+
+/// Customize the body of error responses.
+///
+/// The default formatter wraps the error into `{"error": ...}`. Set a custom
+/// formatter with `App::error_formatter` to produce another envelope.
+pub trait ErrorFormatter: Send + Sync {
+
+    /// The content type of the formatted error response.
+    fn content_type(&self) -> &str {
+        "application/json"
+    }
+
+    /// Format an error into a JSON response body.
+    fn format(&self, error: &Error, status: StatusCode) -> JsonValue;
+}
+
+impl<F> ErrorFormatter for F where F: Fn(&Error, StatusCode) -> JsonValue + Send + Sync {
+
+    fn format(&self, error: &Error, status: StatusCode) -> JsonValue {
+        self(error, status)
+    }
+}
+
+/// The builtin `{"error": ...}` envelope.
+pub struct DefaultErrorFormatter;
+
+impl ErrorFormatter for DefaultErrorFormatter {
+
+    fn format(&self, error: &Error, _status: StatusCode) -> JsonValue {
+        json!({
+            "error": error_to_json(error)
+        })
+    }
+}
+
+/// RFC 7807 `application/problem+json` envelope.
+pub struct ProblemJsonErrorFormatter;
+
+impl ErrorFormatter for ProblemJsonErrorFormatter {
+
+    fn content_type(&self) -> &str {
+        "application/problem+json"
+    }
+
+    fn format(&self, error: &Error, status: StatusCode) -> JsonValue {
+        let mut problem = json!({
+            "type": "about:blank",
+            "title": status.canonical_reason().unwrap_or("Unknown Error"),
+            "status": status.as_u16(),
+            "detail": error.to_string(),
+        });
+        if let Some(fields) = error_to_json(error).get("fields") {
+            problem.as_object_mut().unwrap().insert("fields".to_owned(), fields.clone());
+        }
+        problem
+    }
+}
+
+/// Convert an error into its JSON representation. Values which cannot be
+/// represented fall back to the error message.
+pub fn error_to_json(error: &Error) -> JsonValue {
+    let value: Value = error.into();
+    match JsonValue::try_from(value) {
+        Ok(json_value) => json_value,
+        Err(_) => json!({
+            "type": "InternalServerError",
+            "message": error.to_string(),
+        }),
+    }
+}
 
 #[derive(Debug)]
-pub(super) struct WrapError(Error);
+pub(crate) struct WrapError(Error);
 
 impl Display for WrapError {
 
@@ -26,14 +97,25 @@ impl From<Error> for WrapError {
 impl ResponseError for WrapError {
 
     fn status_code(&self) -> StatusCode {
-        StatusCode::from_u16(self.0.code.unwrap_or(500)).unwrap()
+        self.0.code.and_then(|code| StatusCode::from_u16(code).ok()).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR)
     }
 
     fn error_response(&self) -> HttpResponse<BoxBody> {
-        let value: Value = (&self.0).into();
-        let json_value: serde_json::Value = value.try_into().unwrap();
-        HttpResponse::Ok().status(self.status_code()).json(json!({
-            "error": json_value
-        }))
+        match Ctx::current() {
+            Some(ctx) => self.error_response_with(ctx.error_formatter().as_ref()),
+            None => self.error_response_with(&DefaultErrorFormatter),
+        }
+    }
+}
+
+impl WrapError {
+
+    /// Format the error response with `formatter` instead of the formatter
+    /// of the current app.
+    pub(crate) fn error_response_with(&self, formatter: &dyn ErrorFormatter) -> HttpResponse<BoxBody> {
+        let status = self.status_code();
+        HttpResponse::build(status)
+            .content_type(formatter.content_type())
+            .body(formatter.format(&self.0, status).to_string())
     }
 }
@@ -0,0 +1,198 @@
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use teo_result::{Error, Result};
+use crate::server::make::lookup_by_handler_key;
+
+/// Request timeouts and concurrency limits.
+///
+/// Per handler settings are keyed by the dotted handler path. A key matches
+/// the handler itself and, when it names a namespace or a handler group,
+/// every handler under it. The longest matching key wins.
+#[derive(Default)]
+pub struct RequestLimits {
+    timeout: Option<Duration>,
+    handler_timeouts: BTreeMap<String, Duration>,
+    concurrency: Option<ConcurrencyLimit>,
+    handler_concurrency: BTreeMap<String, ConcurrencyLimit>,
+}
+
+impl RequestLimits {
+
+    pub(crate) fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = Some(timeout);
+    }
+
+    pub(crate) fn set_handler_timeout(&mut self, key: &str, timeout: Duration) {
+        self.handler_timeouts.insert(key.to_owned(), timeout);
+    }
+
+    pub(crate) fn set_concurrency(&mut self, max: usize, queue: usize) {
+        self.concurrency = Some(ConcurrencyLimit::new(max, queue));
+    }
+
+    pub(crate) fn set_handler_concurrency(&mut self, key: &str, max: usize, queue: usize) {
+        self.handler_concurrency.insert(key.to_owned(), ConcurrencyLimit::new(max, queue));
+    }
+
+    /// Run a request for the handler `key` under these limits. Time spent
+    /// waiting for a slot counts towards the timeout.
+    pub(crate) async fn run<F, T, E>(&self, key: &str, fut: F) -> std::result::Result<T, E> where F: Future<Output = std::result::Result<T, E>>, E: From<Error> {
+        match lookup_by_handler_key(&self.handler_timeouts, key).map(|(_, t)| t).or(self.timeout.as_ref()) {
+            Some(timeout) => match tokio::time::timeout(*timeout, self.run_with_permits(key, fut)).await {
+                Ok(result) => result,
+                Err(_) => Err(error_with_code("request timeout", 504))?,
+            },
+            None => self.run_with_permits(key, fut).await,
+        }
+    }
+
+    async fn run_with_permits<F, T, E>(&self, key: &str, fut: F) -> std::result::Result<T, E> where F: Future<Output = std::result::Result<T, E>>, E: From<Error> {
+        // the handler's slot is taken first, so that requests queued for a
+        // busy handler don't hold global slots other handlers could use
+        let mut permits = vec![];
+        if let Some(concurrency) = lookup_by_handler_key(&self.handler_concurrency, key).map(|(_, c)| c) {
+            permits.push(concurrency.acquire().await?);
+        }
+        if let Some(concurrency) = &self.concurrency {
+            permits.push(concurrency.acquire().await?);
+        }
+        let result = fut.await;
+        drop(permits);
+        result
+    }
+}
+
+struct ConcurrencyLimit {
+    semaphore: Arc<Semaphore>,
+    queue: usize,
+    waiting: AtomicUsize,
+}
+
+impl ConcurrencyLimit {
+
+    fn new(max: usize, queue: usize) -> Self {
+        Self {
+            semaphore: Arc::new(Semaphore::new(max)),
+            queue,
+            waiting: AtomicUsize::new(0),
+        }
+    }
+
+    async fn acquire(&self) -> Result<OwnedSemaphorePermit> {
+        if let Ok(permit) = self.semaphore.clone().try_acquire_owned() {
+            return Ok(permit);
+        }
+        if self.waiting.fetch_add(1, Ordering::SeqCst) >= self.queue {
+            self.waiting.fetch_sub(1, Ordering::SeqCst);
+            return Err(error_with_code("service unavailable", 503));
+        }
+        let permit = self.semaphore.clone().acquire_owned().await;
+        self.waiting.fetch_sub(1, Ordering::SeqCst);
+        match permit {
+            Ok(permit) => Ok(permit),
+            Err(_) => Err(error_with_code("service unavailable", 503)),
+        }
+    }
+}
+
+fn error_with_code(message: &str, code: u16) -> Error {
+    let mut error = Error::new(message);
+    error.code = Some(code);
+    error
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use futures_util::join;
+    use teo_result::Error;
+    use tokio::sync::oneshot;
+    use super::RequestLimits;
+
+    async fn ok() -> Result<(), Error> {
+        Ok(())
+    }
+
+    #[actix_web::test]
+    async fn times_out_with_504() {
+        let mut limits = RequestLimits::default();
+        limits.set_timeout(Duration::from_secs(60));
+        limits.set_handler_timeout("Artist", Duration::from_millis(10));
+        let err = limits.run("Artist.findMany", async {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            Ok::<(), Error>(())
+        }).await.unwrap_err();
+        assert_eq!(err.code, Some(504));
+        assert!(limits.run("Song.findMany", ok()).await.is_ok());
+    }
+
+    #[actix_web::test]
+    async fn times_out_while_waiting_for_a_slot() {
+        let mut limits = RequestLimits::default();
+        limits.set_handler_timeout("Artist", Duration::from_millis(10));
+        limits.set_concurrency(1, 1);
+        let (sender, receiver) = oneshot::channel::<()>();
+        let (first, queued) = join!(
+            limits.run("Song.findMany", async {
+                let _ = receiver.await;
+                Ok::<(), Error>(())
+            }),
+            async {
+                let result = limits.run("Artist.findMany", ok()).await;
+                let _ = sender.send(());
+                result
+            },
+        );
+        assert!(first.is_ok());
+        assert_eq!(queued.unwrap_err().code, Some(504));
+    }
+
+    #[actix_web::test]
+    async fn rejects_with_503_when_the_queue_is_full() {
+        let mut limits = RequestLimits::default();
+        limits.set_concurrency(1, 0);
+        let (sender, receiver) = oneshot::channel::<()>();
+        let (first, second) = join!(
+            limits.run("Artist.findMany", async {
+                let _ = receiver.await;
+                Ok::<(), Error>(())
+            }),
+            async {
+                let result = limits.run("Artist.findMany", ok()).await;
+                let _ = sender.send(());
+                result
+            },
+        );
+        assert!(first.is_ok());
+        assert_eq!(second.unwrap_err().code, Some(503));
+    }
+
+    #[actix_web::test]
+    async fn queued_requests_hold_no_global_slot() {
+        let mut limits = RequestLimits::default();
+        limits.set_concurrency(2, 0);
+        limits.set_handler_concurrency("Report", 1, 5);
+        let (sender, receiver) = oneshot::channel::<()>();
+        let (first, queued, other) = join!(
+            limits.run("Report.generate", async {
+                let _ = receiver.await;
+                Ok::<(), Error>(())
+            }),
+            limits.run("Report.generate", ok()),
+            async {
+                // let the requests above take and wait for their slots
+                tokio::task::yield_now().await;
+                let result = limits.run("Artist.findMany", ok()).await;
+                let _ = sender.send(());
+                result
+            },
+        );
+        assert!(first.is_ok());
+        assert!(queued.is_ok());
+        assert!(other.is_ok());
+    }
+}
@@ -1,6 +1,14 @@
+use std::any::Any;
+use std::future::Future;
+use std::net::TcpListener;
+use std::collections::BTreeMap;
+use std::panic::AssertUnwindSafe;
+use std::rc::Rc;
 use std::sync::Arc;
 use std::time::SystemTime;
-use actix_web::dev::Service;
+use actix_service::IntoServiceFactory;
+use actix_web::dev::{AppConfig, Service};
+use futures_util::future::LocalBoxFuture;
 use futures_util::FutureExt;
 use colored::Colorize;
 use futures_util::future;
@@ -8,17 +16,17 @@ use serde_json::{Value as JsonValue};
 use teo_result::{Error, Result};
 use teo_runtime::config::server::Server;
 use teo_runtime::namespace::Namespace;
-use actix_http::body::MessageBody;
-use actix_http::{HttpMessage, Method as HttpMethod};
-use actix_web::{App, FromRequest, HttpRequest, HttpResponse, HttpServer, web};
-use actix_web::dev::{ServiceFactory, ServiceRequest, ServiceResponse};
+use actix_http::body::{BoxBody, EitherBody, MessageBody};
+use actix_http::{HttpMessage, Method as HttpMethod, Request};
+use actix_web::{App, FromRequest, HttpRequest, HttpResponse, HttpServer, ResponseError, Scope, web};
+use actix_web::dev::{Server as ActixServer, ServiceFactory, ServiceRequest, ServiceResponse};
 use actix_web::middleware::DefaultHeaders;
 use teo_parser::ast::handler::HandlerInputFormat;
 use teo_runtime::action::Action;
 use teo_runtime::handler::action::builtin_action_handler_from_name;
 use teo_runtime::handler::Handler;
 use teo_runtime::handler::handler::Method;
-use teo_runtime::{connection, request};
+use teo_runtime::request;
 use teo_runtime::connection::transaction;
 use teo_runtime::handler::default::{create, find_first, find_many, find_unique, update, upsert, copy, create_many, update_many, copy_many, delete_many, count, aggregate, group_by, delete};
 use teo_runtime::model::Model;
@@ -28,22 +36,27 @@ use crate::cli::entrance::Entrance;
 use crate::cli::runtime_version::RuntimeVersion;
 use crate::purge;
 use crate::seeder::seed::seed;
-use crate::server::parse::{parse_form_body, parse_json_body};
+use crate::server::parse::{parse_form_body, parse_json_body, read_body};
+use crate::server::rate_limit::hit_rate_limiters;
 use teo_runtime::handler::input::{validate_and_transform_json_input_for_handler, validate_and_transform_json_input_for_builtin_action};
 use teo_runtime::handler::r#match::HandlerMatch;
 use teo_runtime::schema::load::load_data_sets::load_data_sets;
 use crate::app::Ctx;
-use crate::app::database::connect_databases;
+use crate::app::environment::interpolate;
 use crate::cli::command::SeedCommandAction;
-use crate::message::{info_message, request_message, unhandled_request_message};
-use crate::server::error::WrapError;
-use crate::server::request::RequestImpl;
+use crate::message::{error_message, info_message, request_message, unhandled_request_message};
+use crate::server::compression::{CompressionConfig, compress_response};
+use crate::server::conditional::{conditional_response, is_cacheable};
+use crate::server::error::{ErrorFormatter, WrapError};
+use crate::server::request::{CURRENT_REQUEST, RequestImpl};
 use crate::server::responder::IntoHttpResponse;
+use crate::server::session::with_session;
 use teo_runtime::error_runtime_ext::ErrorRuntimeExt;
 
-fn make_server_app(
-    main_namespace: &'static Namespace,
-    conf: &'static Server,
+pub(crate) fn make_server_app(
+    ctx: Ctx,
+    main_namespace: Arc<Namespace>,
+    path_prefix: Option<String>,
 ) -> App<impl ServiceFactory<
     ServiceRequest,
     Response = ServiceResponse<impl MessageBody>,
@@ -51,233 +64,380 @@ fn make_server_app(
     InitError = (),
     Error = actix_web::Error,
 > + 'static> {
+    let error_formatter = ctx.error_formatter();
+    let compression = ctx.compression();
     let app = App::new()
-        .wrap(DefaultHeaders::new()
-            .add(("Access-Control-Allow-Origin", "*"))
-            .add(("Access-Control-Allow-Methods", "OPTIONS, POST, GET"))
-            .add(("Access-Control-Allow-Headers", "*"))
-            .add(("Access-Control-Max-Age", "86400")))
-        .wrap_fn(|req, srv| {
-            let start = SystemTime::now();
-            let fut = srv.call(req);
-            async move {
-                let res = fut.await?;
-                {
-                    let binding = res.request().extensions();
-                    let handler_found_info = binding.get::<HandlerMatch>().clone();
-                    let time_elapsed = SystemTime::now().duration_since(start).unwrap();
-                    let path = res.request().path();
-                    let method = res.request().method().as_str();
-                    if let Some(handler_found_info) = handler_found_info {
-                        request_message(time_elapsed, method, path, &handler_found_info.path, handler_found_info.name.as_str(), res.response().status().as_u16());
-                    } else {
-                        unhandled_request_message(time_elapsed, method, path, res.response().status().as_u16());
-                    }
-                }
-                Ok(res)
+        .wrap_fn(move |req, srv| catch_panic(error_formatter.clone(), req, srv))
+        .wrap(default_headers())
+        .wrap_fn(move |req, srv| compress(compression.clone(), req, srv))
+        .wrap_fn(|req, srv| log_request(req, srv))
+        .default_service(web::route().to(move |http_request: HttpRequest, payload: web::Payload| {
+            serve_request(ctx.clone(), main_namespace.clone(), path_prefix.clone(), http_request, payload)
+        }));
+    app
+}
+
+/// The same service as `make_server_app`, as a scope which can be mounted
+/// under `path` of another actix app.
+pub(crate) fn make_server_scope(
+    ctx: Ctx,
+    main_namespace: Arc<Namespace>,
+    path: &str,
+) -> Scope<impl ServiceFactory<
+    ServiceRequest,
+    Response = ServiceResponse<impl MessageBody>,
+    Config = (),
+    InitError = (),
+    Error = actix_web::Error,
+> + 'static> {
+    let error_formatter = ctx.error_formatter();
+    let compression = ctx.compression();
+    web::scope(path)
+        .wrap_fn(move |req, srv| catch_panic(error_formatter.clone(), req, srv))
+        .wrap(default_headers())
+        .wrap_fn(move |req, srv| compress(compression.clone(), req, srv))
+        .wrap_fn(|req, srv| log_request(req, srv))
+        .default_service(web::route().to(move |http_request: HttpRequest, payload: web::Payload| {
+            serve_request(ctx.clone(), main_namespace.clone(), None, http_request, payload)
+        }))
+}
+
+/// Requests handled by `make_server_app` without an actix server. Actix
+/// services are not `Send`, so it's used on the thread which built it.
+pub(crate) type LocalService = Rc<dyn Fn(Request) -> LocalBoxFuture<'static, HttpResponse<BoxBody>>>;
+
+pub(crate) async fn make_local_service(
+    ctx: Ctx,
+    main_namespace: Arc<Namespace>,
+    path_prefix: Option<String>,
+) -> Result<LocalService> {
+    let factory = make_server_app(ctx, main_namespace, path_prefix).into_factory();
+    let service = match factory.new_service(AppConfig::default()).await {
+        Ok(service) => Rc::new(service),
+        Err(_) => Err(Error::new("cannot build the app service"))?,
+    };
+    Ok(Rc::new(move |request: Request| -> LocalBoxFuture<'static, HttpResponse<BoxBody>> {
+        let service = service.clone();
+        Box::pin(async move {
+            match service.call(request).await {
+                Ok(response) => response.into_parts().1.map_into_boxed_body(),
+                Err(err) => err.error_response(),
             }
         })
-        .default_service(web::route().to(move |http_request: HttpRequest, payload: web::Payload| async move {
-            // validate path
-            let path = main_namespace.handler_map.remove_path_prefix(http_request.path(), conf.path_prefix.as_ref().map(|s| s.as_str()));
-            let method = method_from(http_request.method())?;
-            let match_result = if let Some(m_result) = main_namespace.handler_map.r#match(method, path) {
-                m_result
-            } else if let Some(m_result) = main_namespace.handler_map.default_match(method, path) {
-                m_result
-            } else {
-                Err(Error::not_found_message_only())?
-            };
+    }))
+}
 
-            // High-risk operations for testing
-            #[cfg(feature="dangerous_operation")]
-            if match_result.path()[0] == "danger" {
-                return Ok::<HttpResponse, WrapError>(
-                    dangerous_operation(match_result.handler_name())
-                        .await?
-                        .into_http_response(http_request.clone()),
-                );
+fn catch_panic<S, B>(error_formatter: Arc<dyn ErrorFormatter>, req: ServiceRequest, srv: &S) -> impl Future<Output = actix_web::Result<ServiceResponse<EitherBody<B>>>> where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    B: MessageBody {
+    let http_request = req.request().clone();
+    let fut = srv.call(req);
+    async move {
+        match AssertUnwindSafe(fut).catch_unwind().await {
+            Ok(res) => res.map(ServiceResponse::map_into_left_body),
+            Err(panic) => {
+                error_message(format!("handler panicked at {}: {}", http_request.path(), panic_message(panic.as_ref())));
+                let response = WrapError::from(Error::internal_server_error_message_only("internal server error")).error_response_with(error_formatter.as_ref());
+                Ok(ServiceResponse::new(http_request, response).map_into_right_body())
             }
+        }
+    }
+}
 
-            // Normal handling
-            let mut group = false;
-            let dest_namespace = if let Some(d) = main_namespace.namespace_at_path(&match_result.path()) {
-                d
-            } else if match_result.path().len() > 0 {
-                if let Some(d) = main_namespace.namespace_at_path(&match_result.path_without_last()) {
-                    group = true;
-                    d
-                } else {
-                    Err(Error::not_found_message_only())?
-                }
+fn default_headers() -> DefaultHeaders {
+    DefaultHeaders::new()
+        .add(("Access-Control-Allow-Origin", "*"))
+        .add(("Access-Control-Allow-Methods", "OPTIONS, POST, GET"))
+        .add(("Access-Control-Allow-Headers", "*"))
+        .add(("Access-Control-Max-Age", "86400"))
+}
+
+fn compress<S, B>(compression: Option<Arc<CompressionConfig>>, req: ServiceRequest, srv: &S) -> impl Future<Output = actix_web::Result<ServiceResponse<BoxBody>>> where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    B: MessageBody + 'static {
+    let fut = srv.call(req);
+    async move {
+        let res = fut.await?;
+        match compression {
+            Some(compression) => compress_response(compression.as_ref(), res).await,
+            None => Ok(res.map_into_boxed_body()),
+        }
+    }
+}
+
+fn log_request<S, B>(req: ServiceRequest, srv: &S) -> impl Future<Output = actix_web::Result<ServiceResponse<B>>> where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> {
+    let start = SystemTime::now();
+    let fut = srv.call(req);
+    async move {
+        let res = fut.await?;
+        {
+            let binding = res.request().extensions();
+            let handler_found_info = binding.get::<HandlerMatch>().clone();
+            let time_elapsed = SystemTime::now().duration_since(start).unwrap_or_default();
+            let path = res.request().path();
+            let method = res.request().method().as_str();
+            if let Some(handler_found_info) = handler_found_info {
+                request_message(time_elapsed, method, path, &handler_found_info.path, handler_found_info.name.as_str(), res.response().status().as_u16());
             } else {
-                Err(Error::not_found_message_only())?
-            };
-            let handler_resolved = if group {
-                if let Some(model) = dest_namespace.models.get(match_result.group_name()) {
-                    if let Some(group) = dest_namespace.model_handler_groups.get(match_result.group_name()) {
-                        if let Some(handler) = group.handlers.get(match_result.handler_name()) {
-                            (dest_namespace, HandlerResolved::Custom(handler))
-                        } else {
-                            if let Some(action) = builtin_action_handler_from_name(match_result.handler_name()) {
-                                (dest_namespace, HandlerResolved::Builtin(model, action))
-                            } else {
-                                Err(Error::not_found_message_only())?
-                            }
-                        }
-                    } else {
-                        if let Some(action) = builtin_action_handler_from_name(match_result.handler_name()) {
-                            (dest_namespace, HandlerResolved::Builtin(model, action))
-                        } else {
-                            Err(Error::not_found_message_only())?
-                        }
-                    }
-                } else if let Some(group) = dest_namespace.handler_groups.get(match_result.group_name()) {
-                    if let Some(handler) = group.handlers.get(match_result.handler_name()) {
-                        (dest_namespace, HandlerResolved::Custom(handler))
+                unhandled_request_message(time_elapsed, method, path, res.response().status().as_u16());
+            }
+        }
+        Ok(res)
+    }
+}
+
+async fn serve_request(
+    ctx: Ctx,
+    main_namespace: Arc<Namespace>,
+    path_prefix: Option<String>,
+    http_request: HttpRequest,
+    payload: web::Payload,
+) -> std::result::Result<HttpResponse, WrapError> {
+    let request_impl = RequestImpl::new(&http_request, ctx.clone());
+    CURRENT_REQUEST.scope(request_impl.clone(), ctx.clone().scope(async move {
+        let fut = handle_request(ctx.clone(), main_namespace.as_ref(), path_prefix.as_deref(), http_request.clone(), payload, request_impl);
+        let result = match ctx.session_config() {
+            Some(session_config) => with_session(session_config, http_request, fut).await,
+            None => fut.await,
+        };
+        // format errors while the app ctx is still the current one
+        Ok(result.unwrap_or_else(|err| err.error_response()))
+    })).await
+}
+
+async fn handle_request(
+    ctx: Ctx,
+    main_namespace: &Namespace,
+    path_prefix: Option<&str>,
+    http_request: HttpRequest,
+    payload: web::Payload,
+    request_impl: Arc<RequestImpl>,
+) -> std::result::Result<HttpResponse, WrapError> {
+    // validate path, relative to the scope when mounted
+    let path = main_namespace.handler_map.remove_path_prefix(http_request.match_info().unprocessed(), path_prefix);
+    let method = method_from(http_request.method())?;
+    let match_result = if let Some(m_result) = main_namespace.handler_map.r#match(method, path) {
+        m_result
+    } else if let Some(m_result) = main_namespace.handler_map.default_match(method, path) {
+        m_result
+    } else {
+        Err(Error::not_found_message_only())?
+    };
+
+    // High-risk operations for testing
+    #[cfg(feature="dangerous_operation")]
+    if match_result.path()[0] == "danger" {
+        return Ok::<HttpResponse, WrapError>(
+            dangerous_operation(&ctx, match_result.handler_name())
+                .await?
+                .into_http_response(http_request.clone())?,
+        );
+    }
+
+    // rate limits apply before the request takes a slot and its body is read
+    let rate_limit_hits = hit_rate_limiters(&ctx, main_namespace, &request_impl, &match_result).await?;
+    if let Some(response) = rate_limit_hits.rejection(&ctx) {
+        return Ok(response);
+    }
+    let key = handler_key(&match_result);
+    let mut response = ctx.request_limits().run(&key, handle_matched_request(&ctx, main_namespace, http_request, payload, request_impl, method, match_result)).await?;
+    rate_limit_hits.set_headers(&mut response);
+    Ok(response)
+}
+
+async fn handle_matched_request(
+    ctx: &Ctx,
+    main_namespace: &Namespace,
+    http_request: HttpRequest,
+    payload: web::Payload,
+    request_impl: Arc<RequestImpl>,
+    method: Method,
+    match_result: HandlerMatch,
+) -> std::result::Result<HttpResponse, WrapError> {
+    // Normal handling
+    let mut group = false;
+    let dest_namespace = if let Some(d) = main_namespace.namespace_at_path(&match_result.path()) {
+        d
+    } else if match_result.path().len() > 0 {
+        if let Some(d) = main_namespace.namespace_at_path(&match_result.path_without_last()) {
+            group = true;
+            d
+        } else {
+            Err(Error::not_found_message_only())?
+        }
+    } else {
+        Err(Error::not_found_message_only())?
+    };
+    let handler_resolved = if group {
+        if let Some(model) = dest_namespace.models.get(match_result.group_name()) {
+            if let Some(group) = dest_namespace.model_handler_groups.get(match_result.group_name()) {
+                if let Some(handler) = group.handlers.get(match_result.handler_name()) {
+                    (dest_namespace, HandlerResolved::Custom(handler))
+                } else {
+                    if let Some(action) = builtin_action_handler_from_name(match_result.handler_name()) {
+                        (dest_namespace, HandlerResolved::Builtin(model, action))
                     } else {
                         Err(Error::not_found_message_only())?
                     }
-                } else {
-                    Err(Error::not_found_message_only())?
                 }
             } else {
-                if let Some(handler) = dest_namespace.handlers.get(match_result.handler_name()) {
-                    (dest_namespace, HandlerResolved::Custom(handler))
+                if let Some(action) = builtin_action_handler_from_name(match_result.handler_name()) {
+                    (dest_namespace, HandlerResolved::Builtin(model, action))
                 } else {
                     Err(Error::not_found_message_only())?
                 }
-            };
-            let dest_namespace = handler_resolved.0;
-            let handler_resolved = handler_resolved.1;
-            if method == Method::Options {
-                // special handle for options
-                let conn_ctx = connection::Ctx::from_namespace(main_namespace);
-                let transaction_ctx = transaction::Ctx::new(conn_ctx);
-                let ctx = request::Ctx::new(
-                    request::Request::new(Arc::new(RequestImpl::new(http_request.clone()))),
-                    Arc::new(Value::Null),
-                    transaction_ctx,
-                    match_result
-                );
-                return Ok::<HttpResponse, WrapError>(dest_namespace.middleware_stack.call(ctx, &|ctx: request::Ctx| async {
-                    Ok(Response::empty())
-                }).await?.into_http_response(http_request.clone()));
             }
-            http_request.extensions_mut().insert(match_result.clone());
-            // parse body
-            let mut format = HandlerInputFormat::Json;
-            match handler_resolved {
-                HandlerResolved::Custom(handler) => {
-                    format = handler.format;
-                }
-                _ => (),
+        } else if let Some(group) = dest_namespace.handler_groups.get(match_result.group_name()) {
+            if let Some(handler) = group.handlers.get(match_result.handler_name()) {
+                (dest_namespace, HandlerResolved::Custom(handler))
+            } else {
+                Err(Error::not_found_message_only())?
             }
-            let json_body = match format {
-                HandlerInputFormat::Json => if method == Method::Get || method == Method::Delete {
-                    JsonValue::Null
-                } else {
-                    parse_json_body(payload).await?
-                },
-                HandlerInputFormat::Form => parse_form_body(http_request.clone(), payload).await?,
+        } else {
+            Err(Error::not_found_message_only())?
+        }
+    } else {
+        if let Some(handler) = dest_namespace.handlers.get(match_result.handler_name()) {
+            (dest_namespace, HandlerResolved::Custom(handler))
+        } else {
+            Err(Error::not_found_message_only())?
+        }
+    };
+    let dest_namespace = handler_resolved.0;
+    let handler_resolved = handler_resolved.1;
+    if method == Method::Options {
+        // special handle for options
+        let conn_ctx = ctx.conn_ctx()?.clone();
+        let transaction_ctx = transaction::Ctx::new(conn_ctx);
+        let ctx = request::Ctx::new(
+            request::Request::new(request_impl.clone()),
+            Arc::new(Value::Null),
+            transaction_ctx,
+            match_result
+        );
+        return Ok::<HttpResponse, WrapError>(dest_namespace.middleware_stack.call(ctx, &|ctx: request::Ctx| async {
+            Ok(Response::empty())
+        }).await?.into_http_response(http_request.clone())?);
+    }
+    http_request.extensions_mut().insert(match_result.clone());
+    // parse body
+    let mut format = HandlerInputFormat::Json;
+    match handler_resolved {
+        HandlerResolved::Custom(handler) => {
+            format = handler.format;
+        }
+        _ => (),
+    }
+    let json_body = match format {
+        HandlerInputFormat::Json => if method == Method::Get || method == Method::Delete {
+            JsonValue::Null
+        } else {
+            let body = read_body(&http_request, payload).await?;
+            request_impl.set_body(body.clone());
+            parse_json_body(&body)?
+        },
+        HandlerInputFormat::Form => parse_form_body(http_request.clone(), payload).await?,
+    };
+    return match handler_resolved {
+        HandlerResolved::Builtin(model, action) => {
+            let body = validate_and_transform_json_input_for_builtin_action(model, action, &json_body, main_namespace)?;
+            let conn_ctx = ctx.conn_ctx()?.clone();
+            let transaction_ctx = transaction::Ctx::new(conn_ctx);
+            let request_ctx = request::Ctx::new(
+                request::Request::new(request_impl.clone()),
+                Arc::new(body),
+                transaction_ctx,
+                match_result.clone(),
+            );
+            let key = handler_key(&match_result);
+            let response = dest_namespace.middleware_stack.call(request_ctx, &|ctx: request::Ctx| async move {
+                call_builtin_action(&ctx).await
+            }).await?;
+            let cacheable = action_is_read_only(match_result.handler_name()) && is_cacheable(&response);
+            let response = response.into_http_response(http_request.clone())?;
+            if cacheable {
+                Ok(conditional_response(&http_request, response, ctx.cache_policy(&key)))
+            } else {
+                Ok(response)
+            }
+        },
+        HandlerResolved::Custom(handler) => {
+            let body = validate_and_transform_json_input_for_handler(handler, &json_body, main_namespace)?;
+            let key = handler_key(&match_result);
+            let body = Arc::new(body);
+            let conn_ctx = ctx.conn_ctx()?.clone();
+            let transaction_ctx = transaction::Ctx::new(conn_ctx);
+            let call = |transaction_ctx: transaction::Ctx| {
+                let request_ctx = request::Ctx::new(
+                    request::Request::new(request_impl.clone()),
+                    body.clone(),
+                    transaction_ctx,
+                    match_result.clone(),
+                );
+                async move { dest_namespace.middleware_stack.call(request_ctx, handler.call).await }
             };
-            return match handler_resolved {
-                HandlerResolved::Builtin(model, action) => {
-                    let body = validate_and_transform_json_input_for_builtin_action(model, action, &json_body, main_namespace)?;
-                    let conn_ctx = connection::Ctx::from_namespace(main_namespace);
-                    let transaction_ctx = transaction::Ctx::new(conn_ctx);
-                    let ctx = request::Ctx::new(
-                        request::Request::new(Arc::new(RequestImpl::new(http_request.clone()))),
-                        Arc::new(body),
-                        transaction_ctx,
-                        match_result.clone(),
-                    );
-                    match match_result.handler_name() {
-                        "findMany" => Ok::<HttpResponse, WrapError>(dest_namespace.middleware_stack.call(ctx, &|ctx: request::Ctx| async move {
-                            find_many(&ctx).await
-                        }).await?.into_http_response(http_request.clone())),
-                        "findFirst" => Ok::<HttpResponse, WrapError>(dest_namespace.middleware_stack.call(ctx, &|ctx: request::Ctx| async move {
-                            find_first(&ctx).await
-                        }).await?.into_http_response(http_request.clone())),
-                        "findUnique" => Ok::<HttpResponse, WrapError>(dest_namespace.middleware_stack.call(ctx, &|ctx: request::Ctx| async move {
-                            find_unique(&ctx).await
-                        }).await?.into_http_response(http_request.clone())),
-                        "create" => Ok::<HttpResponse, WrapError>(dest_namespace.middleware_stack.call(ctx, &|ctx: request::Ctx| async move {
-                            create(&ctx).await
-                        }).await?.into_http_response(http_request.clone())),
-                        "delete" => Ok::<HttpResponse, WrapError>(dest_namespace.middleware_stack.call(ctx, &|ctx: request::Ctx| async move {
-                            delete(&ctx).await
-                        }).await?.into_http_response(http_request.clone())),
-                        "update" => Ok::<HttpResponse, WrapError>(dest_namespace.middleware_stack.call(ctx, &|ctx: request::Ctx| async move {
-                            update(&ctx).await
-                        }).await?.into_http_response(http_request.clone())),
-                        "upsert" => Ok::<HttpResponse, WrapError>(dest_namespace.middleware_stack.call(ctx, &|ctx: request::Ctx| async move {
-                            upsert(&ctx).await
-                        }).await?.into_http_response(http_request.clone())),
-                        "copy" => Ok::<HttpResponse, WrapError>(dest_namespace.middleware_stack.call(ctx, &|ctx: request::Ctx| async move {
-                            copy(&ctx).await
-                        }).await?.into_http_response(http_request.clone())),
-                        "createMany" => Ok::<HttpResponse, WrapError>(dest_namespace.middleware_stack.call(ctx, &|ctx: request::Ctx| async move {
-                            create_many(&ctx).await
-                        }).await?.into_http_response(http_request.clone())),
-                        "updateMany" => Ok::<HttpResponse, WrapError>(dest_namespace.middleware_stack.call(ctx, &|ctx: request::Ctx| async move {
-                            update_many(&ctx).await
-                        }).await?.into_http_response(http_request.clone())),
-                        "copyMany" => Ok::<HttpResponse, WrapError>(dest_namespace.middleware_stack.call(ctx, &|ctx: request::Ctx| async move {
-                            copy_many(&ctx).await
-                        }).await?.into_http_response(http_request.clone())),
-                        "deleteMany" => Ok::<HttpResponse, WrapError>(dest_namespace.middleware_stack.call(ctx, &|ctx: request::Ctx| async move {
-                            delete_many(&ctx).await
-                        }).await?.into_http_response(http_request.clone())),
-                        "count" => Ok::<HttpResponse, WrapError>(dest_namespace.middleware_stack.call(ctx, &|ctx: request::Ctx| async move {
-                            count(&ctx).await
-                        }).await?.into_http_response(http_request.clone())),
-                        "aggregate" => Ok::<HttpResponse, WrapError>(dest_namespace.middleware_stack.call(ctx, &|ctx: request::Ctx| async move {
-                            aggregate(&ctx).await
-                        }).await?.into_http_response(http_request.clone())),
-                        "groupBy" => Ok::<HttpResponse, WrapError>(dest_namespace.middleware_stack.call(ctx, &|ctx: request::Ctx| async move {
-                            group_by(&ctx).await
-                        }).await?.into_http_response(http_request.clone())),
-                        _ => Err(Error::not_found_message_only())?,
-                    }
-                },
-                HandlerResolved::Custom(handler) => {
-                    let body = validate_and_transform_json_input_for_handler(handler, &json_body, main_namespace)?;
-                    let conn_ctx = connection::Ctx::from_namespace(main_namespace);
-                    let transaction_ctx = transaction::Ctx::new(conn_ctx);
-                    let ctx = request::Ctx::new(
-                        request::Request::new(Arc::new(RequestImpl::new(http_request.clone()))),
-                        Arc::new(body),
-                        transaction_ctx,
-                        match_result
-                    );
-                    Ok::<HttpResponse, WrapError>(dest_namespace.middleware_stack.call(ctx, handler.call).await?.into_http_response(http_request.clone()))
-                }
+            let result = if ctx.is_transactional_handler(&key) {
+                // the handler's writes are committed only if it succeeds
+                transaction_ctx.run_transaction(call).await
+            } else {
+                call(transaction_ctx).await
+            };
+            let response = result?;
+            let cacheable = method == Method::Get && is_cacheable(&response);
+            let response = response.into_http_response(http_request.clone())?;
+            if cacheable {
+                Ok(conditional_response(&http_request, response, ctx.cache_policy(&key)))
+            } else {
+                Ok(response)
             }
-        }));
-    app
+        }
+    }
 }
 
-pub(crate) async fn serve(
-    namespace: &'static Namespace,
-    conf: &'static Server,
-    runtime_version: &'static RuntimeVersion,
-    entrance: &'static Entrance,
-    silent: bool,
-) -> Result<()> {
-    let bind = conf.bind.clone();
-    let port = bind.1;
-    let server = HttpServer::new(move || {
-        make_server_app(namespace, conf)
-    })
-        .bind((bind.0, bind.1 as u16))
-        .unwrap()
-        .run();
-    let result = future::join(server, server_start_message(port as u16, runtime_version, entrance, silent)).await;
+pub(crate) async fn serve(ctx: Ctx, silent: bool) -> Result<()> {
+    let conf = server_conf(&ctx)?;
+    let listener = bind_listener(conf)?;
+    let server = start_server(ctx.clone(), listener)?;
+    let result = future::join(server, server_start_message(conf.bind.1 as u16, ctx.runtime_version(), ctx.entrance(), silent)).await;
+    if let Err(err) = result.0 {
+        Err(Error::new(format!("server stopped unexpectedly: {}", err)))?
+    }
     result.1
 }
 
-async fn server_start_message(port: u16, runtime_version: &'static RuntimeVersion, entrance: &'static Entrance, silent: bool) -> Result<()> {
+pub(crate) fn server_conf(ctx: &Ctx) -> Result<&Server> {
+    match ctx.connected_namespace()?.server.as_ref() {
+        Some(conf) => Ok(conf),
+        None => Err(Error::new("server config is not found")),
+    }
+}
+
+/// Bind the address of `conf`. Environment variables and secret files
+/// referenced in the host are resolved.
+pub(crate) fn bind_listener(conf: &Server) -> Result<TcpListener> {
+    let host = interpolate(&conf.bind.0)?;
+    let port = conf.bind.1 as u16;
+    match TcpListener::bind((host.as_str(), port)) {
+        Ok(listener) => Ok(listener),
+        Err(err) => Err(Error::new(format!("cannot bind to {}:{}: {}", host, port, err))),
+    }
+}
+
+/// Start serving the app of `ctx` on `listener`. The listener is not closed
+/// when the returned server stops, so another server can take it over.
+pub(crate) fn start_server(ctx: Ctx, listener: TcpListener) -> Result<ActixServer> {
+    let namespace = ctx.shared_namespace()?;
+    let path_prefix = server_conf(&ctx)?.path_prefix.clone();
+    match HttpServer::new(move || {
+        make_server_app(ctx.clone(), namespace.clone(), path_prefix.clone())
+    }).listen(listener) {
+        Ok(server) => Ok(server.run()),
+        Err(err) => Err(Error::new(format!("cannot listen: {}", err))),
+    }
+}
+
+pub(crate) async fn server_start_message(port: u16, runtime_version: &RuntimeVersion, entrance: Entrance, silent: bool) -> Result<()> {
     if silent { return Ok(()) }
     // Introducing
     let teo_version = env!("CARGO_PKG_VERSION");
@@ -289,6 +449,69 @@ async fn server_start_message(port: u16, runtime_version: &'static RuntimeVersio
     Ok(())
 }
 
+/// The dotted path of a matched handler, e.g. `Artist.findMany`.
+pub(crate) fn handler_key(handler_match: &HandlerMatch) -> String {
+    let mut components: Vec<&str> = handler_match.path.iter().map(AsRef::as_ref).collect();
+    components.push(handler_match.name.as_str());
+    components.join(".")
+}
+
+/// Run the builtin action named by the handler match of `ctx`. The server
+/// and the console both go through here.
+pub(crate) async fn call_builtin_action(ctx: &request::Ctx) -> Result<Response> {
+    match ctx.handler_match().handler_name() {
+        "findMany" => find_many(ctx).await,
+        "findFirst" => find_first(ctx).await,
+        "findUnique" => find_unique(ctx).await,
+        "create" => create(ctx).await,
+        "delete" => delete(ctx).await,
+        "update" => update(ctx).await,
+        "upsert" => upsert(ctx).await,
+        "copy" => copy(ctx).await,
+        "createMany" => create_many(ctx).await,
+        "updateMany" => update_many(ctx).await,
+        "copyMany" => copy_many(ctx).await,
+        "deleteMany" => delete_many(ctx).await,
+        "count" => count(ctx).await,
+        "aggregate" => aggregate(ctx).await,
+        "groupBy" => group_by(ctx).await,
+        _ => Err(Error::not_found_message_only()),
+    }
+}
+
+pub(crate) fn action_is_read_only(name: &str) -> bool {
+    match name {
+        "findUnique" | "findFirst" | "findMany" | "count" | "aggregate" | "groupBy" => true,
+        _ => false,
+    }
+}
+
+/// Find the value configured for a handler key. A key configured for a
+/// namespace or a handler group applies to every handler under it, and the
+/// longest matching key wins. Returns the matched key along with the value.
+pub(crate) fn lookup_by_handler_key<'a, 'b, T>(map: &'a BTreeMap<String, T>, key: &'b str) -> Option<(&'b str, &'a T)> {
+    let mut key = key;
+    loop {
+        if let Some(value) = map.get(key) {
+            return Some((key, value));
+        }
+        match key.rfind('.') {
+            Some(index) => key = &key[..index],
+            None => return None,
+        }
+    }
+}
+
+fn panic_message(panic: &(dyn Any + Send)) -> &str {
+    if let Some(message) = panic.downcast_ref::<&'static str>() {
+        message
+    } else if let Some(message) = panic.downcast_ref::<String>() {
+        message.as_str()
+    } else {
+        "unknown panic"
+    }
+}
+
 fn method_from(m: &HttpMethod) -> Result<Method> {
     Ok(match m.as_str() {
         "GET" => Method::Get,
@@ -301,28 +524,26 @@ fn method_from(m: &HttpMethod) -> Result<Method> {
     })
 }
 
-async fn dangerous_operation(action :&str)-> Result<Response>{
+async fn dangerous_operation(ctx: &Ctx, action :&str)-> Result<Response>{
         let dangerous_operation = DangerousOperations::try_from(action)?;
         match dangerous_operation {
             DangerousOperations::Seed | DangerousOperations::Unseed | DangerousOperations::Reseed => {
-                let data_sets = load_data_sets(Ctx::main_namespace(), None, false, Ctx::schema())?;
-                let transaction_ctx = transaction::Ctx::new(Ctx::conn_ctx().clone());
+                let data_sets = load_data_sets(ctx.main_namespace(), None, false, ctx.schema())?;
+                let transaction_ctx = transaction::Ctx::new(ctx.conn_ctx()?.clone());
                 seed(
                     seed_from_dangerous_operation(dangerous_operation)?,
                     data_sets,
                     transaction_ctx,
-                    false,
                 )
                 .await?
             }
             DangerousOperations::PurgeAndSeed => {
-                purge::purge().await?;
-                connect_databases(Ctx::main_namespace_mut(),true).await?;
-                let data_sets = load_data_sets(Ctx::main_namespace(), None, false, Ctx::schema())?;
-                let transaction_ctx = transaction::Ctx::new(Ctx::conn_ctx().clone());
-                seed(SeedCommandAction::Seed, data_sets, transaction_ctx, false).await?
+                purge::purge(ctx).await?;
+                let data_sets = load_data_sets(ctx.main_namespace(), None, false, ctx.schema())?;
+                let transaction_ctx = transaction::Ctx::new(ctx.conn_ctx()?.clone());
+                seed(SeedCommandAction::Seed, data_sets, transaction_ctx).await?
             }
-            DangerousOperations::Purge => purge::purge().await?,
+            DangerousOperations::Purge => purge::purge(ctx).await?,
         }
         Ok(Response::data(Value::Bool(true)))
 }
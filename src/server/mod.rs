@@ -4,3 +4,13 @@ pub mod request;
 pub mod responder;
 pub mod error;
 pub mod static_files;
+pub mod session;
+pub mod limits;
+pub(crate) mod options;
+pub mod rate_limit;
+pub mod compression;
+pub mod conditional;
+pub mod watch;
+pub mod mount;
+#[cfg(feature = "tower")]
+pub mod tower;
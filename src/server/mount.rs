@@ -0,0 +1,30 @@
+use actix_http::body::MessageBody;
+use actix_web::dev::{ServiceFactory, ServiceRequest, ServiceResponse};
+use actix_web::Scope;
+use teo_result::Result;
+use crate::app::ctx::Ctx;
+use crate::server::make::make_server_scope;
+
+/// The request handling of an app as an actix scope, to be mounted under
+/// `path` of an existing actix app.
+///
+/// Routing, the middleware stack and builtin actions work as they do when
+/// the app is served by itself. Get `ctx` from `App::mount`. The scope is
+/// usually built inside the app factory of `HttpServer::new`:
+///
+/// ```ignore
+/// let ctx = app.mount().await?;
+/// HttpServer::new(move || {
+///     actix_web::App::new().service(actix_scope(&ctx, "/api").unwrap())
+/// })
+/// ```
+pub fn actix_scope(ctx: &Ctx, path: &str) -> Result<Scope<impl ServiceFactory<
+    ServiceRequest,
+    Response = ServiceResponse<impl MessageBody>,
+    Config = (),
+    InitError = (),
+    Error = actix_web::Error,
+> + 'static>> {
+    let main_namespace = ctx.shared_namespace()?;
+    Ok(make_server_scope(ctx.clone(), main_namespace, path))
+}
@@ -0,0 +1,351 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+use actix_web::cookie::SameSite;
+use teo_parser::ast::decorator::Decorator;
+use teo_parser::ast::schema::Schema;
+use teo_parser::traits::identifiable::Identifiable;
+use teo_parser::traits::named_identifiable::NamedIdentifiable;
+use teo_parser::traits::node_trait::NodeTrait;
+use teo_parser::traits::resolved::Resolve;
+use teo_result::{Error, Result};
+use teo_teon::value::Value;
+use crate::app::config::{config_blocks, integer, ConfigBlock};
+use crate::app::ctx::Ctx;
+use crate::app::environment::interpolate;
+use crate::lint::rules::{argument, decorator, handler_declarations, is_user_declared, location};
+use crate::server::compression::CompressionConfig;
+use crate::server::conditional::CachePolicy;
+use crate::server::session::database::{DatabaseSessionStore, STD_SESSION_MODEL};
+use crate::server::session::store::MemorySessionStore;
+use crate::server::session::SessionConfig;
+
+/// Keys of a `server` block which are read by teo rather than the runtime.
+/// Like the block itself, they may be overridden per environment.
+pub(crate) const SERVER_OPTION_KEYS: [&str; 7] = ["session", "requestTimeout", "handlerTimeouts", "concurrencyLimit", "concurrencyQueue", "handlerConcurrencyLimits", "compression"];
+
+/// The number of requests which may wait for a slot, per slot, when
+/// `concurrencyLimit` is given without `concurrencyQueue`, or a handler
+/// concurrency limit is given without its queue.
+const DEFAULT_QUEUE_FACTOR: usize = 4;
+
+/// Server settings given in the schema. Timeouts are in seconds.
+///
+/// ```teo
+/// server {
+///   bind ("0.0.0.0", 5050)
+///   requestTimeout 30
+///   handlerTimeouts { "Report.generate": 120 }
+///   concurrencyLimit 100
+///   concurrencyQueue 400
+///   handlerConcurrencyLimits { "Report.generate": 2, "Export": { "limit": 1, "queue": 0 } }
+///   compression { "minSize": 1024 }
+///   session { "secret": "${SESSION_SECRET}", "ttl": 86400, "store": "database" }
+/// }
+/// ```
+///
+/// Settings made in code with the `App` methods take precedence, since
+/// they are made after these are applied.
+#[derive(Debug, Default)]
+pub(crate) struct ServerOptions {
+    pub(crate) session: Option<SessionOptions>,
+    pub(crate) request_timeout: Option<u64>,
+    pub(crate) handler_timeouts: BTreeMap<String, u64>,
+    pub(crate) concurrency_limit: Option<u64>,
+    pub(crate) concurrency_queue: Option<u64>,
+    /// Handler concurrency limits by handler path, with their queues.
+    pub(crate) handler_concurrency_limits: BTreeMap<String, (u64, Option<u64>)>,
+    pub(crate) compression: Option<CompressionConfig>,
+}
+
+/// The `session` item of a `server` block.
+#[derive(Debug, Default)]
+pub(crate) struct SessionOptions {
+    pub(crate) secret: String,
+    pub(crate) cookie_name: Option<String>,
+    pub(crate) ttl: Option<u64>,
+    pub(crate) secure: Option<bool>,
+    pub(crate) same_site: Option<SameSite>,
+    /// The path of the session model when sessions are kept in the database,
+    /// `std.Session` unless `model` is given.
+    pub(crate) model: Option<Vec<String>>,
+}
+
+impl ServerOptions {
+
+    fn set(&mut self, block: &ConfigBlock, key: &str, value: &Value) -> Result<()> {
+        match key {
+            "session" => self.session = Some(session_options(block, value)?),
+            "requestTimeout" => self.request_timeout = Some(non_negative(block, key, value)?),
+            "handlerTimeouts" => {
+                let Some(timeouts) = value.as_dictionary() else {
+                    Err(block.invalid("handlerTimeouts expects a dictionary of handler paths and seconds"))?
+                };
+                for (path, value) in timeouts {
+                    self.handler_timeouts.insert(path.clone(), non_negative(block, path, value)?);
+                }
+            }
+            "concurrencyLimit" => self.concurrency_limit = Some(positive(block, key, value)?),
+            "concurrencyQueue" => self.concurrency_queue = Some(non_negative(block, key, value)?),
+            "handlerConcurrencyLimits" => {
+                let Some(limits) = value.as_dictionary() else {
+                    Err(block.invalid("handlerConcurrencyLimits expects a dictionary of handler paths and limits"))?
+                };
+                for (path, value) in limits {
+                    let limit = match value {
+                        Value::Dictionary(items) => {
+                            let mut limit = None;
+                            let mut queue = None;
+                            for (key, value) in items {
+                                match key.as_str() {
+                                    "limit" => limit = Some(positive(block, path, value)?),
+                                    "queue" => queue = Some(non_negative(block, path, value)?),
+                                    _ => Err(block.invalid(&format!("unknown concurrency limit option `{}` of {}", key, path)))?,
+                                }
+                            }
+                            match limit {
+                                Some(limit) => (limit, queue),
+                                None => Err(block.invalid(&format!("concurrency limit of {} requires a limit", path)))?,
+                            }
+                        }
+                        value => (positive(block, path, value)?, None),
+                    };
+                    self.handler_concurrency_limits.insert(path.clone(), limit);
+                }
+            }
+            "compression" => self.compression = match value {
+                Value::Bool(false) => None,
+                Value::Bool(true) => Some(CompressionConfig::default()),
+                Value::Dictionary(items) => {
+                    let mut compression = CompressionConfig::default();
+                    for (key, value) in items {
+                        match key.as_str() {
+                            "minSize" => compression.min_size = non_negative(block, key, value)? as usize,
+                            _ => Err(block.invalid(&format!("unknown compression option `{}`", key)))?,
+                        }
+                    }
+                    Some(compression)
+                }
+                _ => Err(block.invalid("compression expects a bool or a dictionary"))?,
+            },
+            _ => Err(block.invalid(&format!("unknown server option `{}`", key)))?,
+        }
+        Ok(())
+    }
+
+    /// Configure `ctx` with these settings.
+    pub(crate) fn apply(self, ctx: &mut Ctx) -> Result<()> {
+        if let Some(session) = self.session {
+            let secret = interpolate(&session.secret)?;
+            let mut config = match session.model {
+                Some(model_path) => SessionConfig::new(secret, DatabaseSessionStore::new(model_path)),
+                None => SessionConfig::new(secret, MemorySessionStore::new()),
+            };
+            if let Some(cookie_name) = session.cookie_name {
+                config.cookie_name = cookie_name;
+            }
+            if let Some(ttl) = session.ttl {
+                config.ttl = Duration::from_secs(ttl);
+            }
+            if let Some(secure) = session.secure {
+                config.secure = secure;
+            }
+            if let Some(same_site) = session.same_site {
+                config.same_site = same_site;
+            }
+            ctx.set_session_config(config)?;
+        }
+        let limits = ctx.request_limits_mut()?;
+        if let Some(timeout) = self.request_timeout {
+            limits.set_timeout(Duration::from_secs(timeout));
+        }
+        for (path, timeout) in &self.handler_timeouts {
+            limits.set_handler_timeout(path, Duration::from_secs(*timeout));
+        }
+        if let Some(max) = self.concurrency_limit {
+            let max = max as usize;
+            let queue = self.concurrency_queue.map_or(max * DEFAULT_QUEUE_FACTOR, |queue| queue as usize);
+            limits.set_concurrency(max, queue);
+        }
+        for (path, (max, queue)) in &self.handler_concurrency_limits {
+            let max = *max as usize;
+            let queue = queue.map_or(max * DEFAULT_QUEUE_FACTOR, |queue| queue as usize);
+            limits.set_handler_concurrency(path, max, queue);
+        }
+        if let Some(compression) = self.compression {
+            ctx.set_compression(compression)?;
+        }
+        Ok(())
+    }
+}
+
+/// Configure `ctx` with the server options and cache policies of its
+/// schema.
+pub(crate) fn apply_schema_options(ctx: &mut Ctx) -> Result<()> {
+    let options = server_options(ctx.schema(), ctx.environment())?;
+    let policies = cache_policies(ctx.schema())?;
+    options.apply(ctx)?;
+    for (path, policy) in policies {
+        ctx.insert_cache_policy(&path, policy)?;
+    }
+    Ok(())
+}
+
+/// The options of the main `server` block, with those of the
+/// `server <environment>` block applied.
+pub(crate) fn server_options(schema: &Schema, environment: Option<&str>) -> Result<ServerOptions> {
+    let blocks = config_blocks(schema)?;
+    let mut result = ServerOptions::default();
+    let defaults = blocks.iter().filter(|b| b.environment.is_none());
+    let overrides = blocks.iter().filter(|b| b.environment.is_some() && b.environment == environment);
+    for block in defaults.chain(overrides).filter(|b| b.keyword == "server" && b.namespace.is_empty()) {
+        for (key, value) in &block.items {
+            if SERVER_OPTION_KEYS.contains(key) {
+                result.set(block, key, value)?;
+            }
+        }
+    }
+    if result.concurrency_queue.is_some() && result.concurrency_limit.is_none() {
+        Err(Error::new("concurrencyQueue is given without concurrencyLimit"))?
+    }
+    Ok(result)
+}
+
+fn session_options(block: &ConfigBlock, value: &Value) -> Result<SessionOptions> {
+    let Some(items) = value.as_dictionary() else {
+        Err(block.invalid("session expects a dictionary"))?
+    };
+    let mut options = SessionOptions::default();
+    let mut store = "memory";
+    for (key, value) in items {
+        match (key.as_str(), value) {
+            ("secret", Value::String(secret)) => options.secret = secret.clone(),
+            ("cookie", Value::String(name)) => options.cookie_name = Some(name.clone()),
+            ("ttl", value) => options.ttl = Some(non_negative(block, "session ttl", value)?),
+            ("secure", Value::Bool(secure)) => options.secure = Some(*secure),
+            ("sameSite", Value::String(same_site)) => options.same_site = Some(match same_site.as_str() {
+                "strict" => SameSite::Strict,
+                "lax" => SameSite::Lax,
+                "none" => SameSite::None,
+                _ => Err(block.invalid("session sameSite expects \"strict\", \"lax\" or \"none\""))?,
+            }),
+            ("store", Value::String(name)) if name == "memory" || name == "database" => store = name.as_str(),
+            ("model", Value::String(path)) => options.model = Some(path.split('.').map(ToOwned::to_owned).collect()),
+            _ => Err(block.invalid(&format!("invalid session option `{}`", key)))?,
+        }
+    }
+    if options.secret.is_empty() {
+        Err(block.invalid("session requires a secret"))?
+    }
+    match (store, options.model.is_some()) {
+        ("database", false) => options.model = Some(STD_SESSION_MODEL.iter().map(|name| name.to_string()).collect()),
+        ("memory", true) => Err(block.invalid("session model is only used by the database store"))?,
+        _ => (),
+    }
+    Ok(options)
+}
+
+fn non_negative(block: &ConfigBlock, key: &str, value: &Value) -> Result<u64> {
+    match integer(value).and_then(|number| u64::try_from(number).ok()) {
+        Some(number) => Ok(number),
+        None => Err(block.invalid(&format!("{} expects a non negative integer", key))),
+    }
+}
+
+fn positive(block: &ConfigBlock, key: &str, value: &Value) -> Result<u64> {
+    match non_negative(block, key, value)? {
+        0 => Err(block.invalid(&format!("{} expects a positive integer", key))),
+        number => Ok(number),
+    }
+}
+
+/// Cache policies of the `@cache` decorators on models and handler
+/// declarations, by handler key.
+///
+/// ```teo
+/// @cache(control: "private, max-age=60", lastModified: "updatedAt")
+/// model Article { ... }
+/// ```
+///
+/// A policy on a model applies to its read actions and the `GET` handlers of
+/// its handler group.
+pub(crate) fn cache_policies(schema: &Schema) -> Result<BTreeMap<String, CachePolicy>> {
+    let mut result = BTreeMap::new();
+    let models = schema.models().into_iter().filter(|model| is_user_declared(schema, *model));
+    for model in models {
+        if let Some(cache) = decorator(model.decorators(), "cache") {
+            result.insert(model.string_path().join("."), cache_policy(schema, model, cache)?);
+        }
+    }
+    for (key, handler) in handler_declarations(schema) {
+        if let Some(cache) = decorator(handler.decorators(), "cache") {
+            result.insert(key, cache_policy(schema, handler, cache)?);
+        }
+    }
+    Ok(result)
+}
+
+fn cache_policy<N: Identifiable + NodeTrait>(schema: &Schema, node: &N, cache: &Decorator) -> Result<CachePolicy> {
+    let mut policy = CachePolicy::default();
+    for (name, target) in [("control", &mut policy.cache_control), ("lastModified", &mut policy.last_modified_field)] {
+        let Some(expression) = argument(cache, name) else { continue };
+        match expression.resolved().value.as_ref() {
+            Some(Value::String(value)) => *target = Some(value.clone()),
+            _ => {
+                let location = location(schema, node);
+                Err(Error::new(format!("{}:{}: @cache {} expects a string", location.file, location.line, name)))?
+            }
+        }
+    }
+    Ok(policy)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test::schema::parse_schema_source;
+    use super::{cache_policies, server_options};
+
+    const CONNECTOR: &str = "connector {\n  provider .sqlite\n  url \"sqlite::memory:\"\n}\n\n";
+
+    #[test]
+    fn reads_server_options() {
+        let schema = parse_schema_source(&format!("{}server {{\n  bind (\"0.0.0.0\", 5050)\n  requestTimeout 30\n  handlerTimeouts {{ \"Artist.findMany\": 5 }}\n  concurrencyLimit 10\n  handlerConcurrencyLimits {{ \"Report.generate\": 2, \"Export\": {{ \"limit\": 1, \"queue\": 0 }} }}\n  compression {{ \"minSize\": 256 }}\n  session {{ \"secret\": \"s3cret\", \"ttl\": 60, \"store\": \"database\", \"model\": \"Session\" }}\n}}\n\nserver production {{\n  requestTimeout 10\n}}\n", CONNECTOR));
+        let options = server_options(&schema, None).unwrap();
+        assert_eq!(options.request_timeout, Some(30));
+        assert_eq!(options.handler_timeouts.get("Artist.findMany"), Some(&5));
+        assert_eq!(options.concurrency_limit, Some(10));
+        assert_eq!(options.handler_concurrency_limits.get("Report.generate"), Some(&(2, None)));
+        assert_eq!(options.handler_concurrency_limits.get("Export"), Some(&(1, Some(0))));
+        assert_eq!(options.compression.as_ref().map(|c| c.min_size), Some(256));
+        let session = options.session.unwrap();
+        assert_eq!(session.secret, "s3cret");
+        assert_eq!(session.ttl, Some(60));
+        assert_eq!(session.model, Some(vec!["Session".to_owned()]));
+        assert_eq!(server_options(&schema, Some("production")).unwrap().request_timeout, Some(10));
+    }
+
+    #[test]
+    fn rejects_invalid_server_options() {
+        let schema = parse_schema_source(&format!("{}server {{\n  bind (\"0.0.0.0\", 5050)\n  session {{ \"secret\": \"s3cret\", \"model\": \"Session\" }}\n}}\n", CONNECTOR));
+        let err = server_options(&schema, None).unwrap_err();
+        assert!(err.to_string().contains("only used by the database store"), "{}", err);
+        let schema = parse_schema_source(&format!("{}server {{\n  bind (\"0.0.0.0\", 5050)\n  concurrencyLimit 0\n}}\n", CONNECTOR));
+        assert!(server_options(&schema, None).is_err());
+    }
+
+    #[test]
+    fn keeps_sessions_in_the_std_model_by_default() {
+        let schema = parse_schema_source(&format!("{}server {{\n  bind (\"0.0.0.0\", 5050)\n  session {{ \"secret\": \"s3cret\", \"store\": \"database\" }}\n}}\n", CONNECTOR));
+        let session = server_options(&schema, None).unwrap().session.unwrap();
+        assert_eq!(session.model, Some(vec!["std".to_owned(), "Session".to_owned()]));
+        assert!(schema.models().into_iter().any(|model| model.string_path().join(".") == "std.Session"));
+    }
+
+    #[test]
+    fn reads_cache_decorators() {
+        let schema = parse_schema_source(&format!("{}@cache(control: \"private, max-age=60\", lastModified: \"updatedAt\")\nmodel Article {{\n  @id @autoIncrement\n  id: Int\n  updatedAt: DateTime\n}}\n", CONNECTOR));
+        let policies = cache_policies(&schema).unwrap();
+        let policy = policies.get("Article").unwrap();
+        assert_eq!(policy.cache_control.as_deref(), Some("private, max-age=60"));
+        assert_eq!(policy.last_modified_field.as_deref(), Some("updatedAt"));
+    }
+}
@@ -6,18 +6,30 @@ use regex::Regex;
 use serde_json::{json, Value as JsonValue};
 use teo_result::{Result, Error};
 use teo_runtime::error_runtime_ext::ErrorRuntimeExt;
+use crate::server::compression::decompress_request_body;
 
-pub(super) async fn parse_json_body(mut payload: web::Payload) -> Result<JsonValue> {
+/// The max size of a request body which is read into memory. Form bodies are
+/// streamed into files and not limited by this.
+pub(crate) const MAX_IN_MEMORY_BODY_SIZE: usize = 262_144;
+
+pub(super) async fn read_body(http_request: &HttpRequest, mut payload: web::Payload) -> Result<web::Bytes> {
     let mut body = web::BytesMut::new();
     while let Some(chunk) = payload.next().await {
-        let chunk = chunk.unwrap();
+        let chunk = match chunk {
+            Ok(chunk) => chunk,
+            Err(_) => return Err(Error::value_error_message_only("incorrect request body")),
+        };
         // limit max size of in-memory payload
-        if (body.len() + chunk.len()) > 262_144usize {
+        if (body.len() + chunk.len()) > MAX_IN_MEMORY_BODY_SIZE {
             return Err(Error::internal_server_error_message_only("memory overflow"));
         }
         body.extend_from_slice(&chunk);
     }
-    let parsed_json_body_result: std::result::Result<JsonValue, serde_json::Error> = serde_json::from_slice(&body);
+    decompress_request_body(http_request, body.freeze(), MAX_IN_MEMORY_BODY_SIZE)
+}
+
+pub(super) fn parse_json_body(body: &[u8]) -> Result<JsonValue> {
+    let parsed_json_body_result: std::result::Result<JsonValue, serde_json::Error> = serde_json::from_slice(body);
     let parsed_json_body = match parsed_json_body_result {
         Ok(b) => b,
         Err(_) => {
@@ -38,17 +50,24 @@ pub(super) async fn parse_form_body(http_request: HttpRequest, mut payload: web:
         Err(err) => return Err(Error::value_error_message_only("incorrect form format")),
     };
     let mut result_value = json!({});
-    while let Some(mut field) = multipart.try_next().await.unwrap() {
+    while let Some(mut field) = multipart.try_next().await.map_err(|_| Error::value_error_message_only("incorrect form format"))? {
         // A multipart/form-data stream has to contain `content_disposition`
         if let Some(filename) = field.content_disposition().get_filename().map(|f| f.to_owned()) {
-            let filepath = std::env::temp_dir().join(filename.clone()).to_str().unwrap().to_owned();
+            let filepath = match std::env::temp_dir().join(filename.clone()).to_str() {
+                Some(filepath) => filepath.to_owned(),
+                None => return Err(Error::value_error_message_only("incorrect file name")),
+            };
             let filepath2 = filepath.clone();
             // File::create is blocking operation, use threadpool
-            let mut f = web::block(move || std::fs::File::create(&filepath)).await.unwrap().unwrap();
+            let mut f = web::block(move || std::fs::File::create(&filepath)).await
+                .map_err(|e| Error::internal_server_error_message_only(e.to_string()))?
+                .map_err(|e| Error::internal_server_error_message_only(e.to_string()))?;
             // Field in turn is stream of *Bytes* object
-            while let Some(chunk) = field.try_next().await.unwrap() {
+            while let Some(chunk) = field.try_next().await.map_err(|_| Error::value_error_message_only("incorrect form format"))? {
                 // filesystem operations are blocking, we have to use threadpool
-                f = web::block(move || f.write_all(&chunk).map(|_| f)).await.unwrap().unwrap();
+                f = web::block(move || f.write_all(&chunk).map(|_| f)).await
+                    .map_err(|e| Error::internal_server_error_message_only(e.to_string()))?
+                    .map_err(|e| Error::internal_server_error_message_only(e.to_string()))?;
             }
             let owned_field_name = field.name().to_owned();
             if owned_field_name.ends_with("[]") {
@@ -64,11 +83,14 @@ pub(super) async fn parse_form_body(http_request: HttpRequest, mut payload: web:
                 }));
             } else if owned_field_name.ends_with("]") {
                 let regex = Regex::new("(.*)\\[(.*)\\]").unwrap();
-                let found = regex.captures(&owned_field_name).unwrap();
+                let found = match regex.captures(&owned_field_name) {
+                    Some(found) => found,
+                    None => return Err(Error::value_error_message_only("incorrect form field name")),
+                };
                 let field_name = found.get(1).unwrap().as_str().to_owned();
                 let dict_name = found.get(2).unwrap().as_str().to_owned();
                 if !result_value.as_object_mut().unwrap().contains_key(&field_name) {
-                    result_value.as_object_mut().unwrap().insert(field_name.clone(), json!([]));
+                    result_value.as_object_mut().unwrap().insert(field_name.clone(), json!({}));
                 }
                 result_value.as_object_mut().unwrap().get_mut(&field_name).unwrap().as_object_mut().unwrap().insert(dict_name, json!({
                     "filepath": filepath2,
@@ -86,10 +108,14 @@ pub(super) async fn parse_form_body(http_request: HttpRequest, mut payload: web:
             }
         } else {
             let mut body = web::BytesMut::new();
-            while let Some(chunk) = field.try_next().await.unwrap() {
+            while let Some(chunk) = field.try_next().await.map_err(|_| Error::value_error_message_only("incorrect form format"))? {
                 body.extend_from_slice(&chunk);
             }
-            result_value.as_object_mut().unwrap().insert(field.name().to_owned(), serde_json::Value::String(String::from_utf8(body.as_ref().to_vec()).unwrap()));
+            let string_value = match String::from_utf8(body.as_ref().to_vec()) {
+                Ok(string_value) => string_value,
+                Err(_) => return Err(Error::value_error_message_only("incorrect form field encoding")),
+            };
+            result_value.as_object_mut().unwrap().insert(field.name().to_owned(), serde_json::Value::String(string_value));
         }
     }
     Ok(result_value)
@@ -0,0 +1,246 @@
+pub mod store;
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use actix_http::header::{HeaderName, HeaderValue};
+use actix_web::HttpResponse;
+use ring::digest;
+use teo_result::{Error, Result};
+use teo_runtime::connection::transaction;
+use teo_runtime::middleware::middleware::{Middleware, middleware_wrap_fn};
+use teo_runtime::middleware::next::Next;
+use teo_runtime::namespace::Namespace;
+use teo_runtime::request;
+use teo_runtime::handler::r#match::HandlerMatch;
+use teo_teon::Value;
+use crate::app::ctx::Ctx;
+use crate::server::error::WrapError;
+use crate::server::make::{handler_key, lookup_by_handler_key};
+use crate::server::rate_limit::store::{MemoryRateLimitStore, Quota, RateLimitStatus, RateLimitStore};
+use crate::server::request::{RequestExt, RequestImpl};
+
+/// What a client is identified by.
+#[derive(Clone)]
+pub enum RateLimitKey {
+    /// The client address, see `App::trusted_proxies`.
+    Ip,
+    /// The value of a request header, e.g. an API key.
+    Header(String),
+    /// The bearer token of the request, which the identity is authenticated
+    /// with. Requests without one fall back to the client address.
+    Identity,
+    /// The session of the request, see `App::session`. Requests without a
+    /// valid session cookie fall back to the client address.
+    Session,
+    /// A custom key. Requests without a key fall back to the client address.
+    /// Limits apply before the request body is read, so the ctx has no body.
+    Custom(Arc<dyn Fn(&request::Ctx) -> Option<String> + Send + Sync>),
+}
+
+/// Limits how many requests a client can send.
+///
+/// Register it with `App::rate_limit` and attach it to namespaces in the
+/// schema like a middleware. It's applied before the request body is read,
+/// so rejected requests cost no parsing.
+pub struct RateLimiter {
+    key: RateLimitKey,
+    quota: Quota,
+    handler_quotas: BTreeMap<String, Quota>,
+    store: Arc<dyn RateLimitStore>,
+}
+
+impl RateLimiter {
+
+    pub fn new(key: RateLimitKey, quota: Quota) -> Self {
+        Self {
+            key,
+            quota,
+            handler_quotas: BTreeMap::new(),
+            store: Arc::new(MemoryRateLimitStore::new()),
+        }
+    }
+
+    /// Use another quota for a handler, or for every handler under a
+    /// namespace or handler group, e.g. `"Artist.create"` or `"Artist"`.
+    pub fn handler_quota(mut self, path: &str, quota: Quota) -> Self {
+        self.handler_quotas.insert(path.to_owned(), quota);
+        self
+    }
+
+    /// Count requests in another store.
+    pub fn store(mut self, store: impl RateLimitStore + 'static) -> Self {
+        self.store = Arc::new(store);
+        self
+    }
+
+    fn client_key(&self, ctx: &request::Ctx) -> String {
+        let request = ctx.request();
+        let key = match &self.key {
+            RateLimitKey::Ip => None,
+            RateLimitKey::Header(name) => request.headers().get(name).map(|v| format!("header:{}", v)),
+            RateLimitKey::Identity => request.headers().get("authorization")
+                .and_then(|value| value.strip_prefix("Bearer "))
+                .map(|token| format!("identity:{}", hex_digest(token.trim()))),
+            RateLimitKey::Session => RequestImpl::of(request).and_then(|request_impl| {
+                let config = request_impl.app_ctx().session_config()?;
+                let cookie = request.cookie(&config.cookie_name)?;
+                let id = config.verify(cookie.value())?;
+                Some(format!("session:{}", id))
+            }),
+            RateLimitKey::Custom(f) => f(ctx).map(|v| format!("custom:{}", v)),
+        };
+        key.unwrap_or_else(|| {
+            let ip = request.client_ip();
+            format!("ip:{}", ip.map(|ip| ip.to_string()).unwrap_or_default())
+        })
+    }
+
+    fn quota_for<'a>(&self, handler: &'a str) -> (&'a str, &Quota) {
+        lookup_by_handler_key(&self.handler_quotas, handler).unwrap_or(("", &self.quota))
+    }
+
+    async fn hit(&self, ctx: &request::Ctx) -> Result<(Quota, RateLimitStatus)> {
+        let handler = handler_key(&ctx.handler_match());
+        let (scope, quota) = self.quota_for(&handler);
+        let key = format!("{}|{}", scope, self.client_key(ctx));
+        Ok((*quota, self.store.hit(&key, quota).await?))
+    }
+}
+
+/// The middleware which stands for a rate limiter in the schema. It passes
+/// requests on, the limiter is applied by `hit_rate_limiters` before the
+/// request body is read.
+pub(crate) fn rate_limit_middleware() -> &'static dyn Middleware {
+    middleware_wrap_fn(|ctx: request::Ctx, next: &'static dyn Next| async move {
+        next.call(ctx).await
+    })
+}
+
+/// The counts of the rate limiters which apply to a request.
+pub(crate) struct RateLimitHits {
+    hits: Vec<(Quota, RateLimitStatus)>,
+}
+
+impl RateLimitHits {
+
+    /// The 429 response if any limiter rejects the request.
+    pub(crate) fn rejection(&self, ctx: &Ctx) -> Option<HttpResponse> {
+        let (quota, status) = self.hits.iter().find(|(_, status)| !status.allowed)?;
+        let mut error = Error::new("too many requests");
+        error.code = Some(429);
+        let mut response = WrapError::from(error).error_response_with(ctx.error_formatter().as_ref());
+        insert_header(&mut response, "retry-after", (status.retry_after.as_secs_f64().ceil() as u64).max(1).to_string());
+        insert_limit_headers(&mut response, quota, status);
+        Some(response)
+    }
+
+    /// Add `X-RateLimit-*` headers of the most restrictive limiter.
+    pub(crate) fn set_headers(&self, response: &mut HttpResponse) {
+        if let Some((quota, status)) = self.hits.iter().min_by_key(|(_, status)| status.remaining) {
+            insert_limit_headers(response, quota, status);
+        }
+    }
+}
+
+/// Count the request against the rate limiters attached to the namespaces
+/// of `match_result`, from the main namespace inwards.
+pub(crate) async fn hit_rate_limiters(ctx: &Ctx, main_namespace: &Namespace, request_impl: &Arc<RequestImpl>, match_result: &HandlerMatch) -> Result<RateLimitHits> {
+    let limiters = attached_rate_limiters(ctx, main_namespace, &match_result.path());
+    let mut hits = vec![];
+    if limiters.is_empty() {
+        return Ok(RateLimitHits { hits });
+    }
+    let request_ctx = request::Ctx::new(
+        request::Request::new(request_impl.clone()),
+        Arc::new(Value::Null),
+        transaction::Ctx::new(ctx.conn_ctx()?.clone()),
+        match_result.clone(),
+    );
+    for limiter in limiters {
+        hits.push(limiter.hit(&request_ctx).await?);
+    }
+    Ok(RateLimitHits { hits })
+}
+
+fn attached_rate_limiters(ctx: &Ctx, main_namespace: &Namespace, path: &Vec<String>) -> Vec<Arc<RateLimiter>> {
+    let mut result = vec![];
+    let mut namespace = Some(main_namespace);
+    let mut names = path.iter();
+    while let Some(current) = namespace {
+        if let Some(block) = current.middlewares_block.as_ref() {
+            result.extend(block.uses.iter().filter_map(|r#use| ctx.rate_limiter(&r#use.path.join("."))));
+        }
+        namespace = names.next().and_then(|name| current.namespaces.get(name));
+    }
+    result
+}
+
+fn insert_limit_headers(response: &mut HttpResponse, quota: &Quota, status: &RateLimitStatus) {
+    insert_header(response, "x-ratelimit-limit", quota.limit.to_string());
+    insert_header(response, "x-ratelimit-remaining", status.remaining.to_string());
+    insert_header(response, "x-ratelimit-reset", (status.reset_after.as_secs_f64().ceil() as u64).to_string());
+}
+
+fn insert_header(response: &mut HttpResponse, name: &'static str, value: String) {
+    if let Ok(value) = HeaderValue::from_str(&value) {
+        response.headers_mut().insert(HeaderName::from_static(name), value);
+    }
+}
+
+fn hex_digest(value: &str) -> String {
+    digest::digest(&digest::SHA256, value.as_bytes()).as_ref().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_http::StatusCode;
+    use serde_json::json;
+    use crate::test::schema::app_for_schema_source;
+    use super::RateLimiter;
+    use super::RateLimitKey;
+    use super::store::Quota;
+
+    const SCHEMA: &str = "connector {\n  provider .sqlite\n  url \"sqlite::memory:\"\n}\n\nserver {\n  bind (\"0.0.0.0\", 4000)\n}\n\ndeclare middleware limit\n\nmiddlewares [limit]\n\nmodel Artist {\n  @id @autoIncrement @readonly\n  id: Int\n  name: String\n}\n";
+
+    #[actix_web::test]
+    async fn rejects_with_429_and_limit_headers() {
+        let mut app = app_for_schema_source(SCHEMA);
+        app.rate_limit("limit", RateLimiter::new(RateLimitKey::Header("x-api-key".to_owned()), Quota::per_minute(2))).unwrap();
+        let client = app.test_client_with_memory_database().await.unwrap();
+        client.set_header("x-api-key", "a");
+        let first = client.model("Artist").find_many(json!({})).await;
+        assert_eq!(first.status(), StatusCode::OK);
+        assert_eq!(first.header("X-RateLimit-Limit"), Some("2"));
+        assert_eq!(first.header("X-RateLimit-Remaining"), Some("1"));
+        assert_eq!(first.header("X-RateLimit-Reset"), Some("30"));
+        assert_eq!(first.header("Retry-After"), None);
+        client.model("Artist").find_many(json!({})).await;
+        let rejected = client.model("Artist").find_many(json!({})).await;
+        assert_eq!(rejected.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(rejected.header("X-RateLimit-Remaining"), Some("0"));
+        assert_eq!(rejected.header("Retry-After"), Some("30"));
+        client.set_header("x-api-key", "b");
+        assert_eq!(client.model("Artist").find_many(json!({})).await.status(), StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn keys_clients_by_their_bearer_tokens() {
+        let mut app = app_for_schema_source(SCHEMA);
+        app.rate_limit("limit", RateLimiter::new(RateLimitKey::Identity, Quota::per_minute(1))).unwrap();
+        let client = app.test_client_with_memory_database().await.unwrap();
+        client.set_header("authorization", "Bearer a");
+        assert_eq!(client.model("Artist").find_many(json!({})).await.status(), StatusCode::OK);
+        assert_eq!(client.model("Artist").find_many(json!({})).await.status(), StatusCode::TOO_MANY_REQUESTS);
+        client.set_header("authorization", "Bearer b");
+        assert_eq!(client.model("Artist").find_many(json!({})).await.status(), StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn rejects_before_the_body_is_validated() {
+        let mut app = app_for_schema_source(SCHEMA);
+        app.rate_limit("limit", RateLimiter::new(RateLimitKey::Ip, Quota::per_minute(1))).unwrap();
+        let client = app.test_client_with_memory_database().await.unwrap();
+        assert_eq!(client.model("Artist").find_many(json!({ "where": 1 })).await.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(client.model("Artist").find_many(json!({ "where": 1 })).await.status(), StatusCode::TOO_MANY_REQUESTS);
+    }
+}
@@ -0,0 +1,176 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use async_trait::async_trait;
+use teo_result::Result;
+
+/// How many requests are allowed in a window.
+#[derive(Debug, Copy, Clone)]
+pub struct Quota {
+    pub limit: u32,
+    pub window: Duration,
+}
+
+impl Quota {
+
+    pub fn new(limit: u32, window: Duration) -> Self {
+        Self { limit, window }
+    }
+
+    pub fn per_second(limit: u32) -> Self {
+        Self::new(limit, Duration::from_secs(1))
+    }
+
+    pub fn per_minute(limit: u32) -> Self {
+        Self::new(limit, Duration::from_secs(60))
+    }
+
+    pub fn per_hour(limit: u32) -> Self {
+        Self::new(limit, Duration::from_secs(60 * 60))
+    }
+}
+
+/// The result of counting a request against a quota.
+#[derive(Debug, Copy, Clone)]
+pub struct RateLimitStatus {
+    /// Whether the request is allowed.
+    pub allowed: bool,
+    /// How many requests are left in the current window.
+    pub remaining: u32,
+    /// How long until the quota is fully restored.
+    pub reset_after: Duration,
+    /// How long the client should wait before retrying a rejected request.
+    pub retry_after: Duration,
+}
+
+/// A backend which counts requests. Implement this to share rate limits
+/// between several server processes, e.g. with Redis.
+#[async_trait]
+pub trait RateLimitStore: Send + Sync {
+
+    /// Count a request for `key` against `quota`.
+    async fn hit(&self, key: &str, quota: &Quota) -> Result<RateLimitStatus>;
+}
+
+/// Buckets which are full again are forgotten at most once per this interval.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+    /// When the bucket is full again, and can be forgotten.
+    full_at: Instant,
+}
+
+struct Buckets {
+    map: HashMap<String, Bucket>,
+    swept_at: Instant,
+}
+
+/// A token bucket rate limit store in the process memory.
+pub struct MemoryRateLimitStore {
+    buckets: Mutex<Buckets>,
+}
+
+impl MemoryRateLimitStore {
+
+    pub fn new() -> Self {
+        Self { buckets: Mutex::new(Buckets { map: HashMap::new(), swept_at: Instant::now() }) }
+    }
+
+    fn hit_at(&self, key: &str, quota: &Quota, now: Instant) -> RateLimitStatus {
+        let capacity = quota.limit as f64;
+        let refill_per_sec = capacity / quota.window.as_secs_f64().max(f64::EPSILON);
+        let mut buckets = self.buckets.lock().unwrap();
+        if now.duration_since(buckets.swept_at) >= SWEEP_INTERVAL {
+            buckets.map.retain(|_, bucket| bucket.full_at > now);
+            buckets.swept_at = now;
+        }
+        let bucket = buckets.map.entry(key.to_owned()).or_insert(Bucket { tokens: capacity, updated_at: now, full_at: now });
+        bucket.tokens = (bucket.tokens + now.duration_since(bucket.updated_at).as_secs_f64() * refill_per_sec).min(capacity);
+        bucket.updated_at = now;
+        let allowed = bucket.tokens >= 1.0;
+        if allowed {
+            bucket.tokens -= 1.0;
+        }
+        let reset_after = quota.window.mul_f64((capacity - bucket.tokens) / capacity.max(1.0));
+        bucket.full_at = now + reset_after;
+        let retry_after = if allowed {
+            Duration::ZERO
+        } else {
+            quota.window.mul_f64((1.0 - bucket.tokens) / capacity.max(1.0))
+        };
+        RateLimitStatus {
+            allowed,
+            remaining: bucket.tokens.floor() as u32,
+            reset_after,
+            retry_after,
+        }
+    }
+}
+
+impl Default for MemoryRateLimitStore {
+
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl RateLimitStore for MemoryRateLimitStore {
+
+    async fn hit(&self, key: &str, quota: &Quota) -> Result<RateLimitStatus> {
+        Ok(self.hit_at(key, quota, Instant::now()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+    use super::{MemoryRateLimitStore, Quota, SWEEP_INTERVAL};
+
+    #[test]
+    fn rejects_requests_over_the_limit() {
+        let store = MemoryRateLimitStore::new();
+        let quota = Quota::per_minute(2);
+        let now = Instant::now();
+        let first = store.hit_at("a", &quota, now);
+        assert!(first.allowed);
+        assert_eq!(first.remaining, 1);
+        assert_eq!(first.retry_after, Duration::ZERO);
+        assert!(store.hit_at("a", &quota, now).allowed);
+        let rejected = store.hit_at("a", &quota, now);
+        assert!(!rejected.allowed);
+        assert_eq!(rejected.remaining, 0);
+        assert_eq!(rejected.retry_after, Duration::from_secs(30));
+        assert_eq!(rejected.reset_after, Duration::from_secs(60));
+        assert!(store.hit_at("b", &quota, now).allowed);
+    }
+
+    #[test]
+    fn refills_over_time() {
+        let store = MemoryRateLimitStore::new();
+        let quota = Quota::per_second(1);
+        let now = Instant::now();
+        assert!(store.hit_at("a", &quota, now).allowed);
+        assert!(!store.hit_at("a", &quota, now).allowed);
+        assert!(store.hit_at("a", &quota, now + Duration::from_secs(1)).allowed);
+    }
+
+    #[test]
+    fn sweeps_full_buckets_once_per_interval() {
+        let store = MemoryRateLimitStore::new();
+        let short = Quota::per_second(1);
+        let long = Quota::per_hour(1);
+        let now = Instant::now();
+        store.hit_at("short", &short, now);
+        store.hit_at("long", &long, now);
+        store.hit_at("other", &short, now + Duration::from_secs(2));
+        assert_eq!(store.buckets.lock().unwrap().map.len(), 3);
+        store.hit_at("other", &short, now + SWEEP_INTERVAL);
+        let buckets = store.buckets.lock().unwrap();
+        assert!(!buckets.map.contains_key("short"));
+        assert!(buckets.map.contains_key("long"));
+        assert!(buckets.map.contains_key("other"));
+    }
+}
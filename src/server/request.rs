@@ -1,14 +1,53 @@
-use std::sync::Arc;
-use actix_http::header::HeaderMap as HTTPHeaderMap;
-use actix_http::HttpMessage;
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex, OnceLock, Weak};
+use actix_http::header::{self, HeaderMap as HTTPHeaderMap};
+use actix_http::{Method, Uri};
+use actix_web::cookie::Cookie;
 use actix_web::HttpRequest;
+use actix_web::web::Bytes;
+use indexmap::IndexMap;
+use once_cell::sync::Lazy;
+use teo_result::{Error, Result};
 use teo_runtime::request::header::readonly::HeaderMap;
 use teo_runtime::request::request::r#trait;
+use teo_runtime::request::Request;
+use teo_runtime::error_runtime_ext::ErrorRuntimeExt;
+use crate::app::ctx::Ctx;
+
+tokio::task_local! {
+    /// The request which is being handled by the current task.
+    pub(crate) static CURRENT_REQUEST: Arc<RequestImpl>;
+}
+
+/// Requests built by this crate, by the address of their headers.
+///
+/// The runtime's `Request` only exposes the runtime trait, this is how
+/// `RequestExt` gets back to the `RequestImpl` it wraps. An entry is removed
+/// when its request is dropped.
+static REQUESTS: Lazy<Mutex<HashMap<usize, Weak<RequestImpl>>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+fn registry() -> std::sync::MutexGuard<'static, HashMap<usize, Weak<RequestImpl>>> {
+    REQUESTS.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn headers_key(header_map: &HeaderMap) -> usize {
+    Arc::as_ptr(&header_map.inner) as *const () as usize
+}
 
 pub struct HeadersImpl {
     pub http_headers: HTTPHeaderMap,
 }
 
+impl HeadersImpl {
+
+    /// Get the raw bytes of a header value. Use this for header values which
+    /// are not valid UTF-8.
+    pub fn get_bytes(&self, key: &str) -> Option<&[u8]> {
+        self.http_headers.get(key).map(|v| v.as_bytes())
+    }
+}
+
 impl teo_runtime::request::header::readonly::r#trait::HeaderMap for HeadersImpl {
 
     fn keys(&self) -> Vec<&str> {
@@ -24,48 +63,409 @@ impl teo_runtime::request::header::readonly::r#trait::HeaderMap for HeadersImpl
     }
 
     fn get(&self, key: &str) -> Option<&str> {
-        self.http_headers.get(key).map(|v| v.to_str().unwrap())
+        self.http_headers.get(key).and_then(|v| v.to_str().ok())
     }
 }
 
 pub struct RequestImpl {
-    pub http_request: HttpRequest,
+    method: Method,
+    uri: Uri,
     pub header_map: HeaderMap,
+    headers: Arc<HeadersImpl>,
+    peer_addr: Option<SocketAddr>,
+    secure: bool,
+    default_host: String,
+    trusted_proxies: Arc<Vec<IpAddr>>,
+    body: OnceLock<Bytes>,
+    ctx: Ctx,
 }
 
 impl RequestImpl {
 
-    pub fn new(http_request: HttpRequest) -> Self {
-        Self {
-            http_request: http_request.clone(),
+    /// A request to the app of `ctx`.
+    pub fn new(http_request: &HttpRequest, ctx: Ctx) -> Arc<Self> {
+        Self::build(
+            http_request.method().clone(),
+            http_request.uri().clone(),
+            http_request.headers().clone(),
+            http_request.peer_addr(),
+            http_request.app_config().secure(),
+            http_request.app_config().host().to_owned(),
+            ctx,
+        )
+    }
+
+    /// A request which no client has sent, for running handlers outside of
+    /// the server, like the console does.
+    pub(crate) fn detached(path: &str, ctx: Ctx) -> Result<Arc<Self>> {
+        let uri = match path.parse::<Uri>() {
+            Ok(uri) => uri,
+            Err(_) => Err(Error::value_error_message_only(format!("invalid path {}", path)))?,
+        };
+        Ok(Self::build(Method::POST, uri, HTTPHeaderMap::new(), None, false, "localhost".to_owned(), ctx))
+    }
+
+    fn build(
+        method: Method,
+        uri: Uri,
+        http_headers: HTTPHeaderMap,
+        peer_addr: Option<SocketAddr>,
+        secure: bool,
+        default_host: String,
+        ctx: Ctx,
+    ) -> Arc<Self> {
+        let headers = Arc::new(HeadersImpl { http_headers });
+        let request = Arc::new(Self {
+            method,
+            uri,
             header_map: HeaderMap {
-                inner: Arc::new(HeadersImpl {
-                    http_headers: http_request.headers().clone()
-                })
+                inner: headers.clone(),
+            },
+            headers,
+            peer_addr,
+            secure,
+            default_host,
+            trusted_proxies: ctx.trusted_proxies(),
+            body: OnceLock::new(),
+            ctx,
+        });
+        registry().insert(headers_key(&request.header_map), Arc::downgrade(&request));
+        request
+    }
+
+    /// The request which is being handled by the current task, if any.
+    ///
+    /// This is available inside handlers and middlewares.
+    pub fn current() -> Option<Arc<RequestImpl>> {
+        CURRENT_REQUEST.try_with(|request| request.clone()).ok()
+    }
+
+    /// The request wrapped by `request`, if it's built by this crate.
+    pub fn of(request: &Request) -> Option<Arc<RequestImpl>> {
+        registry().get(&headers_key(request.headers())).and_then(Weak::upgrade)
+    }
+
+    /// The ctx of the app this request is sent to.
+    pub fn app_ctx(&self) -> &Ctx {
+        &self.ctx
+    }
+
+    /// The raw bytes of a header value.
+    pub fn header_bytes(&self, key: &str) -> Option<&[u8]> {
+        self.headers.get_bytes(key)
+    }
+
+    /// All cookies sent with this request.
+    pub fn cookies(&self) -> Result<Vec<Cookie<'static>>> {
+        let mut cookies = vec![];
+        for value in self.headers.http_headers.get_all(header::COOKIE) {
+            let value = match value.to_str() {
+                Ok(value) => value,
+                Err(_) => Err(Error::value_error_message_only("incorrect cookie format"))?,
+            };
+            for pair in value.split(';').map(str::trim).filter(|pair| !pair.is_empty()) {
+                match Cookie::parse_encoded(pair) {
+                    Ok(cookie) => cookies.push(cookie.into_owned()),
+                    Err(_) => Err(Error::value_error_message_only("incorrect cookie format"))?,
+                }
             }
         }
+        Ok(cookies)
+    }
+
+    /// The cookie with `name`.
+    pub fn cookie(&self, name: &str) -> Option<Cookie<'static>> {
+        self.cookies().ok()?.into_iter().find(|cookie| cookie.name() == name)
+    }
+
+    /// The parsed query parameters. When a key appears several times, the
+    /// last value wins. Use `query_all` to get every value.
+    pub fn query(&self) -> IndexMap<String, String> {
+        parse_query(self.uri.query().unwrap_or_default())
+    }
+
+    /// Every value of the query parameter `key`.
+    pub fn query_all(&self, key: &str) -> Vec<String> {
+        parse_query_all(self.uri.query().unwrap_or_default(), key)
+    }
+
+    /// The address of the connected peer.
+    pub fn peer_addr(&self) -> Option<SocketAddr> {
+        self.peer_addr
+    }
+
+    /// The address of the client. Forwarding headers are only honored when
+    /// the peer is a trusted proxy.
+    pub fn client_ip(&self) -> Option<IpAddr> {
+        let peer_ip = self.peer_addr()?.ip();
+        if !self.peer_is_trusted() {
+            return Some(peer_ip);
+        }
+        if let Some(forwarded_for) = self.header_str("x-forwarded-for") {
+            let forwarded_ips: Vec<IpAddr> = forwarded_for.split(',').filter_map(|ip| ip.trim().parse().ok()).collect();
+            // walk from the nearest hop and skip our own proxies
+            if let Some(ip) = forwarded_ips.iter().rev().find(|ip| !self.trusted_proxies.contains(ip)) {
+                return Some(*ip);
+            }
+            if let Some(ip) = forwarded_ips.first() {
+                return Some(*ip);
+            }
+        }
+        if let Some(real_ip) = self.header_str("x-real-ip").and_then(|ip| ip.trim().parse().ok()) {
+            return Some(real_ip);
+        }
+        Some(peer_ip)
+    }
+
+    /// The scheme of the request, `http` or `https`.
+    pub fn scheme(&self) -> String {
+        if self.peer_is_trusted() {
+            if let Some(scheme) = self.forwarded("proto").or_else(|| self.header_str("x-forwarded-proto").map(ToOwned::to_owned)) {
+                return scheme;
+            }
+        }
+        if self.secure { "https".to_owned() } else { "http".to_owned() }
+    }
+
+    /// The host of the request.
+    pub fn host(&self) -> String {
+        if self.peer_is_trusted() {
+            if let Some(host) = self.forwarded("host").or_else(|| self.header_str("x-forwarded-host").map(ToOwned::to_owned)) {
+                return host;
+            }
+        }
+        match self.header_str("host") {
+            Some(host) => host.to_owned(),
+            None => self.default_host.clone(),
+        }
+    }
+
+    /// The raw body of the request. Only JSON bodies are kept, form bodies
+    /// are streamed into files.
+    pub fn body(&self) -> Option<&Bytes> {
+        self.body.get()
+    }
+
+    pub(crate) fn set_body(&self, body: Bytes) {
+        let _ = self.body.set(body);
+    }
+
+    fn peer_is_trusted(&self) -> bool {
+        match self.peer_addr() {
+            Some(peer_addr) => self.trusted_proxies.contains(&peer_addr.ip()),
+            None => false,
+        }
+    }
+
+    fn header_str(&self, key: &str) -> Option<&str> {
+        self.headers.http_headers.get(key).and_then(|v| v.to_str().ok())
+    }
+
+    /// A parameter of the nearest hop of the `Forwarded` header.
+    fn forwarded(&self, name: &str) -> Option<String> {
+        let hop = self.header_str("forwarded")?.split(',').next()?;
+        hop.split(';').find_map(|pair| {
+            let (key, value) = pair.split_once('=')?;
+            key.trim().eq_ignore_ascii_case(name).then(|| value.trim().trim_matches('"').to_owned())
+        })
+    }
+}
+
+impl Drop for RequestImpl {
+    fn drop(&mut self) {
+        let mut registry = registry();
+        let key = headers_key(&self.header_map);
+        // the entry is replaced if the headers were kept and their address
+        // is taken by another request, which cannot happen while they live
+        if registry.get(&key).map_or(false, |request| std::ptr::eq(request.as_ptr(), self)) {
+            registry.remove(&key);
+        }
     }
 }
 
 impl r#trait::Request for RequestImpl {
 
     fn method(&self) -> &str {
-        self.http_request.method().as_str()
+        self.method.as_str()
     }
 
     fn path(&self) -> &str {
-        self.http_request.path()
+        self.uri.path()
     }
 
     fn query_string(&self) -> &str {
-        self.http_request.query_string()
+        self.uri.query().unwrap_or_default()
     }
 
     fn content_type(&self) -> &str {
-        self.http_request.content_type()
+        match self.header_str(header::CONTENT_TYPE.as_str()) {
+            Some(content_type) => content_type.split(';').next().unwrap_or_default().trim(),
+            None => "",
+        }
     }
 
     fn headers(&self) -> &HeaderMap {
         &self.header_map
     }
-}
\ No newline at end of file
+}
+
+/// The accessors of `RequestImpl` on the request which handlers and
+/// middlewares get from `ctx.request()`.
+///
+/// Requests which are not built by this crate only have their headers and
+/// query.
+pub trait RequestExt {
+
+    /// The raw bytes of a header value, for values which are not valid
+    /// UTF-8.
+    fn header_bytes(&self, key: &str) -> Option<Vec<u8>>;
+
+    /// All cookies sent with the request.
+    fn cookies(&self) -> Result<Vec<Cookie<'static>>>;
+
+    /// The cookie with `name`.
+    fn cookie(&self, name: &str) -> Option<Cookie<'static>>;
+
+    /// The parsed query parameters, the last value of a repeated key wins.
+    fn query(&self) -> IndexMap<String, String>;
+
+    /// Every value of the query parameter `key`.
+    fn query_all(&self, key: &str) -> Vec<String>;
+
+    /// The address of the connected peer.
+    fn peer_addr(&self) -> Option<SocketAddr>;
+
+    /// The address of the client, see `App::trusted_proxies`.
+    fn client_ip(&self) -> Option<IpAddr>;
+
+    /// The scheme of the request, `http` or `https`.
+    fn scheme(&self) -> Option<String>;
+
+    /// The host of the request.
+    fn host(&self) -> Option<String>;
+
+    /// The raw body of a JSON request.
+    fn body(&self) -> Option<Bytes>;
+}
+
+impl RequestExt for Request {
+
+    fn header_bytes(&self, key: &str) -> Option<Vec<u8>> {
+        match RequestImpl::of(self) {
+            Some(request) => request.header_bytes(key).map(ToOwned::to_owned),
+            None => self.headers().get(key).map(|value| value.as_bytes().to_owned()),
+        }
+    }
+
+    fn cookies(&self) -> Result<Vec<Cookie<'static>>> {
+        match RequestImpl::of(self) {
+            Some(request) => request.cookies(),
+            None => Ok(vec![]),
+        }
+    }
+
+    fn cookie(&self, name: &str) -> Option<Cookie<'static>> {
+        RequestImpl::of(self)?.cookie(name)
+    }
+
+    fn query(&self) -> IndexMap<String, String> {
+        parse_query(self.query_string())
+    }
+
+    fn query_all(&self, key: &str) -> Vec<String> {
+        parse_query_all(self.query_string(), key)
+    }
+
+    fn peer_addr(&self) -> Option<SocketAddr> {
+        RequestImpl::of(self)?.peer_addr()
+    }
+
+    fn client_ip(&self) -> Option<IpAddr> {
+        RequestImpl::of(self)?.client_ip()
+    }
+
+    fn scheme(&self) -> Option<String> {
+        Some(RequestImpl::of(self)?.scheme())
+    }
+
+    fn host(&self) -> Option<String> {
+        match RequestImpl::of(self) {
+            Some(request) => Some(request.host()),
+            None => self.headers().get("host").map(ToOwned::to_owned),
+        }
+    }
+
+    fn body(&self) -> Option<Bytes> {
+        RequestImpl::of(self)?.body().cloned()
+    }
+}
+
+fn parse_query(query_string: &str) -> IndexMap<String, String> {
+    url::form_urlencoded::parse(query_string.as_bytes()).into_owned().collect()
+}
+
+fn parse_query_all(query_string: &str, key: &str) -> Vec<String> {
+    url::form_urlencoded::parse(query_string.as_bytes())
+        .filter(|(k, _)| k == key)
+        .map(|(_, v)| v.into_owned())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use actix_web::test::TestRequest;
+    use teo_runtime::request::Request;
+    use crate::test::schema::app_for_schema_source;
+    use super::{RequestExt, RequestImpl};
+
+    const SCHEMA: &str = "connector {\n  provider .sqlite\n  url \"sqlite::memory:\"\n}\n\nserver {\n  bind (\"0.0.0.0\", 4000)\n}\n";
+
+    fn build(test_request: TestRequest, trusted_proxies: Vec<&str>) -> Request {
+        let mut app = app_for_schema_source(SCHEMA);
+        app.trusted_proxies(trusted_proxies.into_iter().map(|ip| ip.parse().unwrap()).collect()).unwrap();
+        let peer: SocketAddr = "10.0.0.1:5000".parse().unwrap();
+        let http_request = test_request.peer_addr(peer).to_http_request();
+        Request::new(RequestImpl::new(&http_request, app.ctx().clone()))
+    }
+
+    #[test]
+    fn exposes_the_request_through_the_runtime_request() {
+        let request = build(TestRequest::post()
+            .uri("/Artist/findMany?tag=a&tag=b&page=2")
+            .insert_header(("cookie", "theme=dark; lang=en"))
+            .insert_header(("x-raw", &b"caf\xe9"[..])), vec![]);
+        assert_eq!(request.cookie("lang").map(|cookie| cookie.value().to_owned()), Some("en".to_owned()));
+        assert_eq!(request.cookies().unwrap().len(), 2);
+        assert_eq!(request.query().get("page").map(String::as_str), Some("2"));
+        assert_eq!(request.query_all("tag"), vec!["a", "b"]);
+        assert_eq!(request.headers().get("x-raw"), None);
+        assert_eq!(request.header_bytes("x-raw"), Some(b"caf\xe9".to_vec()));
+        assert_eq!(request.client_ip(), Some("10.0.0.1".parse().unwrap()));
+    }
+
+    #[test]
+    fn honors_forwarding_headers_of_trusted_proxies() {
+        let forwarded = || TestRequest::get()
+            .insert_header(("host", "internal"))
+            .insert_header(("x-forwarded-for", "1.2.3.4, 10.0.0.2"))
+            .insert_header(("forwarded", "proto=https;host=example.com"));
+        let request = build(forwarded(), vec!["10.0.0.1", "10.0.0.2"]);
+        assert_eq!(request.client_ip(), Some("1.2.3.4".parse().unwrap()));
+        assert_eq!(request.scheme().as_deref(), Some("https"));
+        assert_eq!(request.host().as_deref(), Some("example.com"));
+        let request = build(forwarded(), vec![]);
+        assert_eq!(request.client_ip(), Some("10.0.0.1".parse().unwrap()));
+        assert_eq!(request.scheme().as_deref(), Some("http"));
+        assert_eq!(request.host().as_deref(), Some("internal"));
+    }
+
+    #[test]
+    fn detached_requests_have_no_client() {
+        let app = app_for_schema_source(SCHEMA);
+        let request = Request::new(RequestImpl::detached("/Artist/findMany", app.ctx().clone()).unwrap());
+        assert_eq!(request.path(), "/Artist/findMany");
+        assert_eq!(request.client_ip(), None);
+        assert!(request.cookies().unwrap().is_empty());
+    }
+}
@@ -1,32 +1,46 @@
+use std::io::ErrorKind;
 use actix_web::{HttpRequest, HttpResponse};
 use actix_web::http::StatusCode;
+use teo_result::{Error, Result};
 use teo_runtime::response::body::BodyInner;
 use teo_runtime::response::Response;
+use teo_runtime::error_runtime_ext::ErrorRuntimeExt;
 use actix_files::NamedFile;
 
 pub trait IntoHttpResponse {
-    fn into_http_response(self, http_request: HttpRequest) -> HttpResponse;
+    fn into_http_response(self, http_request: HttpRequest) -> Result<HttpResponse>;
 }
 
 impl IntoHttpResponse for Response {
 
-    fn into_http_response(self, http_request: HttpRequest) -> HttpResponse {
+    fn into_http_response(self, http_request: HttpRequest) -> Result<HttpResponse> {
         let mut builder = HttpResponse::Ok();
-        builder.status(StatusCode::from_u16(self.code()).unwrap());
+        match StatusCode::from_u16(self.code()) {
+            Ok(status) => builder.status(status),
+            Err(_) => Err(Error::internal_server_error_message_only(format!("invalid response status code {}", self.code())))?,
+        };
         for key in self.headers().keys() {
-            builder.insert_header((key.clone(), self.headers().get(&key).unwrap().as_str()));
+            if let Some(value) = self.headers().get(&key) {
+                builder.insert_header((key.clone(), value.as_str()));
+            }
         }
         match self.body().inner.as_ref() {
             BodyInner::Empty => (),
-            BodyInner::String(content) => return builder.body(content.to_string()),
-            BodyInner::File(file) => return NamedFile::open(file).unwrap().into_response(&http_request),
+            BodyInner::String(content) => return Ok(builder.body(content.to_string())),
+            BodyInner::File(file) => return match NamedFile::open(file) {
+                Ok(named_file) => Ok(named_file.into_response(&http_request)),
+                Err(err) if err.kind() == ErrorKind::NotFound => Err(Error::not_found_message_only()),
+                Err(err) => Err(Error::internal_server_error_message_only(format!("cannot open file: {}", err))),
+            },
             BodyInner::Teon(value) => {
                 builder.content_type("application/json");
-                let json_value = serde_json::Value::try_from(value).unwrap();
-                let string_value = serde_json::to_string(&json_value).unwrap();
-                return builder.body(string_value);
+                let json_value = match serde_json::Value::try_from(value) {
+                    Ok(json_value) => json_value,
+                    Err(_) => Err(Error::internal_server_error_message_only("response value cannot be serialized"))?,
+                };
+                return Ok(builder.body(json_value.to_string()));
             }
         }
-        builder.finish()
+        Ok(builder.finish())
     }
 }
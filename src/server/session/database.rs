@@ -0,0 +1,111 @@
+use std::time::Duration;
+use async_trait::async_trait;
+use chrono::Utc;
+use key_path::path;
+use teo_result::{Error, Result};
+use teo_runtime::connection::transaction;
+use teo_runtime::model;
+use teo_runtime::model::Model;
+use teo_teon::teon;
+use crate::app::ctx::Ctx;
+use crate::server::session::store::{SessionData, SessionStore};
+
+/// The builtin session model, which is used unless another one is given.
+pub(crate) const STD_SESSION_MODEL: [&str; 2] = ["std", "Session"];
+
+/// A session store which keeps sessions in a model of the connected
+/// namespace.
+///
+/// By default sessions are kept in the builtin `std.Session` model. Another
+/// model declared in the schema can be used instead, it's expected to have
+/// these fields: `id: String`, `data: String` and `expiresAt: Int64`, where
+/// `expiresAt` is a unix timestamp in seconds.
+pub struct DatabaseSessionStore {
+    model_path: Vec<String>,
+}
+
+impl DatabaseSessionStore {
+
+    /// Keep sessions in the model at `model_path`, e.g. `["Session"]`.
+    pub fn new(model_path: Vec<String>) -> Self {
+        Self { model_path }
+    }
+}
+
+impl Default for DatabaseSessionStore {
+
+    fn default() -> Self {
+        Self::new(STD_SESSION_MODEL.iter().map(|name| name.to_string()).collect())
+    }
+}
+
+impl DatabaseSessionStore {
+
+    fn transaction_ctx(&self) -> Result<transaction::Ctx> {
+        match Ctx::current() {
+            Some(ctx) => Ok(transaction::Ctx::new(ctx.conn_ctx()?.clone())),
+            None => Err(Error::new("database session store is only available while handling requests")),
+        }
+    }
+
+    fn model<'a>(&self, ctx: &'a transaction::Ctx) -> Result<&'a Model> {
+        match ctx.namespace().model_at_path(&self.model_path.iter().map(AsRef::as_ref).collect()) {
+            Some(model) => Ok(model),
+            None => Err(Error::new(format!("session model `{}` is not found", self.model_path.join(".")))),
+        }
+    }
+
+    async fn find(&self, id: &str, ctx: &transaction::Ctx) -> Result<Option<model::Object>> {
+        let model = self.model(ctx)?;
+        ctx.find_unique(model, &teon!({ "where": { "id": id } }), None, path![]).await
+    }
+}
+
+#[async_trait]
+impl SessionStore for DatabaseSessionStore {
+
+    async fn load(&self, id: &str) -> Result<Option<SessionData>> {
+        let ctx = self.transaction_ctx()?;
+        let object = match self.find(id, &ctx).await? {
+            Some(object) => object,
+            None => return Ok(None),
+        };
+        let expires_at: i64 = object.get("expiresAt")?;
+        if expires_at <= Utc::now().timestamp() {
+            object.delete().await?;
+            return Ok(None);
+        }
+        let data: String = object.get("data")?;
+        match serde_json::from_str(&data) {
+            Ok(data) => Ok(Some(data)),
+            Err(_) => Ok(None),
+        }
+    }
+
+    async fn save(&self, id: &str, data: &SessionData, ttl: Duration) -> Result<()> {
+        let ctx = self.transaction_ctx()?;
+        let data = serde_json::to_string(data).map_err(|e| Error::new(e.to_string()))?;
+        let expires_at = Utc::now().timestamp() + ttl.as_secs() as i64;
+        if let Some(object) = self.find(id, &ctx).await? {
+            object.set("data", data)?;
+            object.set("expiresAt", expires_at)?;
+            object.save().await
+        } else {
+            let model = self.model(&ctx)?;
+            let object: model::Object = ctx.create_object(model, &teon!({
+                "id": id,
+                "data": data,
+                "expiresAt": expires_at,
+            }), None).await?;
+            object.save().await
+        }
+    }
+
+    async fn destroy(&self, id: &str) -> Result<()> {
+        let ctx = self.transaction_ctx()?;
+        if let Some(object) = self.find(id, &ctx).await? {
+            object.delete().await?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,366 @@
+pub mod store;
+pub mod database;
+
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use actix_web::{HttpRequest, HttpResponse, ResponseError};
+use actix_web::cookie::{Cookie, SameSite};
+use actix_web::cookie::time::Duration as CookieDuration;
+use ring::hmac;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value as JsonValue;
+use teo_result::{Error, Result};
+use teo_runtime::request;
+use teo_runtime::request::ctx::extract::ExtractFromRequestCtx;
+use crate::server::error::WrapError;
+use crate::server::session::store::{SessionData, SessionStore};
+
+tokio::task_local! {
+    static CURRENT_SESSION: Session;
+}
+
+/// Session settings.
+pub struct SessionConfig {
+    /// The name of the session cookie.
+    pub cookie_name: String,
+    /// The key which session cookies are signed with.
+    pub secret: Vec<u8>,
+    /// The `SameSite` attribute of the session cookie.
+    pub same_site: SameSite,
+    /// Whether the session cookie is only sent over HTTPS.
+    pub secure: bool,
+    /// Whether the session cookie is hidden from scripts.
+    pub http_only: bool,
+    /// The path of the session cookie.
+    pub path: String,
+    /// How long an untouched session lives.
+    pub ttl: Duration,
+    /// Where session data is kept.
+    pub store: Arc<dyn SessionStore>,
+}
+
+impl SessionConfig {
+
+    pub fn new(secret: impl Into<Vec<u8>>, store: impl SessionStore + 'static) -> Self {
+        Self {
+            cookie_name: "teo_session".to_owned(),
+            secret: secret.into(),
+            same_site: SameSite::Lax,
+            secure: true,
+            http_only: true,
+            path: "/".to_owned(),
+            ttl: Duration::from_secs(60 * 60 * 24 * 7),
+            store: Arc::new(store),
+        }
+    }
+
+    fn key(&self) -> hmac::Key {
+        hmac::Key::new(hmac::HMAC_SHA256, &self.secret)
+    }
+
+    fn sign(&self, id: &str) -> String {
+        format!("{}.{}", id, hex_encode(hmac::sign(&self.key(), id.as_bytes()).as_ref()))
+    }
+
+    /// The session id of a signed cookie value.
+    pub(crate) fn verify(&self, value: &str) -> Option<String> {
+        let (id, signature) = value.rsplit_once('.')?;
+        let signature = hex_decode(signature)?;
+        hmac::verify(&self.key(), id.as_bytes(), &signature).ok()?;
+        Some(id.to_owned())
+    }
+}
+
+struct SessionInner {
+    id: Option<String>,
+    previous_id: Option<String>,
+    data: SessionData,
+    modified: bool,
+    destroyed: bool,
+    disabled: bool,
+}
+
+/// The session of the current request.
+///
+/// Extract it in handlers and middlewares. Call `renew` whenever the
+/// privilege of the client changes, e.g. after signing in or out, to prevent
+/// session fixation. When sessions are not enabled, the extracted session
+/// is empty and inserting into it is an error.
+#[derive(Clone)]
+pub struct Session {
+    inner: Arc<Mutex<SessionInner>>,
+}
+
+impl Session {
+
+    fn new(id: Option<String>, data: SessionData) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(SessionInner {
+                id,
+                previous_id: None,
+                data,
+                modified: false,
+                destroyed: false,
+                disabled: false,
+            }))
+        }
+    }
+
+    fn disabled() -> Self {
+        let session = Self::new(None, SessionData::new());
+        session.inner.lock().unwrap().disabled = true;
+        session
+    }
+
+    /// The session of the request which is being handled by the current task.
+    pub fn current() -> Option<Session> {
+        CURRENT_SESSION.try_with(|session| session.clone()).ok()
+    }
+
+    /// The id of this session. New sessions don't have an id until saved.
+    pub fn id(&self) -> Option<String> {
+        self.inner.lock().unwrap().id.clone()
+    }
+
+    /// Get a value from the session.
+    pub fn get<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>> {
+        match self.inner.lock().unwrap().data.get(key) {
+            Some(value) => match serde_json::from_value(value.clone()) {
+                Ok(value) => Ok(Some(value)),
+                Err(e) => Err(Error::new(format!("cannot decode session value `{}`: {}", key, e))),
+            },
+            None => Ok(None),
+        }
+    }
+
+    /// Insert a value into the session.
+    pub fn insert<T: Serialize>(&self, key: &str, value: T) -> Result<()> {
+        let value = match serde_json::to_value(value) {
+            Ok(value) => value,
+            Err(e) => Err(Error::new(format!("cannot encode session value `{}`: {}", key, e)))?,
+        };
+        let mut inner = self.inner.lock().unwrap();
+        if inner.disabled {
+            Err(Error::new("sessions are not enabled, call `App::session` or add `session` to the server block"))?
+        }
+        inner.data.insert(key.to_owned(), value);
+        inner.modified = true;
+        Ok(())
+    }
+
+    /// Remove a value from the session.
+    pub fn remove(&self, key: &str) -> Option<JsonValue> {
+        let mut inner = self.inner.lock().unwrap();
+        let removed = inner.data.remove(key);
+        if removed.is_some() {
+            inner.modified = true;
+        }
+        removed
+    }
+
+    /// Remove every value from the session.
+    pub fn clear(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.data.clear();
+        inner.modified = true;
+    }
+
+    /// Give this session a new id while keeping its data.
+    pub fn renew(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.previous_id.is_none() {
+            inner.previous_id = inner.id.take();
+        }
+        inner.id = None;
+        inner.modified = true;
+    }
+
+    /// Remove this session and its cookie.
+    pub fn destroy(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.data.clear();
+        inner.destroyed = true;
+    }
+
+    async fn load(config: &SessionConfig, http_request: &HttpRequest) -> Result<Self> {
+        if let Some(cookie) = http_request.cookie(&config.cookie_name) {
+            if let Some(id) = config.verify(cookie.value()) {
+                if let Some(data) = config.store.load(&id).await? {
+                    return Ok(Self::new(Some(id), data));
+                }
+            }
+        }
+        Ok(Self::new(None, SessionData::new()))
+    }
+
+    async fn persist(&self, config: &SessionConfig, response: &mut HttpResponse) -> Result<()> {
+        let (id, previous_id, data, modified, destroyed) = {
+            let inner = self.inner.lock().unwrap();
+            (inner.id.clone(), inner.previous_id.clone(), inner.data.clone(), inner.modified, inner.destroyed)
+        };
+        if let Some(previous_id) = previous_id {
+            config.store.destroy(&previous_id).await?;
+        }
+        if destroyed {
+            if let Some(id) = id {
+                config.store.destroy(&id).await?;
+            }
+            let mut cookie = self.cookie(config, String::new());
+            cookie.make_removal();
+            return add_cookie(response, cookie);
+        }
+        if !modified {
+            return Ok(());
+        }
+        let id = match id {
+            Some(id) => id,
+            None => generate_session_id()?,
+        };
+        config.store.save(&id, &data, config.ttl).await?;
+        self.inner.lock().unwrap().id = Some(id.clone());
+        add_cookie(response, self.cookie(config, config.sign(&id)))
+    }
+
+    fn cookie(&self, config: &SessionConfig, value: String) -> Cookie<'static> {
+        Cookie::build(config.cookie_name.clone(), value)
+            .path(config.path.clone())
+            .same_site(config.same_site)
+            .secure(config.secure)
+            .http_only(config.http_only)
+            .max_age(CookieDuration::seconds(config.ttl.as_secs() as i64))
+            .finish()
+    }
+}
+
+impl ExtractFromRequestCtx for Session {
+    fn extract(_ctx: &request::Ctx) -> Self {
+        Session::current().unwrap_or_else(Session::disabled)
+    }
+}
+
+/// Run `fut` with the session of `http_request`, then save the session and
+/// set the session cookie.
+pub(crate) async fn with_session<F>(config: Arc<SessionConfig>, http_request: HttpRequest, fut: F) -> std::result::Result<HttpResponse, WrapError> where F: Future<Output = std::result::Result<HttpResponse, WrapError>> {
+    let session = Session::load(&config, &http_request).await?;
+    let mut response = match CURRENT_SESSION.scope(session.clone(), fut).await {
+        Ok(response) => response,
+        Err(err) => err.error_response(),
+    };
+    session.persist(&config, &mut response).await?;
+    Ok(response)
+}
+
+fn add_cookie(response: &mut HttpResponse, cookie: Cookie<'static>) -> Result<()> {
+    match response.add_cookie(&cookie) {
+        Ok(()) => Ok(()),
+        Err(e) => Err(Error::new(format!("cannot set session cookie: {}", e))),
+    }
+}
+
+fn generate_session_id() -> Result<String> {
+    let mut bytes = [0u8; 32];
+    match SystemRandom::new().fill(&mut bytes) {
+        Ok(()) => Ok(hex_encode(&bytes)),
+        Err(_) => Err(Error::new("cannot generate session id")),
+    }
+}
+
+fn hex_encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn hex_decode(string: &str) -> Option<Vec<u8>> {
+    if string.len() % 2 != 0 {
+        return None;
+    }
+    (0..string.len()).step_by(2).map(|i| u8::from_str_radix(string.get(i..i + 2)?, 16).ok()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+    use actix_web::HttpResponse;
+    use actix_web::cookie::Cookie;
+    use actix_web::test::TestRequest;
+    use crate::server::session::store::MemorySessionStore;
+    use super::{Session, SessionConfig, with_session};
+
+    fn config(ttl: Duration) -> Arc<SessionConfig> {
+        let mut config = SessionConfig::new("secret", MemorySessionStore::new());
+        config.ttl = ttl;
+        Arc::new(config)
+    }
+
+    /// Send a request with `cookie`, run `f` with its session and return the
+    /// session cookie of the response.
+    async fn request(config: &Arc<SessionConfig>, cookie: Option<&str>, f: impl FnOnce(Session)) -> Option<String> {
+        let mut request = TestRequest::get();
+        if let Some(cookie) = cookie {
+            request = request.cookie(Cookie::new("teo_session", cookie.to_owned()));
+        }
+        let response = with_session(config.clone(), request.to_http_request(), async {
+            f(Session::current().unwrap());
+            Ok(HttpResponse::Ok().finish())
+        }).await.unwrap();
+        let cookie = response.cookies().find(|cookie| cookie.name() == "teo_session");
+        cookie.map(|cookie| cookie.value().to_owned())
+    }
+
+    #[test]
+    fn verifies_signatures() {
+        let config = config(Duration::from_secs(60));
+        let signed = config.sign("abc");
+        assert_eq!(config.verify(&signed), Some("abc".to_owned()));
+        assert_eq!(config.verify(&signed.replace("abc", "abd")), None);
+        assert_eq!(config.verify("abc"), None);
+        assert_eq!(config.verify("abc.zz"), None);
+        let other = SessionConfig::new("other", MemorySessionStore::new());
+        assert_eq!(other.verify(&signed), None);
+    }
+
+    #[actix_web::test]
+    async fn keeps_data_between_requests() {
+        let config = config(Duration::from_secs(60));
+        assert_eq!(request(&config, None, |_| ()).await, None);
+        let cookie = request(&config, None, |session| session.insert("user", 1).unwrap()).await.unwrap();
+        request(&config, Some(&cookie), |session| assert_eq!(session.get::<i32>("user").unwrap(), Some(1))).await;
+        let forged = format!("{}0", cookie);
+        request(&config, Some(&forged), |session| assert_eq!(session.get::<i32>("user").unwrap(), None)).await;
+    }
+
+    #[actix_web::test]
+    async fn renews_the_id_and_forgets_the_previous_one() {
+        let config = config(Duration::from_secs(60));
+        let cookie = request(&config, None, |session| session.insert("user", 1).unwrap()).await.unwrap();
+        let renewed = request(&config, Some(&cookie), |session| session.renew()).await.unwrap();
+        assert_ne!(renewed, cookie);
+        request(&config, Some(&renewed), |session| assert_eq!(session.get::<i32>("user").unwrap(), Some(1))).await;
+        request(&config, Some(&cookie), |session| assert_eq!(session.get::<i32>("user").unwrap(), None)).await;
+    }
+
+    #[actix_web::test]
+    async fn expires_sessions() {
+        let config = config(Duration::ZERO);
+        let cookie = request(&config, None, |session| session.insert("user", 1).unwrap()).await.unwrap();
+        request(&config, Some(&cookie), |session| assert_eq!(session.get::<i32>("user").unwrap(), None)).await;
+    }
+
+    #[actix_web::test]
+    async fn destroys_sessions() {
+        let config = config(Duration::from_secs(60));
+        let cookie = request(&config, None, |session| session.insert("user", 1).unwrap()).await.unwrap();
+        assert_eq!(request(&config, Some(&cookie), |session| session.destroy()).await, Some(String::new()));
+        request(&config, Some(&cookie), |session| assert_eq!(session.get::<i32>("user").unwrap(), None)).await;
+    }
+
+    #[test]
+    fn disabled_sessions_reject_inserts() {
+        let session = Session::disabled();
+        assert!(session.insert("user", 1).is_err());
+        assert_eq!(session.get::<i32>("user").unwrap(), None);
+    }
+}
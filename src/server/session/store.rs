@@ -0,0 +1,96 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use async_trait::async_trait;
+use serde_json::{Map, Value as JsonValue};
+use teo_result::Result;
+
+/// Session data is a JSON object.
+pub type SessionData = Map<String, JsonValue>;
+
+/// A backend which persists session data by session id.
+#[async_trait]
+pub trait SessionStore: Send + Sync {
+
+    /// Load the data of a session. Returns `None` if the session doesn't
+    /// exist or is expired.
+    async fn load(&self, id: &str) -> Result<Option<SessionData>>;
+
+    /// Save the data of a session, which expires after `ttl`.
+    async fn save(&self, id: &str, data: &SessionData, ttl: Duration) -> Result<()>;
+
+    /// Remove a session.
+    async fn destroy(&self, id: &str) -> Result<()>;
+}
+
+/// Expired sessions are forgotten at most once per this interval.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+struct Sessions {
+    map: HashMap<String, (SessionData, Instant)>,
+    swept_at: Instant,
+}
+
+/// A session store which keeps sessions in the process memory. Sessions are
+/// lost when the server restarts.
+pub struct MemorySessionStore {
+    sessions: Mutex<Sessions>,
+}
+
+impl MemorySessionStore {
+
+    pub fn new() -> Self {
+        Self { sessions: Mutex::new(Sessions { map: HashMap::new(), swept_at: Instant::now() }) }
+    }
+}
+
+impl Default for MemorySessionStore {
+
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl SessionStore for MemorySessionStore {
+
+    async fn load(&self, id: &str) -> Result<Option<SessionData>> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let now = Instant::now();
+        if now.duration_since(sessions.swept_at) >= SWEEP_INTERVAL {
+            sessions.map.retain(|_, (_, expires_at)| *expires_at > now);
+            sessions.swept_at = now;
+        }
+        Ok(sessions.map.get(id).filter(|(_, expires_at)| *expires_at > now).map(|(data, _)| data.clone()))
+    }
+
+    async fn save(&self, id: &str, data: &SessionData, ttl: Duration) -> Result<()> {
+        self.sessions.lock().unwrap().map.insert(id.to_owned(), (data.clone(), Instant::now() + ttl));
+        Ok(())
+    }
+
+    async fn destroy(&self, id: &str) -> Result<()> {
+        self.sessions.lock().unwrap().map.remove(id);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use serde_json::{json, Map};
+    use super::{MemorySessionStore, SessionStore};
+
+    #[actix_web::test]
+    async fn expires_sessions() {
+        let store = MemorySessionStore::new();
+        let mut data = Map::new();
+        data.insert("user".to_owned(), json!(1));
+        store.save("a", &data, Duration::from_secs(60)).await.unwrap();
+        store.save("b", &data, Duration::ZERO).await.unwrap();
+        assert_eq!(store.load("a").await.unwrap(), Some(data));
+        assert_eq!(store.load("b").await.unwrap(), None);
+        store.destroy("a").await.unwrap();
+        assert_eq!(store.load("a").await.unwrap(), None);
+    }
+}
@@ -0,0 +1,196 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::fmt::Display;
+use std::future::{ready, Future};
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Weak};
+use std::task::{Context, Poll};
+use actix_http::body::BoxBody;
+use actix_http::error::PayloadError;
+use actix_http::header::{HeaderName, HeaderValue};
+use actix_http::{Method, Payload, Request};
+use actix_web::web::Bytes;
+use actix_web::HttpResponse;
+use futures_util::TryStreamExt;
+use http_body::Body;
+use http_body_util::{BodyStream, Full};
+use teo_result::{Error, Result};
+use teo_runtime::error_runtime_ext::ErrorRuntimeExt;
+use teo_runtime::namespace::Namespace;
+use tokio::runtime::Handle;
+use tokio::task::{spawn_blocking, LocalSet};
+use crate::app::ctx::Ctx;
+use crate::server::error::WrapError;
+use crate::server::make::{make_local_service, LocalService};
+
+/// The request handling of an app as a `tower::Service`, to be mounted into
+/// an axum router or another tower based server.
+///
+/// Requests are handled on the runtime which calls the service. Actix
+/// services are not `Send`, so each request is driven on a blocking thread
+/// of that runtime, which keeps the app service it built for later requests.
+/// Request bodies are streamed in like they are from the actix server, so
+/// the same payload limits apply.
+///
+/// The peer address is read from a `SocketAddr` request extension, or from
+/// axum's `ConnectInfo` with the `axum` feature. Without it, rate limits
+/// keyed by client IP can't tell clients apart.
+#[derive(Clone)]
+pub struct TowerService {
+    ctx: Ctx,
+    main_namespace: Arc<Namespace>,
+    id: usize,
+    alive: Arc<()>,
+}
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    static SERVICES: RefCell<HashMap<usize, (Weak<()>, LocalService)>> = RefCell::new(HashMap::new());
+}
+
+impl TowerService {
+
+    /// Get `ctx` from `App::mount`.
+    pub fn new(ctx: &Ctx) -> Result<Self> {
+        Ok(Self {
+            main_namespace: ctx.shared_namespace()?,
+            ctx: ctx.clone(),
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            alive: Arc::new(()),
+        })
+    }
+
+    async fn local_service(&self) -> Result<LocalService> {
+        if let Some(service) = SERVICES.with(|services| services.borrow().get(&self.id).map(|(_, service)| service.clone())) {
+            return Ok(service);
+        }
+        let service = make_local_service(self.ctx.clone(), self.main_namespace.clone(), None).await?;
+        SERVICES.with(|services| {
+            let mut services = services.borrow_mut();
+            // drop the services of dropped tower services
+            services.retain(|_, (alive, _)| alive.strong_count() > 0);
+            services.insert(self.id, (Arc::downgrade(&self.alive), service.clone()));
+        });
+        Ok(service)
+    }
+
+    async fn handle<B>(self, parts: http::request::Parts, body: B) -> http::Response<Full<Bytes>> where B: Body<Data = Bytes> + 'static, B::Error: Display {
+        let handled = match self.local_service().await {
+            Ok(service) => match actix_request(parts, body) {
+                Ok(request) => Ok(service(request).await),
+                Err(err) => Err(err),
+            },
+            Err(err) => Err(err),
+        };
+        let response = match handled {
+            Ok(response) => response,
+            Err(err) => WrapError::from(err).error_response_with(self.ctx.error_formatter().as_ref()),
+        };
+        http_response(response).await
+    }
+}
+
+impl<B> tower_service::Service<http::Request<B>> for TowerService where B: Body<Data = Bytes> + Send + 'static, B::Error: Display {
+    type Response = http::Response<Full<Bytes>>;
+    type Error = Infallible;
+    type Future = Pin<Box<dyn Future<Output = std::result::Result<Self::Response, Infallible>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<std::result::Result<(), Infallible>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: http::Request<B>) -> Self::Future {
+        let service = self.clone();
+        Box::pin(async move {
+            let runtime = Handle::current();
+            let (parts, body) = request.into_parts();
+            let handled = spawn_blocking(move || {
+                runtime.block_on(LocalSet::new().run_until(service.handle(parts, body)))
+            }).await;
+            match handled {
+                Ok(response) => Ok(response),
+                Err(_) => Ok(plain_response(http::StatusCode::INTERNAL_SERVER_ERROR, "internal server error".to_owned())),
+            }
+        })
+    }
+}
+
+fn actix_request<B>(parts: http::request::Parts, body: B) -> Result<Request> where B: Body<Data = Bytes> + 'static, B::Error: Display {
+    let stream = BodyStream::new(body)
+        .try_filter_map(|frame| ready(Ok(frame.into_data().ok())))
+        .map_err(|err| PayloadError::Io(io::Error::new(io::ErrorKind::Other, err.to_string())));
+    let mut request = Request::with_payload(Payload::Stream { payload: Box::pin(stream) });
+    let head = request.head_mut();
+    head.method = match Method::from_bytes(parts.method.as_str().as_bytes()) {
+        Ok(method) => method,
+        Err(_) => Err(Error::value_error_message_only(format!("unknown http method {}", parts.method)))?,
+    };
+    head.uri = match parts.uri.to_string().parse() {
+        Ok(uri) => uri,
+        Err(_) => Err(Error::value_error_message_only(format!("invalid uri {}", parts.uri)))?,
+    };
+    for (name, value) in parts.headers.iter() {
+        match (HeaderName::from_bytes(name.as_str().as_bytes()), HeaderValue::from_bytes(value.as_bytes())) {
+            (Ok(name), Ok(value)) => head.headers.append(name, value),
+            _ => Err(Error::value_error_message_only(format!("invalid header {}", name)))?,
+        }
+    }
+    head.peer_addr = peer_addr(&parts.extensions);
+    Ok(request)
+}
+
+fn peer_addr(extensions: &http::Extensions) -> Option<SocketAddr> {
+    #[cfg(feature = "axum")]
+    if let Some(axum::extract::ConnectInfo(addr)) = extensions.get::<axum::extract::ConnectInfo<SocketAddr>>() {
+        return Some(*addr);
+    }
+    extensions.get::<SocketAddr>().copied()
+}
+
+async fn http_response(response: HttpResponse<BoxBody>) -> http::Response<Full<Bytes>> {
+    let mut builder = http::Response::builder().status(response.status().as_u16());
+    for (name, value) in response.headers() {
+        builder = builder.header(name.as_str(), value.as_bytes());
+    }
+    let body = actix_web::body::to_bytes(response.into_body()).await.unwrap_or_default();
+    match builder.body(Full::new(body)) {
+        Ok(response) => response,
+        Err(_) => plain_response(http::StatusCode::INTERNAL_SERVER_ERROR, "internal server error".to_owned()),
+    }
+}
+
+fn plain_response(status: http::StatusCode, message: String) -> http::Response<Full<Bytes>> {
+    let mut response = http::Response::new(Full::new(Bytes::from(message)));
+    *response.status_mut() = status;
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_http::HttpMessage;
+    use futures_util::StreamExt;
+    use super::*;
+
+    #[actix_web::test]
+    async fn copies_the_peer_address_and_streams_the_body() {
+        let addr: SocketAddr = "10.0.0.2:51000".parse().unwrap();
+        let mut request = http::Request::post("/User/findMany").body(Full::new(Bytes::from_static(b"{}"))).unwrap();
+        request.extensions_mut().insert(addr);
+        let (parts, body) = request.into_parts();
+        let mut request = actix_request(parts, body).unwrap();
+        assert_eq!(request.head().peer_addr, Some(addr));
+        let mut payload = request.take_payload();
+        assert_eq!(payload.next().await.unwrap().unwrap(), Bytes::from_static(b"{}"));
+        assert!(payload.next().await.is_none());
+    }
+
+    #[test]
+    fn has_no_peer_address_without_the_extension() {
+        assert_eq!(peer_addr(&http::Extensions::new()), None);
+    }
+}
@@ -0,0 +1,166 @@
+use std::collections::BTreeMap;
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+use actix_web::dev::Server as ActixServer;
+use teo_parser::ast::schema::Schema;
+use teo_parser::diagnostics::printer::print_diagnostics;
+use teo_result::{Error, Result};
+use teo_runtime::namespace::Namespace;
+use teo_runtime::schema::load::load_schema::load_schema;
+use crate::app::app::{new_main_namespace, parse_schema};
+use crate::app::ctx::Ctx;
+use crate::app::database::connect_databases;
+use crate::app::database::options::connector_options;
+use crate::app::environment::apply_environment_overrides;
+use crate::cli::entrance::Entrance;
+use crate::message::{error_message, info_message};
+use crate::migrate::migrate;
+use crate::server::make::{bind_listener, server_conf, server_start_message, start_server};
+
+/// Serve the main namespace and reload it whenever a schema file changes.
+///
+/// The listening socket is kept open across reloads. The changed schema is
+/// loaded and migrated while the previous one keeps serving, and replaces
+/// it only once its server runs. If the changed schema has errors or cannot
+/// be migrated, the previous one keeps serving.
+pub(crate) async fn serve_and_watch(mut ctx: Ctx, migrate_on_reload: bool, silent: bool) -> Result<()> {
+    if let Entrance::APP = ctx.entrance() {
+        Err(Error::new("watch mode is only available to the CLI, handlers defined in code cannot be reloaded"))?
+    }
+    let conf = server_conf(&ctx)?;
+    let listener = bind_listener(conf)?;
+    let mut server = Box::pin(start_server(ctx.clone(), clone_listener(&listener)?)?);
+    server_start_message(conf.bind.1 as u16, ctx.runtime_version(), ctx.entrance(), silent).await?;
+    let mut modified_times = schema_modified_times(&ctx);
+    loop {
+        tokio::select! {
+            result = server.as_mut() => {
+                return match result {
+                    Ok(()) => Ok(()),
+                    Err(err) => Err(Error::new(format!("server stopped unexpectedly: {}", err))),
+                };
+            }
+            _ = tokio::time::sleep(Duration::from_millis(500)) => {
+                let new_modified_times = schema_modified_times(&ctx);
+                if new_modified_times == modified_times {
+                    continue;
+                }
+                modified_times = new_modified_times;
+                info_message("schema changed, reloading");
+                let reloaded = match reload(&ctx, &listener, migrate_on_reload, silent).await {
+                    Ok(Some(reloaded)) => reloaded,
+                    Ok(None) => {
+                        error_message("schema has errors, keep serving the previous one");
+                        continue;
+                    }
+                    Err(err) => {
+                        error_message(format!("cannot reload schema, keep serving the previous one: {}", err));
+                        continue;
+                    }
+                };
+                // the new server takes new connections from now on, the
+                // previous one finishes its in-flight requests and stops
+                let (new_ctx, new_server) = reloaded;
+                let previous = std::mem::replace(&mut server, Box::pin(new_server));
+                let handle = previous.handle();
+                tokio::spawn(previous);
+                tokio::spawn(handle.stop(true));
+                ctx = new_ctx;
+                modified_times = schema_modified_times(&ctx);
+                info_message("schema reloaded");
+            }
+        }
+    }
+}
+
+/// Load and migrate the changed schema, and start serving it on
+/// `listener`. Returns `None` if the schema has errors.
+async fn reload(ctx: &Ctx, listener: &TcpListener, migrate_on_reload: bool, silent: bool) -> Result<Option<(Ctx, ActixServer)>> {
+    let Some((namespace, schema)) = load_changed_schema(ctx, silent).await? else {
+        return Ok(None);
+    };
+    let reloaded = ctx.reloaded(namespace, schema)?;
+    if migrate_on_reload {
+        migrate(&reloaded, false, false, silent).await?;
+    }
+    let server = start_server(reloaded.clone(), clone_listener(listener)?)?;
+    Ok(Some((reloaded, server)))
+}
+
+fn clone_listener(listener: &TcpListener) -> Result<TcpListener> {
+    listener.try_clone().map_err(|e| Error::new(e.to_string()))
+}
+
+/// Load the changed schema and connect its databases. Returns `None` if the
+/// schema has errors.
+async fn load_changed_schema(ctx: &Ctx, silent: bool) -> Result<Option<(Namespace, Schema)>> {
+    let (schema, diagnostics) = parse_schema(ctx.main_schema_file())?;
+    print_diagnostics(&diagnostics, true);
+    if diagnostics.has_errors() {
+        return Ok(None);
+    }
+    let mut namespace = new_main_namespace();
+    load_schema(&mut namespace, &schema, false).await?;
+    if let Some(environment) = ctx.environment() {
+        apply_environment_overrides(&mut namespace, &schema, environment)?;
+    }
+    connect_databases(&mut namespace, &connector_options(&schema, ctx.environment())?, silent).await?;
+    Ok(Some((namespace, schema)))
+}
+
+fn schema_modified_times(ctx: &Ctx) -> BTreeMap<PathBuf, Option<SystemTime>> {
+    let mut result = BTreeMap::new();
+    result.insert(ctx.main_schema_file().clone(), modified_time(ctx.main_schema_file()));
+    for source in ctx.schema().sources.values().filter(|source| !source.builtin) {
+        let path = PathBuf::from(&source.file_path);
+        let modified = modified_time(&path);
+        result.insert(path, modified);
+    }
+    result
+}
+
+fn modified_time(path: &PathBuf) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use teo_parser::traits::named_identifiable::NamedIdentifiable;
+    use crate::test::schema::app_for_schema_source;
+    use super::load_changed_schema;
+
+    const SCHEMA: &str = "connector {\n  provider .sqlite\n  url \"sqlite::memory:\"\n}\n\nserver {\n  bind (\"0.0.0.0\", 4000)\n}\n\nmodel Post {\n  @id @autoIncrement\n  id: Int\n}\n";
+
+    #[actix_web::test]
+    async fn keeps_the_previous_schema_if_the_changed_one_has_errors() {
+        let app = app_for_schema_source(SCHEMA);
+        let ctx = app.ctx();
+        std::fs::write(ctx.main_schema_file(), format!("{}\nmodel Broken {{\n", SCHEMA)).unwrap();
+        assert!(load_changed_schema(ctx, true).await.unwrap().is_none());
+        assert_eq!(ctx.schema().models().into_iter().map(|model| model.name()).collect::<Vec<_>>(), vec!["Post"]);
+    }
+
+    #[actix_web::test]
+    async fn loads_the_changed_schema() {
+        let app = app_for_schema_source(SCHEMA);
+        let ctx = app.ctx();
+        std::fs::write(ctx.main_schema_file(), format!("{}\nmodel Comment {{\n  @id @autoIncrement\n  id: Int\n}}\n", SCHEMA)).unwrap();
+        let (namespace, schema) = load_changed_schema(ctx, true).await.unwrap().unwrap();
+        assert!(namespace.model_at_path(&vec!["Comment"]).is_some());
+        assert_eq!(schema.models().into_iter().map(|model| model.name()).collect::<Vec<_>>(), vec!["Post", "Comment"]);
+    }
+
+    #[actix_web::test]
+    async fn reads_server_options_of_the_changed_schema() {
+        let app = app_for_schema_source(SCHEMA);
+        let ctx = app.ctx();
+        assert!(ctx.compression().is_none());
+        let changed = SCHEMA.replace("  bind (\"0.0.0.0\", 4000)\n", "  bind (\"0.0.0.0\", 4000)\n  compression { \"minSize\": 2048 }\n");
+        std::fs::write(ctx.main_schema_file(), changed).unwrap();
+        let (namespace, schema) = load_changed_schema(ctx, true).await.unwrap().unwrap();
+        let reloaded = ctx.reloaded(namespace, schema).unwrap();
+        assert_eq!(reloaded.compression().unwrap().min_size, 2048);
+        assert!(reloaded.is_running());
+    }
+}
@@ -0,0 +1,212 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use actix_http::body::BoxBody;
+use actix_http::header;
+use actix_http::Method;
+use actix_web::cookie::Cookie;
+use actix_web::HttpResponse;
+use actix_web::test::TestRequest;
+use serde_json::Value as JsonValue;
+use teo_result::{Error, Result};
+use crate::app::ctx::Ctx;
+use crate::server::make::{make_local_service, server_conf, LocalService};
+use crate::test::response::TestResponse;
+
+/// Sends requests to an app in process, without binding a port.
+///
+/// Requests go through the same middlewares and handlers as they do when the
+/// app is served. Cookies set by responses are sent with later requests, so
+/// sessions are kept across requests.
+///
+/// Build it with `App::test_client`.
+pub struct TestClient {
+    ctx: Ctx,
+    service: LocalService,
+    path_prefix: String,
+    headers: RefCell<BTreeMap<String, String>>,
+    cookies: RefCell<BTreeMap<String, String>>,
+}
+
+impl TestClient {
+
+    pub(crate) async fn new(ctx: Ctx) -> Result<Self> {
+        let namespace = ctx.shared_namespace()?;
+        let path_prefix = server_conf(&ctx)?.path_prefix.clone();
+        let service = make_local_service(ctx.clone(), namespace, path_prefix.clone()).await?;
+        let path_prefix = match path_prefix.as_ref().map(|p| p.trim_matches('/')) {
+            Some(path_prefix) if !path_prefix.is_empty() => format!("/{}", path_prefix),
+            _ => "".to_owned(),
+        };
+        Ok(Self {
+            ctx,
+            service,
+            path_prefix,
+            headers: RefCell::new(BTreeMap::new()),
+            cookies: RefCell::new(BTreeMap::new()),
+        })
+    }
+
+    /// The ctx of the app under test. Use it to prepare or inspect data
+    /// without going through handlers.
+    pub fn ctx(&self) -> &Ctx {
+        &self.ctx
+    }
+
+    /// Send `value` as header `name` with every following request.
+    pub fn set_header(&self, name: &str, value: &str) {
+        self.headers.borrow_mut().insert(name.to_owned(), value.to_owned());
+    }
+
+    pub fn remove_header(&self, name: &str) {
+        self.headers.borrow_mut().remove(name);
+    }
+
+    /// Send requests as the identity of `token`.
+    pub fn set_token(&self, token: &str) {
+        self.set_header(header::AUTHORIZATION.as_str(), &format!("Bearer {}", token));
+    }
+
+    /// Sign in with `credentials` through the `signIn` action of `model`, and
+    /// send following requests as the signed in identity.
+    pub async fn sign_in(&self, model: &str, credentials: JsonValue) -> Result<TestResponse> {
+        let response = self.model(model).action("signIn", credentials).await;
+        match response.json().pointer("/meta/token").and_then(JsonValue::as_str) {
+            Some(token) => self.set_token(token),
+            None => Err(Error::new(format!("cannot sign in: {}", response.text())))?,
+        }
+        Ok(response)
+    }
+
+    /// Send following requests without identity.
+    pub fn sign_out(&self) {
+        self.remove_header(header::AUTHORIZATION.as_str());
+    }
+
+    pub fn clear_cookies(&self) {
+        self.cookies.borrow_mut().clear();
+    }
+
+    /// Access the builtin and custom actions of a model, e.g. `"Artist"` or
+    /// `"blog.Post"`.
+    pub fn model<'a>(&'a self, path: &str) -> TestModel<'a> {
+        TestModel { client: self, path: path.replace('.', "/") }
+    }
+
+    pub async fn get(&self, path: &str) -> TestResponse {
+        self.request(Method::GET, path, None).await
+    }
+
+    pub async fn post(&self, path: &str, body: JsonValue) -> TestResponse {
+        self.request(Method::POST, path, Some(body)).await
+    }
+
+    pub async fn request(&self, method: Method, path: &str, body: Option<JsonValue>) -> TestResponse {
+        let uri = format!("{}/{}", self.path_prefix, path.trim_start_matches('/'));
+        let mut request = TestRequest::default().method(method).uri(&uri);
+        for (name, value) in self.headers.borrow().iter() {
+            request = request.insert_header((name.as_str(), value.as_str()));
+        }
+        let cookies = self.cookies.borrow().iter().map(|(name, value)| format!("{}={}", name, value)).collect::<Vec<String>>();
+        if !cookies.is_empty() {
+            request = request.insert_header((header::COOKIE, cookies.join("; ")));
+        }
+        if let Some(body) = body {
+            request = request.set_json(body);
+        }
+        let response = (self.service)(request.to_request()).await;
+        self.store_cookies(&response);
+        let status = response.status();
+        let headers = response.headers().clone();
+        let body = actix_web::body::to_bytes(response.into_body()).await.unwrap_or_default();
+        TestResponse::new(status, headers, body)
+    }
+
+    fn store_cookies(&self, response: &HttpResponse<BoxBody>) {
+        let mut cookies = self.cookies.borrow_mut();
+        for value in response.headers().get_all(header::SET_COOKIE) {
+            let cookie = match value.to_str().ok().and_then(|value| Cookie::parse_encoded(value).ok()) {
+                Some(cookie) => cookie,
+                None => continue,
+            };
+            if cookie.value().is_empty() || cookie.max_age().map_or(false, |max_age| max_age.is_zero()) {
+                cookies.remove(cookie.name());
+            } else {
+                cookies.insert(cookie.name().to_owned(), cookie.value().to_owned());
+            }
+        }
+    }
+}
+
+/// The actions of a model, see `TestClient::model`.
+pub struct TestModel<'a> {
+    client: &'a TestClient,
+    path: String,
+}
+
+impl<'a> TestModel<'a> {
+
+    /// Send `body` to the action `name`. Use this for custom actions.
+    pub async fn action(&self, name: &str, body: JsonValue) -> TestResponse {
+        self.client.post(&format!("{}/{}", self.path, name), body).await
+    }
+
+    pub async fn find_unique(&self, body: JsonValue) -> TestResponse {
+        self.action("findUnique", body).await
+    }
+
+    pub async fn find_first(&self, body: JsonValue) -> TestResponse {
+        self.action("findFirst", body).await
+    }
+
+    pub async fn find_many(&self, body: JsonValue) -> TestResponse {
+        self.action("findMany", body).await
+    }
+
+    pub async fn create(&self, body: JsonValue) -> TestResponse {
+        self.action("create", body).await
+    }
+
+    pub async fn update(&self, body: JsonValue) -> TestResponse {
+        self.action("update", body).await
+    }
+
+    pub async fn upsert(&self, body: JsonValue) -> TestResponse {
+        self.action("upsert", body).await
+    }
+
+    pub async fn copy(&self, body: JsonValue) -> TestResponse {
+        self.action("copy", body).await
+    }
+
+    pub async fn delete(&self, body: JsonValue) -> TestResponse {
+        self.action("delete", body).await
+    }
+
+    pub async fn create_many(&self, body: JsonValue) -> TestResponse {
+        self.action("createMany", body).await
+    }
+
+    pub async fn update_many(&self, body: JsonValue) -> TestResponse {
+        self.action("updateMany", body).await
+    }
+
+    pub async fn copy_many(&self, body: JsonValue) -> TestResponse {
+        self.action("copyMany", body).await
+    }
+
+    pub async fn delete_many(&self, body: JsonValue) -> TestResponse {
+        self.action("deleteMany", body).await
+    }
+
+    pub async fn count(&self, body: JsonValue) -> TestResponse {
+        self.action("count", body).await
+    }
+
+    pub async fn aggregate(&self, body: JsonValue) -> TestResponse {
+        self.action("aggregate", body).await
+    }
+
+    pub async fn group_by(&self, body: JsonValue) -> TestResponse {
+        self.action("groupBy", body).await
+    }
+}
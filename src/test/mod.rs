@@ -0,0 +1,7 @@
+pub mod client;
+pub mod response;
+#[cfg(test)]
+pub(crate) mod schema;
+
+pub use client::{TestClient, TestModel};
+pub use response::TestResponse;
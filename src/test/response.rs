@@ -0,0 +1,51 @@
+use actix_http::header::HeaderMap;
+use actix_http::StatusCode;
+use actix_web::web::Bytes;
+use serde_json::Value as JsonValue;
+
+/// A response received by a `TestClient`.
+#[derive(Debug, Clone)]
+pub struct TestResponse {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+}
+
+impl TestResponse {
+
+    pub(crate) fn new(status: StatusCode, headers: HeaderMap, body: Bytes) -> Self {
+        Self { status, headers, body }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).and_then(|v| v.to_str().ok())
+    }
+
+    pub fn body(&self) -> &Bytes {
+        &self.body
+    }
+
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).into_owned()
+    }
+
+    /// The body parsed as JSON.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the body is not valid JSON.
+    pub fn json(&self) -> JsonValue {
+        match serde_json::from_slice(&self.body) {
+            Ok(json) => json,
+            Err(err) => panic!("response body is not JSON: {}: {}", err, self.text()),
+        }
+    }
+}
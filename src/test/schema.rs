@@ -0,0 +1,36 @@
+use std::fs;
+use std::path::PathBuf;
+use teo_parser::ast::schema::Schema;
+use uuid::Uuid;
+use crate::app::App;
+use crate::app::app::parse_schema;
+
+/// Write `content` as the main schema file of a temporary directory.
+fn write_schema_source(content: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("teo-schema-{}", Uuid::new_v4()));
+    fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("schema.teo");
+    fs::write(&file, content).unwrap();
+    file
+}
+
+/// Parse `content` as the main schema file of a temporary directory.
+/// Panics if the schema has errors.
+pub(crate) fn parse_schema_source(content: &str) -> Schema {
+    let file = write_schema_source(content);
+    let (schema, diagnostics) = parse_schema(&file).unwrap();
+    assert!(!diagnostics.has_errors(), "the schema has errors:\n{}", content);
+    schema
+}
+
+/// An app serving `content` as its schema, for `App::test_client`.
+pub(crate) fn app_for_schema_source(content: &str) -> App {
+    let file = write_schema_source(content);
+    let argv = vec![
+        "teo".to_owned(),
+        "-s".to_owned(),
+        file.to_str().unwrap().to_owned(),
+        "serve".to_owned(),
+    ];
+    App::new_with_entrance_and_runtime_version(None, None, Some(argv)).unwrap()
+}
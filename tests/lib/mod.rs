@@ -9,6 +9,7 @@ use std::path::{Path, PathBuf};
 use key_path::{KeyPath, path};
 use serde_json::{Map, Number, Value};
 use crate::lib::matcher::Matcher;
+use teo::app::App;
 use whoami::Platform;
 
 fn schema_from_file(file: &str) -> PathBuf {
@@ -37,6 +38,17 @@ fn teo_exe_path() -> String {
     teo_exe_path_buf().to_str().unwrap().to_string()
 }
 
+/// An app loading the schema next to `file`, for `App::test_client`.
+pub fn test_app(file: &str) -> App {
+    let argv = vec![
+        "teo".to_owned(),
+        "-s".to_owned(),
+        schema_from_file(file).to_str().unwrap().to_owned(),
+        "serve".to_owned(),
+    ];
+    App::new_with_entrance_and_runtime_version(None, None, Some(argv)).unwrap()
+}
+
 pub struct ExecutionHandle {
     child: Option<Child>
 }
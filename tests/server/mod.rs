@@ -1 +1,3 @@
 pub mod actions;
+pub mod test_client;
+pub mod transactional;
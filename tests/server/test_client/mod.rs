@@ -0,0 +1,81 @@
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+    use teo::prelude::{App, Error, MemorySessionStore, request, Response, Session, SessionConfig, teon};
+    use crate::lib::test_app;
+
+    /// Define `echoHeader`, which returns the `x-echo` header, and `visit`,
+    /// which counts the visits of a session.
+    fn define_handlers(app: &mut App) {
+        app.main_namespace_mut().unwrap().define_handler("echoHeader", |ctx: request::Ctx| async move {
+            let value = ctx.request().headers().get("x-echo").map(|v| v.to_string()).unwrap_or_default();
+            Ok::<Response, Error>(Response::teon(teon!({ "data": value })))
+        });
+        app.main_namespace_mut().unwrap().define_handler("visit", |session: Session| async move {
+            let visits = session.get::<i64>("visits")?.unwrap_or(0) + 1;
+            session.insert("visits", visits)?;
+            Ok::<Response, Error>(Response::teon(teon!({ "data": visits })))
+        });
+        app.session(SessionConfig::new("secret", MemorySessionStore::new())).unwrap();
+    }
+
+    #[actix_web::test]
+    async fn builtin_actions() {
+        let mut app = test_app(file!());
+        let client = app.test_client_with_memory_database().await.unwrap();
+        let support = client.model("Support");
+        let res = support.create(json!({ "create": { "string": "a", "int": 1 } })).await;
+        assert!(res.status().is_success());
+        assert_eq!(res.json().pointer("/data/string"), Some(&json!("a")));
+        support.create(json!({ "create": { "string": "b", "int": 2 } })).await;
+        let res = support.find_many(json!({ "orderBy": { "int": "asc" } })).await.json();
+        assert_eq!(res.pointer("/meta/count"), Some(&json!(2)));
+        assert_eq!(res.pointer("/data/1/string"), Some(&json!("b")));
+        let res = support.update(json!({ "where": { "int": 2 }, "update": { "string": "c" } })).await.json();
+        assert_eq!(res.pointer("/data/string"), Some(&json!("c")));
+        assert_eq!(support.count(json!({ "where": { "string": "c" } })).await.json(), json!({ "data": 1 }));
+        support.delete_many(json!({})).await;
+        assert_eq!(support.count(json!({})).await.json(), json!({ "data": 0 }));
+    }
+
+    #[actix_web::test]
+    async fn memory_databases_start_empty() {
+        let mut app = test_app(file!());
+        let client = app.test_client_with_memory_database().await.unwrap();
+        client.model("Support").create(json!({ "create": {} })).await;
+        let mut app = test_app(file!());
+        let client = app.test_client_with_memory_database().await.unwrap();
+        assert_eq!(client.model("Support").count(json!({})).await.json(), json!({ "data": 0 }));
+    }
+
+    #[actix_web::test]
+    async fn sends_headers() {
+        let mut app = test_app(file!());
+        define_handlers(&mut app);
+        let client = app.test_client_with_memory_database().await.unwrap();
+        client.set_header("x-echo", "hello");
+        assert_eq!(client.post("/echoHeader", json!({})).await.json(), json!({ "data": "hello" }));
+        client.remove_header("x-echo");
+        assert_eq!(client.post("/echoHeader", json!({})).await.json(), json!({ "data": "" }));
+    }
+
+    #[actix_web::test]
+    async fn keeps_cookies() {
+        let mut app = test_app(file!());
+        define_handlers(&mut app);
+        let client = app.test_client_with_memory_database().await.unwrap();
+        let res = client.post("/visit", json!({})).await;
+        assert!(res.header("set-cookie").is_some());
+        assert_eq!(res.json(), json!({ "data": 1 }));
+        assert_eq!(client.post("/visit", json!({})).await.json(), json!({ "data": 2 }));
+        client.clear_cookies();
+        assert_eq!(client.post("/visit", json!({})).await.json(), json!({ "data": 1 }));
+    }
+
+    #[actix_web::test]
+    async fn sign_in_fails_without_a_token() {
+        let mut app = test_app(file!());
+        let client = app.test_client_with_memory_database().await.unwrap();
+        assert!(client.sign_in("Support", json!({ "credentials": {} })).await.is_err());
+    }
+}
@@ -0,0 +1,25 @@
+pub mod sqlite;
+pub mod mongodb;
+
+use teo::prelude::{App, Error, request, Response, teon};
+
+/// Define `createThenFail`, which saves a record and then fails, and
+/// `createThenSucceed`, and run both inside a transaction.
+pub fn define_handlers(app: &mut App) {
+    app.main_namespace_mut().unwrap().define_handler("createThenFail", |ctx: request::Ctx| async move {
+        let model = ctx.transaction_ctx().namespace().model_at_path(&vec!["Support"]).unwrap();
+        let object = ctx.transaction_ctx().create_object(model, &teon!({}), None).await?;
+        object.set_teon(&teon!({ "string": "written" })).await?;
+        object.save().await?;
+        Err::<Response, Error>(Error::new("failed after writing"))
+    });
+    app.main_namespace_mut().unwrap().define_handler("createThenSucceed", |ctx: request::Ctx| async move {
+        let model = ctx.transaction_ctx().namespace().model_at_path(&vec!["Support"]).unwrap();
+        let object = ctx.transaction_ctx().create_object(model, &teon!({}), None).await?;
+        object.set_teon(&teon!({ "string": "written" })).await?;
+        object.save().await?;
+        Ok::<Response, Error>(Response::teon(teon!({ "data": "ok" })))
+    });
+    app.transactional_handler("createThenFail").unwrap();
+    app.transactional_handler("createThenSucceed").unwrap();
+}
@@ -0,0 +1,33 @@
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+    use serial_test::serial;
+    use crate::lib::test_app;
+    use crate::server::transactional::define_handlers;
+
+    #[actix_web::test]
+    #[serial]
+    async fn failed_handler_rolls_back() {
+        let mut app = test_app(file!());
+        define_handlers(&mut app);
+        let client = app.test_client().await.unwrap();
+        client.model("Support").delete_many(json!({})).await;
+        let res = client.post("/createThenFail", json!({})).await;
+        assert!(!res.status().is_success());
+        let res = client.model("Support").count(json!({})).await.json();
+        assert_eq!(res, json!({ "data": 0 }));
+    }
+
+    #[actix_web::test]
+    #[serial]
+    async fn succeeded_handler_commits() {
+        let mut app = test_app(file!());
+        define_handlers(&mut app);
+        let client = app.test_client().await.unwrap();
+        client.model("Support").delete_many(json!({})).await;
+        let res = client.post("/createThenSucceed", json!({})).await;
+        assert!(res.status().is_success());
+        let res = client.model("Support").count(json!({})).await.json();
+        assert_eq!(res, json!({ "data": 1 }));
+    }
+}